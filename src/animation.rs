@@ -0,0 +1,269 @@
+//! Animations made out of several equations, shown one after another, eg to step through a
+//! derivation
+
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use iced::futures::future;
+use image::{Delay, Frame, RgbaImage};
+use image::codecs::gif::{GifEncoder, Repeat};
+use tempdir::TempDir;
+use tokio::fs;
+
+use crate::{GuiError, latex, typst};
+use crate::backends::{self, Backend, ExtraArgs, Layout};
+use crate::gui::Dir;
+use crate::utils::svg_attribute;
+
+#[derive(Default, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum AnimationFormat {
+    #[default]
+    Gif,
+    /// animated with SMIL, so it plays in browsers without any scripts
+    Svg,
+}
+
+impl AnimationFormat {
+    pub const ALL: [Self; 2] = [
+        Self::Gif,
+        Self::Svg,
+    ];
+
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            Self::Svg => "svg",
+        }
+    }
+}
+
+impl Display for AnimationFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Gif => "animated gif",
+            Self::Svg => "animated svg",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    /// one equation per frame
+    pub frames: Vec<String>,
+    /// how long each frame is shown for
+    pub frame_duration: Duration,
+    pub format: AnimationFormat,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            frames: vec![String::new(), String::new()],
+            frame_duration: Duration::from_secs(1),
+            format: AnimationFormat::default(),
+        }
+    }
+}
+
+/// Everything needed to render an [`Animation`] in the background
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub animation: Animation,
+    pub backend: Backend,
+    pub color: String,
+    pub dpi: usize,
+    pub layout: Layout,
+    pub extra_args: ExtraArgs,
+    /// LaTeX's preamble or Typst's imports, see [`latex::gen_svg`] and [`typst::gen_svg`]
+    pub preamble: String,
+    /// where LaTeX compiles, see [`latex::gen_svg`]
+    pub scratch: PathBuf,
+    /// where the animation is written
+    pub path: PathBuf,
+}
+
+/// Renders each frame of the animation and combines them, returning the size of the written file
+pub async fn export(job: Job) -> Result<u64, GuiError> {
+    let temp = TempDir::new("animation_")
+        .map_err(|_| GuiError::TempDir)?;
+
+    // each frame has its own directory, so they can all render at once, as many at a time as
+    //  `backends::worker` allows
+    let frames = future::try_join_all(job.animation.frames.iter()
+        .enumerate()
+        .map(|(i, eq)| job.render_frame(eq.clone(), temp.path().join(format!("frame{i}")))))
+        .await?;
+
+    let Job { animation, path, .. } = job;
+    let path_err = path.to_string_lossy().to_string();
+    match animation.format {
+        AnimationFormat::Gif => {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || write_gif(&frames, animation.frame_duration, &path))
+                .await
+                .expect("writing the gif doesn't panic")
+                .map_err(|_| GuiError::WriteFile(path_err.clone().into()))?;
+        }
+        AnimationFormat::Svg => {
+            let mut svgs = Vec::with_capacity(frames.len());
+            for frame in &frames {
+                svgs.push(fs::read_to_string(frame)
+                    .await
+                    .map_err(|_| GuiError::ReadFile(frame.to_string_lossy().to_string()))?);
+            }
+            fs::write(&path, animated_svg(&svgs, animation.frame_duration))
+                .await
+                .map_err(|_| GuiError::WriteFile(path_err.clone().into()))?;
+        }
+    }
+
+    fs::metadata(&path)
+        .await
+        .map(|metadata| metadata.len())
+        .map_err(|_| GuiError::ReadFile(path_err))
+}
+
+impl Job {
+    /// Renders `eq` into the new directory `dir`, returning the path of the image
+    async fn render_frame(&self, eq: String, dir: Dir) -> Result<PathBuf, GuiError> {
+        let _worker = backends::worker().await;
+        let Self { animation, backend, color, dpi, layout, extra_args, preamble, scratch, .. } = self;
+        match (backend, animation.format) {
+            (Backend::LaTeX, _) => {
+                // the png is converted from the svg, and the frames are nested in one svg, so the text
+                //  has to be paths
+                latex::gen_svg(eq.clone(), preamble.clone(), dir.clone(), color.clone(), *layout, extra_args.clone(), scratch.clone(), latex::SvgFonts::Paths).await?;
+            }
+            (Backend::Typst, AnimationFormat::Svg) => {
+                fs::create_dir(&dir)
+                    .await
+                    .map_err(|_| GuiError::TempDir)?;
+                typst::gen_svg(eq.clone(), preamble.clone(), dir.clone(), color.clone(), *layout, extra_args.clone()).await?;
+            }
+            (Backend::Typst, AnimationFormat::Gif) => {
+                fs::create_dir(&dir)
+                    .await
+                    .map_err(|_| GuiError::TempDir)?;
+            }
+        }
+        let extension = match animation.format {
+            AnimationFormat::Gif => {
+                backend.gen_png(eq, preamble.clone(), dir.clone(), color.clone(), *dpi, *layout, extra_args.clone()).await?;
+                "png"
+            }
+            AnimationFormat::Svg => "svg",
+        };
+        Ok(dir.join(format!("{color}_eq.{extension}")))
+    }
+}
+
+/// Centers each png on a canvas big enough for all of them, and loops through them forever
+fn write_gif(frames: &[PathBuf], frame_duration: Duration, path: &PathBuf) -> image::ImageResult<()> {
+    let images = frames.iter()
+        .map(|frame| image::open(frame).map(image::DynamicImage::into_rgba8))
+        .collect::<Result<Vec<_>, _>>()?;
+    let width = images.iter().map(RgbaImage::width).max().unwrap_or_default();
+    let height = images.iter().map(RgbaImage::height).max().unwrap_or_default();
+    let delay = Delay::from_saturating_duration(frame_duration);
+
+    let mut encoder = GifEncoder::new(BufWriter::new(File::create(path)?));
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder.encode_frames(images.into_iter().map(|image| {
+        let mut canvas = RgbaImage::new(width, height);
+        image::imageops::overlay(
+            &mut canvas,
+            &image,
+            i64::from((width - image.width()) / 2),
+            i64::from((height - image.height()) / 2),
+        );
+        Frame::from_parts(canvas, 0, 0, delay)
+    }))
+}
+
+/// Nests each svg in one big svg, only showing one of them at a time
+#[allow(clippy::format_collect)]
+fn animated_svg(svgs: &[String], frame_duration: Duration) -> String {
+    let frames = svgs.iter()
+        .enumerate()
+        .map(|(i, svg)| SvgFrame::parse(svg, &format!("f{i}-")))
+        .collect::<Vec<_>>();
+    let width = frames.iter().map(|f| f.width).fold(0.0, f64::max);
+    let height = frames.iter().map(|f| f.height).fold(0.0, f64::max);
+    let n = frames.len();
+    let total = frame_duration.as_secs_f64() * n as f64;
+
+    let body = frames.iter().enumerate().map(|(i, frame)| {
+        // hidden except for its slice of the loop
+        let mut values = vec![];
+        let mut key_times = vec![];
+        if i != 0 {
+            values.push("hidden");
+            key_times.push(0.0);
+        }
+        values.push("visible");
+        key_times.push(i as f64 / n as f64);
+        if i != n - 1 {
+            values.push("hidden");
+            key_times.push((i + 1) as f64 / n as f64);
+        }
+        let key_times = key_times.iter()
+            .map(f64::to_string)
+            .collect::<Vec<_>>()
+            .join(";");
+        format!(
+            r#"<g visibility="{initial}"><animate attributeName="visibility" values="{values}" keyTimes="{key_times}" calcMode="discrete" dur="{total}s" repeatCount="indefinite"/><svg x="{x}" y="{y}" width="{w}" height="{h}" viewBox="{view_box}">{content}</svg></g>"#,
+            initial = values[0],
+            values = values.join(";"),
+            x = (width - frame.width) / 2.0,
+            y = (height - frame.height) / 2.0,
+            w = frame.width,
+            h = frame.height,
+            view_box = frame.view_box,
+            content = frame.content,
+        )
+    }).collect::<String>();
+
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{width}pt" height="{height}pt" viewBox="0 0 {width} {height}">{body}</svg>"#,
+    )
+}
+
+/// The parts of a single frame's svg needed to nest it in the animation
+struct SvgFrame {
+    /// in pt
+    width: f64,
+    height: f64,
+    view_box: String,
+    /// everything inside the root `<svg>` element, with ids made unique to this frame
+    content: String,
+}
+
+impl SvgFrame {
+    fn parse(svg: &str, id_prefix: &str) -> Self {
+        let start = svg.find("<svg").unwrap_or_default();
+        let tag_end = svg[start..].find('>').map_or(svg.len(), |end| start + end + 1);
+        let end = svg.rfind("</svg>").unwrap_or(svg.len()).max(tag_end);
+        let root = &svg[start..tag_end];
+
+        let length = |name| svg_attribute(root, name)
+            .and_then(|value| value.trim_end_matches("pt").parse().ok())
+            .unwrap_or_default();
+        let width = length("width");
+        let height = length("height");
+        let view_box = svg_attribute(root, "viewBox")
+            .map_or_else(|| format!("0 0 {width} {height}"), str::to_string);
+
+        // glyphs from different frames are defined with the same ids
+        let content = svg[tag_end..end]
+            .replace("id=\"", &format!("id=\"{id_prefix}"))
+            .replace("id='", &format!("id='{id_prefix}"))
+            .replace("href=\"#", &format!("href=\"#{id_prefix}"))
+            .replace("href='#", &format!("href='#{id_prefix}"))
+            .replace("url(#", &format!("url(#{id_prefix}"));
+
+        Self { width, height, view_box, content }
+    }
+}
@@ -1,21 +1,49 @@
-use std::ffi::OsStr;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::future::{Future, poll_fn};
+use std::hash::{Hash, Hasher};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::process::{ExitStatus, Output};
+use std::task::Poll;
 
+use clap::ValueEnum;
+use color_quant::NeuQuant;
+use image::RgbaImage;
 use itertools::Itertools;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::process::Command;
+use tokio::sync::{Semaphore, SemaphorePermit, watch};
 
-use crate::{GuiError, latex, typst};
-use crate::gui::Dir;
+use crate::{clipboard, color, GuiError, latex, typst};
+use crate::color::ParsedColor;
+use crate::gui::{Dir, ImageFormat};
+use crate::utils::{remove_svg_attribute, set_svg_attribute, svg_attribute};
 
-#[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
 pub enum Backend {
+    #[value(name = "latex")]
     LaTeX,
     #[default]
     Typst,
 }
 
 impl Backend {
+    pub const ALL: [Self; 2] = [
+        Self::LaTeX,
+        Self::Typst,
+    ];
+
     pub const fn flip(self) -> Self {
         match self {
             Self::LaTeX => Self::Typst,
@@ -30,10 +58,11 @@ impl Backend {
         }
     }
 
-    pub const fn name(self) -> &'static str {
+    /// A small equation to show what this backend's syntax looks like
+    pub const fn example(self) -> &'static str {
         match self {
-            Self::LaTeX => "latex",
-            Self::Typst => "typst",
+            Self::LaTeX => r"e^{i\pi} + 1 = 0",
+            Self::Typst => "e^(i pi) + 1 = 0",
         }
     }
 
@@ -44,67 +73,1447 @@ impl Backend {
         }
     }
 
-    pub async fn gen_png(self, eq: String, dir: Dir, color: String, dpi: usize) -> Result<(), GuiError> {
+    /// The backend for a `.tex` or `.typ` source file
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "tex" => Some(Self::LaTeX),
+            "typ" => Some(Self::Typst),
+            _ => None,
+        }
+    }
+
+    /// The equation in a source file, so that whole documents can be loaded into the editor
+    pub fn extract_equation(self, source: &str) -> &str {
+        match self {
+            Self::LaTeX => latex::extract_equation(source),
+            Self::Typst => typst::extract_equation(source),
+        }
+    }
+
+    /// `preamble`, Typst's imports, and `layout` are ignored for LaTeX, since its png is converted
+    /// from the already laid out svg
+    #[allow(clippy::too_many_arguments)]
+    pub async fn gen_png(self, eq: String, preamble: String, dir: Dir, color: String, dpi: usize, layout: Layout, extra_args: ExtraArgs) -> Result<(), GuiError> {
+        match self {
+            Self::LaTeX => latex::gen_png(dir, color, dpi, extra_args).await,
+            Self::Typst => typst::gen_png(eq, preamble, dir, color, dpi, layout, extra_args).await,
+        }
+    }
+
+    /// `preamble` is LaTeX's preamble or Typst's imports. `scratch`, where LaTeX compiles, is only
+    /// used by LaTeX, see [`latex::gen_pdf`]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn gen_pdf(self, eq: String, preamble: String, dir: Dir, color: String, layout: Layout, extra_args: ExtraArgs, scratch: PathBuf) -> Result<(), GuiError> {
+        match self {
+            Self::LaTeX => latex::gen_pdf(eq, preamble, dir, color, layout, extra_args, scratch).await,
+            Self::Typst => typst::gen_pdf(eq, preamble, dir, color, layout, extra_args).await,
+        }
+    }
+}
+
+/// The external programs used to render equations
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Tool {
+    Latex,
+    Dvisvgm,
+    Magick,
+    Typst,
+}
+
+impl Tool {
+    pub const ALL: [Self; 4] = [
+        Self::Latex,
+        Self::Dvisvgm,
+        Self::Magick,
+        Self::Typst,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Latex => "latex",
+            Self::Dvisvgm => "dvisvgm",
+            Self::Magick => "magick",
+            Self::Typst => "typst",
+        }
+    }
+
+    /// Whether this tool is run when rendering with `backend` to `format`
+    pub fn is_used(self, backend: Backend, format: ImageFormat) -> bool {
+        match self {
+            Self::Latex | Self::Dvisvgm => backend == Backend::LaTeX,
+            Self::Magick => backend == Backend::LaTeX && format == ImageFormat::Png,
+            Self::Typst => backend == Backend::Typst,
+        }
+    }
+
+    /// Whether the program can be found, see [`find_on_path`]
+    pub fn is_installed(self) -> bool {
+        match self {
+            Self::Latex | Self::Dvisvgm => find_on_path(self.name()).is_some(),
+            Self::Magick => magick().is_ok(),
+            Self::Typst => typst::is_installed(),
+        }
+    }
+
+    /// Where to get the program, for when it isn't installed
+    pub const fn install_hint(self) -> &'static str {
+        match self {
+            Self::Latex | Self::Dvisvgm => "comes with TeX Live or MiKTeX",
+            Self::Magick => "install ImageMagick",
+            Self::Typst => "install Typst, or set TYPST_IMAGE_TYPST_BIN to it",
+        }
+    }
+
+    /// The tools that rendering with `backend` to `format` needs but can't find
+    pub fn missing(backend: Backend, format: ImageFormat) -> Vec<Self> {
+        Self::ALL.into_iter()
+            .filter(|tool| tool.is_used(backend, format) && !tool.is_installed())
+            .collect()
+    }
+
+    /// Arguments that would let an equation do more than render itself
+    const fn disallowed_args(self) -> &'static [&'static str] {
         match self {
-            Self::LaTeX => latex::gen_png(dir, color, dpi).await,
-            Self::Typst => typst::gen_png(eq, dir, color, dpi).await,
+            Self::Latex => &["-shell-escape", "--shell-escape", "-enable-write18", "--enable-write18", "-shell-restricted"],
+            Self::Dvisvgm => &[],
+            Self::Magick => &["-write", "+write"],
+            Self::Typst => &["--root"],
+        }
+    }
+}
+
+/// User provided arguments passed to each external tool, split on whitespace
+#[derive(Default, Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ExtraArgs {
+    pub latex: String,
+    pub dvisvgm: String,
+    pub magick: String,
+    pub typst: String,
+}
+
+impl ExtraArgs {
+    pub fn get(&self, tool: Tool) -> &str {
+        match tool {
+            Tool::Latex => &self.latex,
+            Tool::Dvisvgm => &self.dvisvgm,
+            Tool::Magick => &self.magick,
+            Tool::Typst => &self.typst,
+        }
+    }
+
+    pub const fn get_mut(&mut self, tool: Tool) -> &mut String {
+        match tool {
+            Tool::Latex => &mut self.latex,
+            Tool::Dvisvgm => &mut self.dvisvgm,
+            Tool::Magick => &mut self.magick,
+            Tool::Typst => &mut self.typst,
         }
     }
+
+    pub fn args(&self, tool: Tool) -> impl Iterator<Item=&str> {
+        self.get(tool).split_whitespace()
+    }
+
+    /// Makes sure none of the arguments are in [`Tool::disallowed_args`], and that there aren't any
+    /// in safe mode
+    pub fn validate(&self) -> Result<(), GuiError> {
+        if safe_mode() && Tool::ALL.into_iter().any(|tool| self.args(tool).next().is_some()) {
+            return Err(GuiError::Unsafe("extra arguments"));
+        }
+        Tool::ALL.into_iter()
+            .flat_map(|tool| self.args(tool)
+                .filter(move |arg| {
+                    let name = arg.split('=').next().unwrap_or(arg);
+                    tool.disallowed_args().contains(&name)
+                })
+                .map(move |arg| GuiError::DisallowedArg(tool.name(), arg.to_string())))
+            .next()
+            .map_or(Ok(()), Err)
+    }
+
+    /// Makes sure the arguments don't change what one tool outputs out from under the next one
+    pub fn check_compatible(&self, backend: Backend) -> Result<(), GuiError> {
+        let has = |tool, names: &[&str]| self.args(tool)
+            .any(|arg| names.contains(&arg.split('=').next().unwrap_or(arg)));
+        let reason = match backend {
+            Backend::LaTeX => {
+                let pdf_output = self.args(Tool::Latex)
+                    .tuple_windows()
+                    .any(|(arg, next)| arg == "-output-format" && next == "pdf")
+                    || self.args(Tool::Latex).any(|arg| arg == "-output-format=pdf" || arg == "-pdf");
+                if pdf_output {
+                    Some("latex would write a pdf, but dvisvgm is run on the dvi")
+                } else if has(Tool::Dvisvgm, &["--pdf", "-P", "--eps", "-E"]) {
+                    Some("dvisvgm would expect a pdf or eps, but latex writes a dvi")
+                } else if has(Tool::Dvisvgm, &["--stdout", "-s", "--output", "-o"]) {
+                    Some("dvisvgm's output file is needed to show and export the svg")
+                } else {
+                    None
+                }
+            }
+            Backend::Typst => has(Tool::Typst, &["--format", "-f"])
+                .then_some("typst's output format is chosen by the Format option"),
+        };
+        reason.map_or(Ok(()), |reason| Err(GuiError::Incompatible(reason)))
+    }
+}
+
+/// Color that lets svgs be recolored with css
+pub const CURRENT_COLOR: &str = "currentColor";
+
+/// How the lines of an equation are lined up
+#[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum LineAlignment {
+    /// lined up at each `&`, which is why `=` usually ends up left aligned
+    #[default]
+    Aligned,
+    /// each line centered on its own
+    Gathered,
+    /// a single centered equation
+    Centered,
+}
+
+impl LineAlignment {
+    pub const ALL: [Self; 3] = [
+        Self::Aligned,
+        Self::Gathered,
+        Self::Centered,
+    ];
+
+    /// The LaTeX environment the equation is wrapped in
+    pub const fn latex_environment(self) -> &'static str {
+        match self {
+            Self::Aligned => "align*",
+            Self::Gathered => "gather*",
+            Self::Centered => "equation*",
+        }
+    }
+
+    /// The environment for laying out the lines inside of math mode, eg in a `TikZ` node
+    pub const fn latex_inner_environment(self) -> Option<&'static str> {
+        match self {
+            Self::Aligned => Some("aligned"),
+            Self::Gathered => Some("gathered"),
+            Self::Centered => None,
+        }
+    }
+
+    /// The horizontal alignment of Typst's `math.equation` blocks
+    pub const fn typst_align(self) -> &'static str {
+        match self {
+            Self::Aligned => "start",
+            Self::Gathered | Self::Centered => "center",
+        }
+    }
+}
+
+impl Display for LineAlignment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Aligned => "aligned",
+            Self::Gathered => "centered lines",
+            Self::Centered => "single equation",
+        })
+    }
+}
+
+/// How an equation is laid out before it is rendered
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Layout {
+    pub alignment: LineAlignment,
+    /// how much bigger the svg is drawn, with dvisvgm's `--scale` or Typst's `scale()`
+    pub scale: f64,
+    /// Typst's math font, LaTeX always uses Computer Modern
+    pub font: typst::MathFont,
+    /// Typst's text size in points, LaTeX's is always 12pt
+    pub font_size: f64,
+    /// dvisvgm's `--exact`, for a bounding box from the glyphs' outlines instead of the fonts'
+    /// metrics. Typst's is always exact
+    pub exact_bbox: bool,
+}
+
+/// How many colors a png can use, fewer colors make for smaller files
+#[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum PngDepth {
+    /// 8 bits per channel, with transparency
+    #[default]
+    TrueColor,
+    /// up to 256 colors
+    Palette,
+    /// fully transparent or the equation's color, nothing in between
+    Bilevel,
+}
+
+impl PngDepth {
+    pub const ALL: [Self; 3] = [
+        Self::TrueColor,
+        Self::Palette,
+        Self::Bilevel,
+    ];
+}
+
+impl Display for PngDepth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::TrueColor => "full color",
+            Self::Palette => "256 colors",
+            Self::Bilevel => "1-bit",
+        })
+    }
+}
+
+/// The color space a png is tagged with, for color managed (eg print) workflows
+#[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum ColorProfile {
+    #[default]
+    Untagged,
+    /// marked as sRGB with an `sRGB` chunk
+    Srgb,
+    /// a user provided ICC profile, embedded in an `iCCP` chunk
+    Icc,
+}
+
+impl ColorProfile {
+    pub const ALL: [Self; 3] = [
+        Self::Untagged,
+        Self::Srgb,
+        Self::Icc,
+    ];
+}
+
+impl Display for ColorProfile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Untagged => "untagged",
+            Self::Srgb => "sRGB",
+            Self::Icc => "ICC profile",
+        })
+    }
+}
+
+/// A fixed size transparent canvas that a png is placed on at an offset, eg for a sprite sheet
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    /// of the image's top left corner, in pixels. Parts of the image off the canvas are cut off
+    pub x: i64,
+    pub y: i64,
+}
+
+impl Canvas {
+    /// Parses a size like `800x600` and an offset like `10,20`, which defaults to the top left
+    pub fn parse(size: &str, offset: &str) -> Option<Self> {
+        let (width, height) = size.split_once(['x', 'X'])?;
+        let (width, height) = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let (x, y) = if offset.trim().is_empty() {
+            (0, 0)
+        } else {
+            let (x, y) = offset.split_once(',')?;
+            (x.trim().parse().ok()?, y.trim().parse().ok()?)
+        };
+        Some(Self { width, height, x, y })
+    }
+}
+
+/// A blurred copy of the equation drawn underneath it, so light text stays legible on busy
+/// backgrounds. The image grows to fit it.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Shadow {
+    /// offset of the shadow, in pixels for pngs and in the svg's own units (pt) for svgs
+    pub x: i32,
+    pub y: i32,
+    /// standard deviation of the blur, in the same units as the offset
+    pub blur: u32,
+    /// any css color. Its transparency is kept
+    pub color: String,
+}
+
+impl Shadow {
+    /// Parses an offset like `2,2`, a blur like `3`, and a color, which defaults to black
+    pub fn parse(offset: &str, blur: &str, color: &str) -> Option<Self> {
+        let (x, y) = offset.split_once(',')?;
+        let (x, y) = (x.trim().parse().ok()?, y.trim().parse().ok()?);
+        let blur = if blur.trim().is_empty() { 0 } else { blur.trim().parse().ok()? };
+        let color = if color.trim().is_empty() { "black" } else { color.trim() };
+        Some(Self { x, y, blur, color: color.to_string() })
+    }
+
+    /// How much to grow each side of the image so that the shadow isn't cut off
+    fn margin(&self) -> u32 {
+        // a gaussian is almost nothing past 3 standard deviations
+        3 * self.blur + self.x.unsigned_abs().max(self.y.unsigned_abs())
+    }
+}
+
+/// How big an svg says it is
+#[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum SvgSize {
+    /// the size the backend wrote
+    #[default]
+    Fixed,
+    /// no width or height, so that it scales to fit wherever it's embedded. Some renderers stretch
+    /// it to fill that space unless told not to, so `preserveAspectRatio` is set too
+    Responsive,
+    /// no width or height, leaving the aspect ratio up to the renderer
+    ResponsiveStretchable,
+}
+
+impl SvgSize {
+    pub const ALL: [Self; 3] = [
+        Self::Fixed,
+        Self::Responsive,
+        Self::ResponsiveStretchable,
+    ];
+}
+
+impl Display for SvgSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Fixed => "fixed size",
+            Self::Responsive => "responsive",
+            Self::ResponsiveStretchable => "responsive, any aspect",
+        })
+    }
+}
+
+/// Adjustments made to an image after it has been compiled and colored
+#[derive(Default, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct PostProcess {
+    /// negate the colors, keeping the transparency
+    pub invert: bool,
+    /// only used for pngs
+    pub depth: PngDepth,
+    /// only used for pngs
+    pub profile: ColorProfile,
+    /// the profile embedded for [`ColorProfile::Icc`]
+    pub icc_path: PathBuf,
+    /// only used for pngs: crop away the transparent border, leaving this many pixels of it so
+    /// anti-aliased edges aren't cut off
+    pub crop: Option<u32>,
+    /// pad the image with transparency to this width:height ratio
+    pub aspect: Option<(u32, u32)>,
+    /// only used for pngs
+    pub canvas: Option<Canvas>,
+    pub shadow: Option<Shadow>,
+    /// fill the image's background with this css color instead of leaving it transparent
+    pub background: Option<String>,
+    /// only used for svgs
+    pub size: SvgSize,
+    /// remove the `<?xml ...?>` declaration and any `<!DOCTYPE>` from svgs, for parsers that
+    /// don't handle them
+    pub strip_prolog: bool,
+}
+
+impl PostProcess {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The adjustments that actually apply to `format`
+    pub fn for_format(&self, format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Svg => Self {
+                invert: self.invert,
+                aspect: self.aspect,
+                shadow: self.shadow.clone(),
+                background: self.background.clone(),
+                size: self.size,
+                strip_prolog: self.strip_prolog,
+                ..Self::default()
+            },
+            ImageFormat::Png => Self {
+                icc_path: if self.profile == ColorProfile::Icc { self.icc_path.clone() } else { PathBuf::new() },
+                size: SvgSize::Fixed,
+                strip_prolog: false,
+                ..self.clone()
+            },
+            // exported as compiled
+            ImageFormat::Pdf => Self::default(),
+        }
+    }
+
+    /// Added after the color in file names, so that each variant of an image is cached separately
+    pub fn tag(&self) -> String {
+        let mut tag = String::new();
+        if self.invert {
+            tag.push_str("_inverted");
+        }
+        match self.depth {
+            PngDepth::TrueColor => {}
+            PngDepth::Palette => tag.push_str("_palette"),
+            PngDepth::Bilevel => tag.push_str("_1bit"),
+        }
+        if let Some(shadow) = &self.shadow {
+            let mut hasher = DefaultHasher::new();
+            shadow.hash(&mut hasher);
+            let shadow = format!("_shadow{:x}", hasher.finish());
+            tag.push_str(&shadow);
+        }
+        if let Some(padding) = self.crop {
+            let crop = format!("_crop{padding}");
+            tag.push_str(&crop);
+        }
+        if let Some((width, height)) = self.aspect {
+            let aspect = format!("_aspect{width}x{height}");
+            tag.push_str(&aspect);
+        }
+        if let Some(Canvas { width, height, x, y }) = self.canvas {
+            let canvas = format!("_canvas{width}x{height}at{x}_{y}");
+            tag.push_str(&canvas);
+        }
+        if let Some(background) = &self.background {
+            let mut hasher = DefaultHasher::new();
+            background.hash(&mut hasher);
+            let background = format!("_bg{:x}", hasher.finish());
+            tag.push_str(&background);
+        }
+        match self.size {
+            SvgSize::Fixed => {}
+            SvgSize::Responsive => tag.push_str("_responsive"),
+            SvgSize::ResponsiveStretchable => tag.push_str("_stretchable"),
+        }
+        if self.strip_prolog {
+            tag.push_str("_noprolog");
+        }
+        match self.profile {
+            ColorProfile::Untagged => {}
+            ColorProfile::Srgb => tag.push_str("_srgb"),
+            ColorProfile::Icc => {
+                let mut hasher = DefaultHasher::new();
+                self.icc_path.hash(&mut hasher);
+                let hash = format!("_icc{:x}", hasher.finish());
+                tag.push_str(&hash);
+            }
+        }
+        tag
+    }
+}
+
+/// Rasterizes `{color}_eq.svg` at `dpi` to `{color}_eq.png` without compiling the equation again,
+/// then applies `post` to it. `fill` replaces [`CURRENT_COLOR`], which only means something in css.
+pub async fn svg_to_png(dir: Dir, color: String, fill: String, dpi: usize, post: PostProcess) -> Result<(), GuiError> {
+    let from = dir.join(format!("{color}_eq.svg"));
+    let to = dir.join(format!("{color}_eq.png"));
+    let svg = tokio::fs::read_to_string(&from)
+        .await
+        .map_err(|_| GuiError::ReadFile(from.to_string_lossy().to_string()))?;
+    check_png_size(&svg, dpi)?;
+    let svg = svg.replace(CURRENT_COLOR, &fill);
+    let png = tokio::task::spawn_blocking(move || clipboard::rasterize(svg.as_bytes(), dpi))
+        .await
+        .expect("rasterizing doesn't panic")
+        .ok_or_else(|| GuiError::ReadFile(from.to_string_lossy().to_string()))?;
+    png.save(&to)
+        .map_err(|_| GuiError::WriteFile(to.to_string_lossy().to_string().into()))?;
+
+    if post.is_identity() {
+        Ok(())
+    } else {
+        post_process(dir, color, ImageFormat::Png, post).await
+    }
+}
+
+/// Applies `post` to `{color}_eq.{format}`, writing the result to `{color}{post.tag()}_eq.{format}`
+pub async fn post_process(dir: Dir, color: String, format: ImageFormat, post: PostProcess) -> Result<(), GuiError> {
+    let from = dir.join(format!("{color}_eq.{format}"));
+    let to = dir.join(format!("{color}{}_eq.{format}", post.tag()));
+    let to_err = to.to_string_lossy().to_string();
+
+    match format {
+        ImageFormat::Svg => {
+            let mut svg = tokio::fs::read_to_string(&from)
+                .await
+                .map_err(|_| GuiError::ReadFile(from.to_string_lossy().to_string()))?;
+            if post.invert {
+                svg = invert_svg(&svg);
+            }
+            if let Some(shadow) = &post.shadow {
+                svg = shadow_svg(&svg, shadow);
+            }
+            if let Some(aspect) = post.aspect {
+                svg = pad_svg(&svg, aspect);
+            }
+            if let Some(background) = &post.background {
+                svg = background_svg(&svg, background);
+            }
+            if post.size != SvgSize::Fixed {
+                svg = responsive_svg(&svg, post.size == SvgSize::Responsive);
+            }
+            if post.strip_prolog {
+                svg = strip_prolog(&svg).to_string();
+            }
+            tokio::fs::write(&to, svg)
+                .await
+                .map_err(|_| GuiError::WriteFile(to_err.into()))
+        }
+        ImageFormat::Png => {
+            let from_err = from.to_string_lossy().to_string();
+            let icc = if post.profile == ColorProfile::Icc {
+                Some(tokio::fs::read(&post.icc_path)
+                    .await
+                    .map_err(|_| GuiError::ReadFile(post.icc_path.to_string_lossy().to_string()))?)
+            } else {
+                None
+            };
+            tokio::task::spawn_blocking(move || {
+                let mut png = image::open(&from)
+                    .map_err(|_| GuiError::ReadFile(from_err))?
+                    .into_rgba8();
+                if post.invert {
+                    for pixel in png.pixels_mut() {
+                        let [r, g, b, _] = &mut pixel.0;
+                        *r = 255 - *r;
+                        *g = 255 - *g;
+                        *b = 255 - *b;
+                    }
+                }
+                if let Some(padding) = post.crop {
+                    png = crop_png(&png, padding);
+                }
+                if let Some(shadow) = &post.shadow {
+                    png = shadow_png(&png, shadow)?;
+                }
+                if let Some(aspect) = post.aspect {
+                    png = pad_png(&png, aspect);
+                }
+                if let Some(Canvas { width, height, x, y }) = post.canvas {
+                    let mut canvas = RgbaImage::new(width, height);
+                    image::imageops::overlay(&mut canvas, &png, x, y);
+                    png = canvas;
+                }
+                if let Some(background) = &post.background {
+                    let background = color::parse_color(background)
+                        .map(ParsedColor::pixel)
+                        .ok_or_else(|| GuiError::InvalidColor(background.clone()))?;
+                    let mut opaque = RgbaImage::from_pixel(png.width(), png.height(), background);
+                    image::imageops::overlay(&mut opaque, &png, 0, 0);
+                    png = opaque;
+                }
+                save_png(&png, post.depth, post.profile, icc, &to)
+                    .map_err(|_| GuiError::WriteFile(to_err.into()))
+            }).await
+                .expect("post processing doesn't panic")
+        }
+        // nothing applies to pdfs, see `PostProcess::for_format`
+        ImageFormat::Pdf => Ok(()),
+    }
+}
+
+/// Saves `png` with `depth` colors, tagged with `profile`. `icc` is the profile's contents for
+/// [`ColorProfile::Icc`].
+fn save_png(
+    png: &RgbaImage,
+    depth: PngDepth,
+    profile: ColorProfile,
+    icc: Option<Vec<u8>>,
+    path: &Path,
+) -> Result<(), png::EncodingError> {
+    let mut info = if depth == PngDepth::TrueColor {
+        let mut info = png::Info::with_size(png.width(), png.height());
+        info.color_type = png::ColorType::Rgba;
+        info.bit_depth = png::BitDepth::Eight;
+        (info, Cow::Borrowed(png.as_raw().as_slice()))
+    } else {
+        let (info, data) = indexed(png, depth);
+        (info, Cow::Owned(data))
+    };
+    match profile {
+        ColorProfile::Untagged => {}
+        ColorProfile::Srgb => info.0.srgb = Some(png::SrgbRenderingIntent::Perceptual),
+        ColorProfile::Icc => info.0.icc_profile = icc.map(Cow::Owned),
+    }
+    let (info, data) = info;
+
+    png::Encoder::with_info(BufWriter::new(File::create(path)?), info)?
+        .write_header()?
+        .write_image_data(&data)
+}
+
+/// Converts `png` to use a palette instead of a color per pixel, returning the png's info and
+/// pixel data. `depth` must be [`PngDepth::Palette`] or [`PngDepth::Bilevel`].
+fn indexed(png: &RgbaImage, depth: PngDepth) -> (png::Info<'static>, Vec<u8>) {
+    let (palette, indices, bit_depth) = if depth == PngDepth::Bilevel {
+        // the ink is whatever color most of the visible pixels are
+        let mut counts = HashMap::<[u8; 3], usize>::new();
+        for pixel in png.pixels().filter(|p| p[3] >= 128) {
+            let [r, g, b, _] = pixel.0;
+            *counts.entry([r, g, b]).or_default() += 1;
+        }
+        let [r, g, b] = counts.into_iter()
+            .max_by_key(|&(_, count)| count)
+            .map_or([0; 3], |(color, _)| color);
+        let indices = png.pixels()
+            .map(|p| u8::from(p[3] >= 128))
+            .collect::<Vec<_>>();
+        (vec![[0, 0, 0, 0], [r, g, b, 255]], indices, png::BitDepth::One)
+    } else {
+        let mut palette = Vec::new();
+        let mut lookup = HashMap::new();
+        for pixel in png.pixels() {
+            if !lookup.contains_key(&pixel.0) && palette.len() <= 256 {
+                lookup.insert(pixel.0, palette.len());
+                palette.push(pixel.0);
+            }
+        }
+        if palette.len() <= 256 {
+            #[allow(clippy::cast_possible_truncation)]
+            let indices = png.pixels()
+                .map(|p| lookup[&p.0] as u8)
+                .collect();
+            (palette, indices, png::BitDepth::Eight)
+        } else {
+            // too many colors (mostly from anti-aliasing), so let NeuQuant pick the best 256
+            let quant = NeuQuant::new(10, 256, png.as_raw());
+            let palette = quant.color_map_rgba()
+                .chunks_exact(4)
+                .map(|c| [c[0], c[1], c[2], c[3]])
+                .collect();
+            #[allow(clippy::cast_possible_truncation)]
+            let indices = png.pixels()
+                .map(|p| quant.index_of(&p.0) as u8)
+                .collect();
+            (palette, indices, png::BitDepth::Eight)
+        }
+    };
+
+    let data = if bit_depth == png::BitDepth::One {
+        // 8 pixels to a byte, with each row starting on a new byte
+        indices.chunks(png.width() as usize)
+            .flat_map(|row| row.chunks(8).map(|byte| byte.iter()
+                .enumerate()
+                .fold(0, |acc, (i, &bit)| acc | (bit << (7 - i)))))
+            .collect()
+    } else {
+        indices
+    };
+
+    let mut info = png::Info::with_size(png.width(), png.height());
+    info.color_type = png::ColorType::Indexed;
+    info.bit_depth = bit_depth;
+    info.palette = Some(palette.iter().flat_map(|c| &c[..3]).copied().collect());
+    info.trns = Some(palette.iter().map(|c| c[3]).collect());
+    (info, data)
+}
+
+/// The size of an image of `width` by `height` after padding it to the `aspect` ratio
+fn padded_size(width: f64, height: f64, (aspect_width, aspect_height): (u32, u32)) -> (f64, f64) {
+    let ratio = f64::from(aspect_width) / f64::from(aspect_height);
+    if width / height < ratio {
+        (height * ratio, height)
+    } else {
+        (width, width / ratio)
+    }
+}
+
+/// Centers `png` on a transparent canvas with the `aspect` ratio
+fn pad_png(png: &RgbaImage, aspect: (u32, u32)) -> RgbaImage {
+    let (width, height) = padded_size(f64::from(png.width()), f64::from(png.height()), aspect);
+    let (width, height) = (width.round() as u32, height.round() as u32);
+    let mut canvas = RgbaImage::new(width, height);
+    image::imageops::overlay(
+        &mut canvas,
+        png,
+        i64::from((width - png.width()) / 2),
+        i64::from((height - png.height()) / 2),
+    );
+    canvas
+}
+
+/// Crops `png` to its visible pixels, leaving `padding` transparent pixels around them. Blank
+/// images are left alone.
+fn crop_png(png: &RgbaImage, padding: u32) -> RgbaImage {
+    let visible = png.enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[3] != 0)
+        .map(|(x, y, _)| (x, y));
+    let Some((left, top, right, bottom)) = visible.fold(None, |bounds: Option<(u32, u32, u32, u32)>, (x, y)| Some(match bounds {
+        None => (x, y, x, y),
+        Some((left, top, right, bottom)) => (left.min(x), top.min(y), right.max(x), bottom.max(y)),
+    })) else {
+        return png.clone();
+    };
+    let (width, height) = (right - left + 1, bottom - top + 1);
+    let content = image::imageops::crop_imm(png, left, top, width, height).to_image();
+    let mut canvas = RgbaImage::new(width + 2 * padding, height + 2 * padding);
+    image::imageops::overlay(&mut canvas, &content, i64::from(padding), i64::from(padding));
+    canvas
+}
+
+/// Draws `shadow` under `png`, on a canvas grown by [`Shadow::margin`] on each side
+fn shadow_png(png: &RgbaImage, shadow: &Shadow) -> Result<RgbaImage, GuiError> {
+    let color = color::parse_color(&shadow.color)
+        .map(ParsedColor::pixel)
+        .ok_or_else(|| GuiError::InvalidColor(shadow.color.clone()))?;
+    let margin = shadow.margin();
+    let (width, height) = (png.width() + 2 * margin, png.height() + 2 * margin);
+
+    // the equation's silhouette in the shadow's color
+    let mut silhouette = RgbaImage::new(width, height);
+    for (x, y, pixel) in png.enumerate_pixels() {
+        let alpha = (u16::from(pixel[3]) * u16::from(color[3]) / 255) as u8;
+        let (x, y) = (i64::from(x + margin) + i64::from(shadow.x), i64::from(y + margin) + i64::from(shadow.y));
+        if (0..i64::from(width)).contains(&x) && (0..i64::from(height)).contains(&y) {
+            silhouette.put_pixel(x as u32, y as u32, image::Rgba([color[0], color[1], color[2], alpha]));
+        }
+    }
+    let mut canvas = if shadow.blur == 0 {
+        silhouette
+    } else {
+        image::imageops::blur(&silhouette, shadow.blur as f32)
+    };
+    image::imageops::overlay(&mut canvas, png, i64::from(margin), i64::from(margin));
+    Ok(canvas)
+}
+
+/// Grows the root element's size and `viewBox` to the `aspect` ratio, keeping the contents centered
+fn pad_svg(svg: &str, aspect: (u32, u32)) -> String {
+    let Some(start) = svg.find("<svg") else {
+        return svg.to_string();
+    };
+    let Some(end) = svg[start..].find('>').map(|end| start + end + 1) else {
+        return svg.to_string();
+    };
+    let root = &svg[start..end];
+
+    // eg `12.3pt` -> (12.3, "pt")
+    let length = |name| {
+        let value = svg_attribute(root, name)?;
+        let unit_start = value.trim_end_matches(|c: char| c.is_ascii_alphabetic() || c == '%').len();
+        Some((value[..unit_start].parse::<f64>().ok()?, &value[unit_start..]))
+    };
+    let (Some((width, unit)), Some((height, _))) = (length("width"), length("height")) else {
+        return svg.to_string();
+    };
+    if width <= 0.0 || height <= 0.0 {
+        return svg.to_string();
+    }
+    let view_box = svg_attribute(root, "viewBox")
+        .map(|view_box| view_box.split([' ', ',']).filter_map(|n| n.parse::<f64>().ok()).collect::<Vec<_>>())
+        .filter(|view_box| view_box.len() == 4)
+        .unwrap_or_else(|| vec![0.0, 0.0, width, height]);
+
+    let (new_width, new_height) = padded_size(width, height, aspect);
+    // the view box might not be in the same units as the size
+    let view_width = view_box[2] * new_width / width;
+    let view_height = view_box[3] * new_height / height;
+    let view_x = view_box[0] - (view_width - view_box[2]) / 2.0;
+    let view_y = view_box[1] - (view_height - view_box[3]) / 2.0;
+
+    let root = set_svg_attribute(root, "width", &format!("{new_width}{unit}"));
+    let root = set_svg_attribute(&root, "height", &format!("{new_height}{unit}"));
+    let root = set_svg_attribute(&root, "viewBox", &format!("{view_x} {view_y} {view_width} {view_height}"));
+    format!("{}{root}{}", &svg[..start], &svg[end..])
+}
+
+/// Draws `shadow` under the contents of `svg` with a filter, growing its `viewBox` and size by
+/// [`Shadow::margin`] on each side
+fn shadow_svg(svg: &str, shadow: &Shadow) -> String {
+    let Some(start) = svg.find("<svg") else {
+        return svg.to_string();
+    };
+    let Some(end) = svg[start..].find('>').map(|end| start + end + 1) else {
+        return svg.to_string();
+    };
+    let Some(close) = svg.rfind("</svg>") else {
+        return svg.to_string();
+    };
+    let root = &svg[start..end];
+
+    let margin = f64::from(shadow.margin());
+    let view_box = svg_attribute(root, "viewBox")
+        .map(|view_box| view_box.split([' ', ',']).filter_map(|n| n.parse::<f64>().ok()).collect::<Vec<_>>())
+        .filter(|view_box| view_box.len() == 4);
+    let mut root = root.to_string();
+    if let Some(view_box) = view_box {
+        let pad = 2.0 * margin;
+        // the view box might not be in the same units as the size
+        for (name, view_length) in [("width", view_box[2]), ("height", view_box[3])] {
+            let Some(value) = svg_attribute(&root, name) else { continue };
+            let unit_start = value.trim_end_matches(|c: char| c.is_ascii_alphabetic()).len();
+            let Ok(length) = value[..unit_start].parse::<f64>() else { continue };
+            let grown = format!("{}{}", length * (view_length + pad) / view_length, &value[unit_start..]);
+            root = set_svg_attribute(&root, name, &grown);
+        }
+        root = set_svg_attribute(&root, "viewBox", &format!(
+            "{} {} {} {}",
+            view_box[0] - margin,
+            view_box[1] - margin,
+            view_box[2] + pad,
+            view_box[3] + pad,
+        ));
+    }
+
+    let color = shadow.color.replace('"', "'");
+    format!(
+        r#"{}{root}<filter id="eq-shadow" x="-50%" y="-50%" width="200%" height="200%"><feDropShadow dx="{}" dy="{}" stdDeviation="{}" flood-color="{color}"/></filter><g filter="url(#eq-shadow)">{}</g>{}"#,
+        &svg[..start],
+        shadow.x,
+        shadow.y,
+        shadow.blur,
+        &svg[end..close],
+        &svg[close..],
+    )
+}
+
+/// Adds a rect filling the whole view box with `color` behind the contents of `svg`
+fn background_svg(svg: &str, color: &str) -> String {
+    let Some(start) = svg.find("<svg") else {
+        return svg.to_string();
+    };
+    let Some(end) = svg[start..].find('>').map(|end| start + end + 1) else {
+        return svg.to_string();
+    };
+    let rect = svg_attribute(&svg[start..end], "viewBox")
+        .map(|view_box| view_box.split([' ', ',']).filter_map(|n| n.parse::<f64>().ok()).collect::<Vec<_>>())
+        .filter(|view_box| view_box.len() == 4)
+        .map_or_else(
+            || r#"<rect width="100%" height="100%""#.to_string(),
+            |view_box| format!(r#"<rect x="{}" y="{}" width="{}" height="{}""#, view_box[0], view_box[1], view_box[2], view_box[3]),
+        );
+    let color = color.replace('"', "'");
+    format!(r#"{}{rect} fill="{color}"/>{}"#, &svg[..end], &svg[end..])
+}
+
+/// Removes the root element's width and height, so that it's sized by its container. Its
+/// `viewBox` is kept, or made from the size if it doesn't have one, so it still knows its aspect
+/// ratio. With `preserve_aspect`, that ratio is kept even by renderers that would stretch it.
+fn responsive_svg(svg: &str, preserve_aspect: bool) -> String {
+    let Some(start) = svg.find("<svg") else {
+        return svg.to_string();
+    };
+    let Some(end) = svg[start..].find('>').map(|end| start + end + 1) else {
+        return svg.to_string();
+    };
+    let mut root = svg[start..end].to_string();
+
+    if svg_attribute(&root, "viewBox").is_none() {
+        let length = |name| svg_attribute(&root, name)
+            .map(|value| value.trim_end_matches(|c: char| c.is_ascii_alphabetic()))
+            .and_then(|value| value.parse::<f64>().ok());
+        if let (Some(width), Some(height)) = (length("width"), length("height")) {
+            root = set_svg_attribute(&root, "viewBox", &format!("0 0 {width} {height}"));
+        }
+    }
+    root = remove_svg_attribute(&root, "width");
+    root = remove_svg_attribute(&root, "height");
+    if preserve_aspect {
+        root = set_svg_attribute(&root, "preserveAspectRatio", "xMidYMid meet");
+    }
+    format!("{}{root}{}", &svg[..start], &svg[end..])
+}
+
+/// `svg` without its leading `<?xml ...?>` declaration or `<!DOCTYPE ...>`. Comments are kept.
+fn strip_prolog(mut svg: &str) -> &str {
+    loop {
+        let rest = svg.trim_start();
+        let end = if rest.starts_with("<?xml") {
+            rest.find("?>").map(|end| end + 2)
+        } else if rest.starts_with("<!DOCTYPE") {
+            // the doctype can have an internal subset, eg `<!DOCTYPE svg [ <!ENTITY ...> ]>`
+            let subset_end = rest.find('[')
+                .filter(|&open| rest.find('>').is_some_and(|close| open < close))
+                .and_then(|open| rest[open..].find(']').map(|close| open + close))
+                .unwrap_or_default();
+            rest[subset_end..].find('>').map(|end| subset_end + end + 1)
+        } else {
+            None
+        };
+        match end {
+            Some(end) => svg = &rest[end..],
+            None => return rest,
+        }
+    }
+}
+
+/// Wraps the contents of `svg` in a filter that negates its colors but not its transparency
+fn invert_svg(svg: &str) -> String {
+    const FILTER: &str = r#"<filter id="eq-invert" color-interpolation-filters="sRGB"><feColorMatrix type="matrix" values="-1 0 0 0 1 0 -1 0 0 1 0 0 -1 0 1 0 0 0 1 0"/></filter><g filter="url(#eq-invert)">"#;
+
+    let Some(start) = svg.find("<svg")
+        .and_then(|svg_start| svg[svg_start..].find('>').map(|end| svg_start + end + 1)) else {
+        return svg.to_string();
+    };
+    let Some(end) = svg.rfind("</svg>") else {
+        return svg.to_string();
+    };
+    format!("{}{FILTER}{}</g>{}", &svg[..start], &svg[start..end], &svg[end..])
 }
 
 #[derive(Debug, Error, Clone)]
 pub enum CommandError {
     #[error("could not start command `{0}`")]
     ErrorSpawning(String),
+    #[error("edited commands can't be run in safe mode")]
+    SafeMode,
+    #[error("cancelled")]
+    Cancelled,
     #[error("{command} returned {status}:\n{message}")]
     Error {
         status: ExitStatus,
         command: String,
         message: String,
+        /// everything it printed, since `message` is only the part with the error
+        output: String,
     },
 }
 
+/// A command that was run by [`run_command`], so it can be shown and re-run while debugging
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RanCommand {
+    /// the working directory it was run in
+    pub dir: PathBuf,
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl RanCommand {
+    /// The command as it would be typed in a shell
+    pub fn command_line(&self) -> String {
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(|arg| if arg.is_empty() || arg.contains(char::is_whitespace) {
+                format!("\"{arg}\"")
+            } else {
+                arg.clone()
+            })
+            .join(" ")
+    }
+
+    /// Splits a command line on whitespace, except inside double quotes
+    fn parse(dir: PathBuf, command_line: &str) -> Option<Self> {
+        let mut words = Vec::new();
+        let mut word = None::<String>;
+        let mut quoted = false;
+        for c in command_line.chars() {
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    word.get_or_insert_with(String::new);
+                }
+                c if c.is_whitespace() && !quoted => words.extend(word.take()),
+                c => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+        words.extend(word);
+        let mut words = words.into_iter();
+        Some(Self {
+            dir,
+            program: words.next()?,
+            args: words.collect(),
+        })
+    }
+}
+
+static LAST_COMMAND: Lazy<Mutex<Option<RanCommand>>> = Lazy::new(|| Mutex::new(None));
+
+/// Tightens what equations can do, for rendering ones that aren't trusted. In safe mode:
+/// - latex is run with `-no-shell-escape` (as always), and kpathsea's `openin_any`/`openout_any` are
+///   set to paranoid, so files can only be read or written below the scratch directory it runs in
+/// - typst is run with `--root .`, so files outside the cache directory can't be read
+/// - Typst equations can't `import` or `include` anything (see [`check_safe`]), and every tool is
+///   run with an unreachable http(s) proxy, so nothing is downloaded
+/// - Typst's imports aren't checked, since the default ones `#import` a package. What they import
+///   is only limited by `--root .` and the proxy
+/// - extra arguments and re-running edited commands are disabled
+///
+/// This doesn't limit cpu time or memory, so a malicious equation can still take a long time to
+/// render. Packages the templates import have to already be downloaded.
+static SAFE_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn safe_mode() -> bool {
+    SAFE_MODE.load(Ordering::Relaxed)
+}
+
+pub fn set_safe_mode(safe_mode: bool) {
+    SAFE_MODE.store(safe_mode, Ordering::Relaxed);
+}
+
+/// The first line of `program --version` for each program, eg to tell when renders from an older
+/// install are cached. Programs that can't be run are left blank.
+pub async fn tool_versions(programs: &[&str]) -> String {
+    let mut versions = Vec::with_capacity(programs.len());
+    for program in programs {
+        let output = Command::new(program)
+            .arg("--version")
+            .output()
+            .await;
+        versions.push(output
+            .ok()
+            .and_then(|output| String::from_utf8_lossy(&output.stdout).lines().next().map(str::to_string))
+            .unwrap_or_default());
+    }
+    versions.join("\n")
+}
+
+/// Bumped by [`cancel`], which stops the commands of [`cancellable`] futures started before then
+static CANCELLED: Lazy<watch::Sender<u64>> = Lazy::new(|| watch::channel(0).0);
+
+tokio::task_local! {
+    /// The value of [`CANCELLED`] when the [`cancellable`] future running this was made
+    static GENERATION: u64;
+}
+
+/// `future`, but [`cancel`] kills the commands it's running and fails any it starts afterwards
+pub fn cancellable<F: Future>(future: F) -> impl Future<Output=F::Output> {
+    GENERATION.scope(*CANCELLED.borrow(), future)
+}
+
+/// Stops every [`cancellable`] future's commands, eg when a slow compile is cancelled
+pub fn cancel() {
+    CANCELLED.send_modify(|generation| *generation += 1);
+}
+
+/// `future`'s output, or `None` if [`cancel`] is called while it runs
+async fn until_cancelled<F: Future>(generation: u64, future: F) -> Option<F::Output> {
+    let mut receiver = CANCELLED.subscribe();
+    let mut cancelled = pin!(receiver.wait_for(|&current| current != generation));
+    let mut future = pin!(future);
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            Poll::Ready(Some(output))
+        } else if cancelled.as_mut().poll(cx).is_ready() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }).await
+}
+
+/// Limits how many renders run at once. An animation renders its frames, and a manifest its
+/// entries, all at once, alongside the preview's compiles.
+static WORKERS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(default_workers()));
+
+/// How many permits [`WORKERS`] has in total, including ones in use
+static WORKER_LIMIT: Lazy<AtomicUsize> = Lazy::new(|| AtomicUsize::new(default_workers()));
+
+/// Half of the cores, since latex and magick are heavy and the gui needs some too
+pub fn default_workers() -> usize {
+    std::thread::available_parallelism()
+        .map_or(1, |cores| cores.get() / 2)
+        .max(1)
+}
+
+/// Waits until another render can start. Hold the permit for the whole render, not just while a
+/// tool runs, so that a render waiting its turn hasn't set anything up yet
+pub async fn worker() -> SemaphorePermit<'static> {
+    WORKERS.acquire()
+        .await
+        .expect("the semaphore is never closed")
+}
+
+/// Changes how many renders can run at once. Shrinking waits for running tools to finish.
+pub async fn set_max_workers(workers: usize) {
+    let workers = workers.max(1);
+    let old = WORKER_LIMIT.swap(workers, Ordering::Relaxed);
+    if workers > old {
+        WORKERS.add_permits(workers - old);
+    } else if workers < old {
+        let shrink = u32::try_from(old - workers).unwrap_or(u32::MAX);
+        WORKERS.acquire_many(shrink)
+            .await
+            .expect("the semaphore is never closed")
+            .forget();
+    }
+}
+
+/// The default for [`MAX_PNG_SIZE`], enough for a full page at 600 dpi
+pub const DEFAULT_MAX_PNG_SIZE: u32 = 10_000;
+
+/// The longest side a png can have, in pixels. Typst pages are sized to fit the equation, so a
+/// runaway one rendered at a high dpi could take minutes and gigabytes of memory to rasterize.
+/// Bigger pngs are refused before they are rendered.
+static MAX_PNG_SIZE: AtomicU32 = AtomicU32::new(DEFAULT_MAX_PNG_SIZE);
+
+pub fn set_max_png_size(max: u32) {
+    MAX_PNG_SIZE.store(max, Ordering::Relaxed);
+}
+
+/// Errors if `svg` would be bigger than [`MAX_PNG_SIZE`] when rasterized at `dpi`. Svgs without
+/// a width & height in pt or px aren't checked.
+pub fn check_png_size(svg: &str, dpi: usize) -> Result<(), GuiError> {
+    let start = svg.find("<svg").unwrap_or_default();
+    let root = &svg[start..svg[start..].find('>').map_or(svg.len(), |end| start + end)];
+    let pixels = |name| {
+        let length = svg_attribute(root, name)?;
+        let pt = length.strip_suffix("pt")
+            .and_then(|pt| pt.parse::<f64>().ok())
+            .or_else(|| length.trim_end_matches("px").parse::<f64>().ok().map(|px| px * 0.75))?;
+        Some((pt / 72.0 * dpi as f64).ceil() as u64)
+    };
+    let (Some(width), Some(height)) = (pixels("width"), pixels("height")) else {
+        return Ok(());
+    };
+    let max = MAX_PNG_SIZE.load(Ordering::Relaxed);
+    if width.max(height) > u64::from(max) {
+        Err(GuiError::TooLarge(width, height, max))
+    } else {
+        Ok(())
+    }
+}
+
+/// Makes sure `eq` doesn't do anything [`SAFE_MODE`] disallows. Typst's imports are passed
+/// separately and aren't checked. For Typst this only looks for the words `import`, `include`, and
+/// `eval`, without parsing the equation: they're rejected even in text, and nothing else is
+/// checked. So it stops the obvious cases with a clear error, but it's `--root .` and the proxy
+/// that actually keep files and downloads out of reach.
+pub fn check_safe(eq: &str, backend: Backend) -> Result<(), GuiError> {
+    if !safe_mode() {
+        return Ok(());
+    }
+    match backend {
+        // everything else is handled by kpathsea
+        Backend::LaTeX => Ok(()),
+        // `eval` could build an `import` out of strings
+        Backend::Typst => ["import", "include", "eval"].into_iter()
+            .find(|keyword| eq.split(|c: char| !c.is_alphanumeric()).any(|word| word == *keyword))
+            .map_or(Ok(()), |keyword| Err(GuiError::Unsafe(keyword))),
+    }
+}
+
+/// The command most recently started by [`run_command`]
+pub fn last_command() -> Option<RanCommand> {
+    LAST_COMMAND.lock()
+        .expect("last command isn't poisoned")
+        .clone()
+}
+
+/// Runs an edited version of a [`RanCommand`] in the same directory, returning its output
+pub async fn rerun(dir: PathBuf, command_line: String) -> Result<String, CommandError> {
+    if safe_mode() {
+        return Err(CommandError::SafeMode);
+    }
+    let command = RanCommand::parse(dir.clone(), &command_line)
+        .ok_or(CommandError::ErrorSpawning(command_line))?;
+    run_command_in(Some(dir), &command.program, command.args).await
+}
+
+/// Finds `program` in one of the directories on the `PATH`, like a shell would
+pub fn find_on_path(program: &str) -> Option<PathBuf> {
+    let name = format!("{program}{}", std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+}
+
+/// How to run `ImageMagick` on this machine
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Magick {
+    pub program: String,
+    /// arguments that go before the conversion's
+    pub subcommand: Option<&'static str>,
+}
+
+/// Looked up once, see [`magick`]
+static MAGICK: Lazy<Option<Magick>> = Lazy::new(|| find_magick(cfg!(windows), find_on_path));
+
+/// How to run `ImageMagick`, with `find` looking a program up on the PATH
+fn find_magick(windows: bool, find: impl Fn(&str) -> Option<PathBuf>) -> Option<Magick> {
+    let magick = |subcommand| find("magick")
+        .map(|path| Magick { program: path.to_string_lossy().to_string(), subcommand });
+    if windows {
+        // `convert.exe` is Windows' own file system converter
+        magick(Some("convert"))
+    } else {
+        // ImageMagick 7 converts without a subcommand, 6 only has `convert`
+        magick(None).or_else(|| find("convert")
+            .map(|path| Magick { program: path.to_string_lossy().to_string(), subcommand: None }))
+    }
+}
+
+/// `ImageMagick`'s `magick`, or its legacy `convert` if that's all that is installed
+pub fn magick() -> Result<&'static Magick, GuiError> {
+    MAGICK.as_ref()
+        .ok_or(GuiError::BinaryNotFound("ImageMagick"))
+}
+
 pub async fn run_command<I, S>(command: &str, args: I) -> Result<String, CommandError>
     where
         I: IntoIterator<Item=S> + Send,
         S: AsRef<OsStr>,
 {
-    fn utf8_to_string(utf8: &[u8]) -> String {
-        std::str::from_utf8(utf8)
-            .map(str::to_string)
-            .expect("latex, typst, dvisvgm always have utf8 outputs")
-    }
+    run_command_in(None, command, args).await
+}
+
+// some locales (and some fonts' names in error messages) aren't utf8, which isn't worth crashing
+//  over
+fn utf8_to_string(utf8: &[u8]) -> String {
+    String::from_utf8_lossy(utf8).into_owned()
+}
+
+/// The message and full output of a command that failed. LaTeX's message is the error starting at
+/// its `!`, other tools' is everything they printed.
+fn error_output(stdout: &[u8], stderr: &[u8]) -> (String, String) {
+    let message = utf8_to_string(stdout);
+    let stderr = utf8_to_string(stderr);
+    println!("stdout = {message}");
+    println!("stderr = {stderr}");
+    let output = [message.trim_end(), stderr.trim_end()].into_iter()
+        .filter(|output| !output.is_empty())
+        .join("\n\n");
+    let message = if message.is_empty() {
+        stderr
+    } else if let Some(idx) = message.find('!') {
+        message[idx..].lines()
+            .take_while(|l| l.chars().any(|c| !c.is_ascii_whitespace()))
+            .join("\n")
+    } else {
+        message
+    };
+    (message, output)
+}
+
+/// The discard port, so any download fails right away, see [`SAFE_MODE`]
+const UNREACHABLE_PROXY: &str = "http://127.0.0.1:9";
+
+/// Runs `command` in `dir`, or the current directory if that's `None`
+pub async fn run_command_in<I, S>(dir: Option<PathBuf>, command: &str, args: I) -> Result<String, CommandError>
+    where
+        I: IntoIterator<Item=S> + Send,
+        S: AsRef<OsStr>,
+{
     // Constant can be found in `winapi` or `windows` crates as well
     //
     // List of all process creation flags:
     // https://learn.microsoft.com/en-us/windows/win32/procthread/process-creation-flags
+    #[cfg(windows)]
     const CREATE_NO_WINDOW: u32 = 0x0800_0000; // Or `134217728u32`
 
-    let Output { status, stdout, stderr } = Command::new(command)
-        .args(args)
-        .creation_flags(CREATE_NO_WINDOW)
-        .output()
-        .await
+    let args = args.into_iter()
+        .map(|arg| arg.as_ref().to_os_string())
+        .collect::<Vec<OsString>>();
+    let working_dir = dir.clone()
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default();
+    *LAST_COMMAND.lock().expect("last command isn't poisoned") = Some(RanCommand {
+        dir: working_dir,
+        program: command.to_string(),
+        args: args.iter()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect(),
+    });
+
+    let mut cmd = Command::new(command);
+    cmd.args(&args);
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    if safe_mode() {
+        cmd.env("openin_any", "p")
+            .env("openout_any", "p")
+            .env("HTTP_PROXY", UNREACHABLE_PROXY)
+            .env("HTTPS_PROXY", UNREACHABLE_PROXY);
+    }
+    #[cfg(windows)]
+    cmd.creation_flags(CREATE_NO_WINDOW);
+    // so that cancelling, which drops `output`, stops the command too
+    cmd.kill_on_drop(true);
+    let output = match GENERATION.try_with(|&generation| generation) {
+        Ok(generation) => until_cancelled(generation, cmd.output())
+            .await
+            .ok_or(CommandError::Cancelled)?,
+        Err(_) => cmd.output().await,
+    };
+    let Output { status, stdout, stderr } = output
         .map_err(|_| CommandError::ErrorSpawning(command.to_string()))?;
     if status.success() {
         Ok(utf8_to_string(&stdout))
     } else {
-        let message = utf8_to_string(&stdout);
-        println!("stdout = {message}");
-        println!("stderr = {}", utf8_to_string(&stderr));
-        let message = if message.is_empty() {
-            utf8_to_string(&stderr)
-        } else if let Some(idx) = message.find('!') {
-            message[idx..].lines()
-                .take_while(|l| l.chars().any(|c| !c.is_ascii_whitespace()))
-                .join("\n")
-        } else {
-            message
-        };
+        let (message, output) = error_output(&stdout, &stderr);
         Err(CommandError::Error {
             status,
             command: command.to_string(),
             message,
+            output,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        assert_eq!(utf8_to_string(b"caf\xe9"), "caf\u{FFFD}");
+    }
+
+    #[test]
+    fn latex_error_with_invalid_utf8() {
+        let stdout = b"This is pdfTeX\n! Undefined control sequence \xff.\nl.5 $\\foo$\n\nNo pages of output.\n";
+        let (message, output) = error_output(stdout, b"");
+        assert_eq!(message, "! Undefined control sequence \u{FFFD}.\nl.5 $\\foo$");
+        assert!(output.starts_with("This is pdfTeX\n"));
+        assert!(output.ends_with("No pages of output."));
+    }
+
+    #[test]
+    fn stderr_with_invalid_utf8() {
+        let (message, output) = error_output(b"", b"error: unknown font \xc3\x28\n");
+        assert_eq!(message, "error: unknown font \u{FFFD}(\n");
+        assert_eq!(output, "error: unknown font \u{FFFD}(");
+    }
+
+    /// Looks up only `installed`, in `/bin`
+    fn installed<'a>(installed: &'a [&'a str]) -> impl Fn(&str) -> Option<PathBuf> + 'a {
+        |program| installed.contains(&program).then(|| Path::new("/bin").join(program))
+    }
+
+    #[test]
+    fn magick_on_windows() {
+        assert_eq!(
+            find_magick(true, installed(&["magick", "convert"])),
+            Some(Magick { program: Path::new("/bin").join("magick").to_string_lossy().to_string(), subcommand: Some("convert") }),
+        );
+        // never Windows' own `convert.exe`
+        assert_eq!(find_magick(true, installed(&["convert"])), None);
+    }
+
+    #[test]
+    fn magick_elsewhere() {
+        assert_eq!(
+            find_magick(false, installed(&["magick", "convert"])),
+            Some(Magick { program: Path::new("/bin").join("magick").to_string_lossy().to_string(), subcommand: None }),
+        );
+        assert_eq!(
+            find_magick(false, installed(&["convert"])),
+            Some(Magick { program: Path::new("/bin").join("convert").to_string_lossy().to_string(), subcommand: None }),
+        );
+        assert_eq!(find_magick(false, installed(&[])), None);
+    }
+
+    #[test]
+    fn responsive_svg_keeps_view_box() {
+        let svg = r#"<?xml version='1.0'?><svg width="12.5pt" height='5pt' viewBox="1 2 12.5 5"><g/></svg>"#;
+        assert_eq!(
+            responsive_svg(svg, true),
+            r#"<?xml version='1.0'?><svg viewBox="1 2 12.5 5" preserveAspectRatio="xMidYMid meet"><g/></svg>"#,
+        );
+        assert_eq!(
+            responsive_svg(svg, false),
+            r#"<?xml version='1.0'?><svg viewBox="1 2 12.5 5"><g/></svg>"#,
+        );
+    }
+
+    #[test]
+    fn responsive_svg_adds_view_box() {
+        assert_eq!(
+            responsive_svg(r#"<svg width="12.5pt" height="5pt"><g/></svg>"#, false),
+            r#"<svg viewBox="0 0 12.5 5"><g/></svg>"#,
+        );
+    }
+
+    #[test]
+    fn responsive_svg_without_svg() {
+        assert_eq!(responsive_svg("not an svg", true), "not an svg");
+    }
+
+    #[test]
+    fn both_outputs_are_kept() {
+        let (message, output) = error_output(b"warning\n", b"error\xfe\n");
+        assert_eq!(message, "warning\n");
+        assert_eq!(output, "warning\n\nerror\u{FFFD}");
+    }
+}
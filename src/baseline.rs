@@ -0,0 +1,36 @@
+//! Finding an equation's baseline, so it can be lined up with the surrounding text when it's
+//! embedded inline
+
+use std::collections::HashMap;
+
+use resvg::usvg::{self, NodeExt, NodeKind, TreeParsing};
+
+/// How far the baseline is above the bottom of `svg`, in pt.
+///
+/// Neither backend writes the baseline out, but both place each glyph with its origin on the
+/// baseline it sits on. The baseline most glyphs sit on is the equation's, since sub- and
+/// superscripts, fractions, and so on are usually fewer. Thin paths like fraction bars aren't
+/// glyphs, so they're skipped.
+pub fn depth(svg: &[u8]) -> Option<f64> {
+    let tree = usvg::Tree::from_data(svg, &usvg::Options::default()).ok()?;
+
+    // glyph origins, rounded so that tiny differences don't split a baseline
+    let mut baselines = HashMap::<i64, usize>::new();
+    for node in tree.root.descendants() {
+        let NodeKind::Path(ref path) = *node.borrow() else { continue };
+        let bounds = path.data.bounds();
+        if bounds.height() * 4.0 < bounds.width() {
+            continue;
+        }
+        let y = node.abs_transform().ty;
+        *baselines.entry((f64::from(y) * 100.0).round() as i64).or_default() += 1;
+    }
+    // ties go to the lowest baseline
+    let (&baseline, _) = baselines.iter()
+        .max_by_key(|&(&y, &count)| (count, y))?;
+
+    let view_box = tree.view_box.rect;
+    // the size is in px, at 96 per inch
+    let pt_per_unit = f64::from(tree.size.height() / view_box.height()) * 0.75;
+    Some((f64::from(view_box.bottom()) - baseline as f64 / 100.0) * pt_per_unit)
+}
@@ -0,0 +1,189 @@
+//! Quick syntactic checks for common mistakes, so they can be pointed out without waiting for the
+//! compiler. These are only heuristics: they never stop an equation from being compiled.
+
+use crate::backends::Backend;
+
+/// Looks for likely mistakes in `eq`, returning a short description of the first one found
+pub fn check(eq: &str, backend: Backend) -> Option<String> {
+    match backend {
+        Backend::LaTeX => check_latex(eq),
+        Backend::Typst => check_typst(eq),
+    }
+}
+
+fn check_latex(eq: &str) -> Option<String> {
+    let mut braces = 0_i32;
+    let mut dollars = 0;
+    let mut envs = Vec::new();
+    let mut lefts = 0_i32;
+
+    let mut chars = eq.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '%' => {
+                // comment until the end of the line
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            }
+            '\\' => {
+                let rest = &eq[i + 1..];
+                let command: String = rest.chars()
+                    .take_while(char::is_ascii_alphabetic)
+                    .collect();
+                if command.is_empty() {
+                    // escaped character such as `\{` or `\\`
+                    chars.next();
+                    continue;
+                }
+                let after = &rest[command.len()..];
+                match command.as_str() {
+                    "frac" | "dfrac" | "tfrac" if after.starts_with("{}") =>
+                        return Some(format!("`\\{command}` has an empty numerator")),
+                    "frac" | "dfrac" | "tfrac" if braced_arg(after).is_some_and(|rest| rest.starts_with("{}")) =>
+                        return Some(format!("`\\{command}` has an empty denominator")),
+                    "left" => lefts += 1,
+                    "right" => lefts -= 1,
+                    "begin" => if let Some(env) = braced_arg_contents(after) {
+                        envs.push(env);
+                    },
+                    "end" => if let Some(env) = braced_arg_contents(after) {
+                        match envs.pop() {
+                            Some(begin) if begin == env => {}
+                            Some(begin) => return Some(format!("`\\begin{{{begin}}}` is closed by `\\end{{{env}}}`")),
+                            None => return Some(format!("`\\end{{{env}}}` has no matching `\\begin`")),
+                        }
+                    },
+                    _ => {}
+                }
+                for _ in 0..command.len() {
+                    chars.next();
+                }
+            }
+            '{' => braces += 1,
+            '}' => {
+                braces -= 1;
+                if braces < 0 {
+                    return Some("unmatched `}`".into());
+                }
+            }
+            '$' => dollars += 1,
+            _ => {}
+        }
+    }
+
+    if braces > 0 {
+        Some(format!("{braces} unclosed `{{`"))
+    } else if dollars % 2 == 1 {
+        Some("unmatched `$`".into())
+    } else if let Some(env) = envs.pop() {
+        Some(format!("`\\begin{{{env}}}` is never closed"))
+    } else if lefts != 0 {
+        Some("`\\left` and `\\right` don't match up".into())
+    } else {
+        None
+    }
+}
+
+/// Skips over a `{...}` argument at the start of `s`, returning what follows it
+fn braced_arg(s: &str) -> Option<&str> {
+    let rest = s.strip_prefix('{')?;
+    let mut depth = 1;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&rest[i + 1..]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The contents of a simple `{...}` argument at the start of `s`, eg an environment name
+fn braced_arg_contents(s: &str) -> Option<&str> {
+    let rest = s.strip_prefix('{')?;
+    rest.find('}').map(|end| &rest[..end])
+}
+
+fn check_typst(eq: &str) -> Option<String> {
+    // `(` and `[` are counted together so that half-open intervals like `[0, 1)` are fine
+    let mut parens = 0_i32;
+    let mut in_string = false;
+
+    let mut chars = eq.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_string {
+            match c {
+                '\\' => { chars.next(); }
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '\\' => { chars.next(); }
+            '/' if chars.peek() == Some(&'/') => {
+                // comment until the end of the line
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '"' => in_string = true,
+            '$' => return Some("`$` ends the equation early, use `\\$` for a literal dollar sign".into()),
+            '(' | '[' => parens += 1,
+            ')' | ']' => {
+                parens -= 1;
+                if parens < 0 {
+                    return Some(format!("unmatched `{c}`"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if in_string {
+        Some("unclosed `\"`".into())
+    } else if parens > 0 {
+        Some(format!("{parens} unclosed bracket{}", if parens == 1 { "" } else { "s" }))
+    } else if eq.contains("frac()") {
+        Some("`frac` needs a numerator and denominator".into())
+    } else {
+        None
+    }
+}
+
+/// Typst functions that are commonly written by mistake in LaTeX mode, eg `frac(1, 2)`
+const TYPST_FUNCTIONS: [&str; 12] = [
+    "frac", "sqrt", "root", "binom", "vec", "mat", "cases", "abs", "norm", "floor", "ceil", "lr",
+];
+
+/// Whether `eq` looks like it was written for the other backend, eg `\frac{1}{2}` in Typst mode.
+/// This is only used to suggest switching after a compile fails, so it errs on the side of `None`.
+pub fn other_syntax(eq: &str, backend: Backend) -> Option<Backend> {
+    let other = match backend {
+        Backend::LaTeX => looks_like_typst(eq),
+        // in Typst `\` only escapes single characters
+        Backend::Typst => has_command(eq),
+    };
+    other.then(|| backend.flip())
+}
+
+/// Has no LaTeX commands, but calls one of [`TYPST_FUNCTIONS`]
+fn looks_like_typst(eq: &str) -> bool {
+    !has_command(eq) && TYPST_FUNCTIONS.iter().any(|function| {
+        eq.match_indices(&format!("{function}(")).any(|(i, _)| {
+            !eq[..i].ends_with(|c: char| c.is_alphanumeric() || c == '\\')
+        })
+    })
+}
+
+/// A `\` followed by at least two letters, eg `\frac` but not `\n` or `\$`
+fn has_command(eq: &str) -> bool {
+    eq.match_indices('\\').any(|(i, _)| {
+        eq[i + 1..].chars()
+            .take_while(char::is_ascii_alphabetic)
+            .nth(1)
+            .is_some()
+    })
+}
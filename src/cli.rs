@@ -0,0 +1,101 @@
+//! Rendering from the command line without opening the window, eg in a build script
+
+use std::future::Future;
+use std::path::PathBuf;
+
+use clap::Parser;
+use tempdir::TempDir;
+
+use crate::{GuiError, latex, manifest, typst};
+use crate::backends::Backend;
+use crate::gui::{DEFAULT_COLOR, ImageFormat};
+use crate::manifest::Entry;
+use crate::utils::file_size;
+
+/// Renders a LaTeX or Typst equation to an svg, png, or pdf. Opens the window when there's no
+/// equation or manifest to render.
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Cli {
+    /// the equation to render
+    pub equation: Option<String>,
+    #[arg(long, value_enum, default_value_t)]
+    pub backend: Backend,
+    /// any color the backend understands
+    #[arg(long, default_value = DEFAULT_COLOR)]
+    pub color: String,
+    #[arg(long, value_enum, default_value_t)]
+    pub format: ImageFormat,
+    /// only used for pngs
+    #[arg(long, default_value_t = 1000)]
+    pub dpi: usize,
+    /// where to write the image, `eq.svg`, `eq.png`, or `eq.pdf` by default
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+    /// render every figure in a json or yaml manifest instead
+    #[arg(long, conflicts_with = "equation")]
+    pub manifest: Option<PathBuf>,
+    /// show a console with debug output, on Windows
+    #[arg(long)]
+    pub console: bool,
+}
+
+impl Cli {
+    /// Whether this renders without opening the window
+    pub const fn is_headless(&self) -> bool {
+        self.equation.is_some() || self.manifest.is_some()
+    }
+
+    /// Renders the equation or manifest, returning the exit code. `None` if the window should be
+    /// opened instead.
+    pub fn run(self) -> Option<i32> {
+        if let Some(path) = self.manifest {
+            let code = match block_on(manifest::run(path)) {
+                Ok(0) => 0,
+                Ok(_) => 1,
+                Err(e) => {
+                    eprintln!("{e}");
+                    2
+                }
+            };
+            return Some(code);
+        }
+
+        let entry = Entry {
+            equation: self.equation?,
+            backend: self.backend,
+            color: self.color,
+            format: self.format,
+            dpi: self.dpi,
+            preamble: latex::DEFAULT_PREAMBLE.into(),
+            imports: typst::DEFAULT_IMPORTS.into(),
+            output: self.out.unwrap_or_else(|| self.format.default_file_name().into()),
+        };
+        let code = match block_on(render(&entry)) {
+            Ok(size) => {
+                println!("{} ({})", entry.output.display(), file_size(size));
+                0
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                1
+            }
+        };
+        Some(code)
+    }
+}
+
+async fn render(entry: &Entry) -> Result<u64, GuiError> {
+    let temp = TempDir::new("cli_")
+        .map_err(|_| GuiError::TempDir)?;
+    manifest::render(entry, temp.path().join("eq"), &entry.output).await
+}
+
+/// The window isn't running iced's runtime, so make one to render with
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("can start the runtime")
+        .block_on(future)
+}
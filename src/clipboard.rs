@@ -0,0 +1,69 @@
+//! Copying rendered images to the system clipboard, without writing them to `out_dir`
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use arboard::{Clipboard, ImageData};
+use image::RgbaImage;
+use once_cell::sync::Lazy;
+use resvg::{tiny_skia, usvg};
+use resvg::usvg::TreeParsing;
+
+use crate::GuiError;
+
+/// Kept around because on Linux the clipboard's contents are lost once it is dropped
+static CLIPBOARD: Lazy<Mutex<Option<Clipboard>>> = Lazy::new(|| Mutex::new(None));
+
+/// Copies the image at `path` to the clipboard. Svgs are rasterized at `dpi`, pngs are copied as is.
+pub async fn copy_image(path: PathBuf, dpi: usize) -> Result<(), GuiError> {
+    tokio::task::spawn_blocking(move || {
+        let path_err = || GuiError::ReadFile(path.to_string_lossy().to_string());
+        let image = if path.extension().is_some_and(|ext| ext == "svg") {
+            let svg = std::fs::read(&path).map_err(|_| path_err())?;
+            rasterize(&svg, dpi).ok_or_else(path_err)?
+        } else {
+            image::open(&path).map_err(|_| path_err())?.into_rgba8()
+        };
+
+        let mut clipboard = CLIPBOARD.lock().expect("clipboard isn't poisoned");
+        if clipboard.is_none() {
+            *clipboard = Some(Clipboard::new().map_err(|e| GuiError::Clipboard(e.to_string()))?);
+        }
+        let (width, height) = image.dimensions();
+        clipboard.as_mut()
+            .expect("just set")
+            .set_image(ImageData {
+                width: width as usize,
+                height: height as usize,
+                bytes: Cow::Owned(image.into_raw()),
+            })
+            .map_err(|e| GuiError::Clipboard(e.to_string()))
+    }).await
+        .expect("copying doesn't panic")
+}
+
+/// Renders `svg` at `dpi`, the same size `magick -density {dpi}` would make it
+pub fn rasterize(svg: &[u8], dpi: usize) -> Option<RgbaImage> {
+    let tree = usvg::Tree::from_data(svg, &usvg::Options::default()).ok()?;
+    // usvg sizes are in px at 96 dpi
+    render(&tree, dpi as f32 / 96.0)
+}
+
+/// Renders `tree` at `scale` times its size
+pub fn render(tree: &usvg::Tree, scale: f32) -> Option<RgbaImage> {
+    let tree = resvg::Tree::from_usvg(tree);
+    let size = tree.size.to_int_size().scale_by(scale)?;
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())?;
+    tree.render(tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    // tiny_skia's pixels have premultiplied alpha
+    let pixels = pixmap.pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let pixel = pixel.demultiply();
+            [pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()]
+        })
+        .collect();
+    RgbaImage::from_raw(size.width(), size.height(), pixels)
+}
@@ -0,0 +1,30 @@
+//! Parsing the colors typed into the gui, so that every backend and export agrees on what's a color
+
+/// A css color, as rgba
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ParsedColor(pub [u8; 4]);
+
+impl ParsedColor {
+    pub const fn pixel(self) -> image::Rgba<u8> {
+        image::Rgba(self.0)
+    }
+
+    pub fn iced(self) -> iced::Color {
+        let [r, g, b, a] = self.0;
+        iced::Color::from_rgba8(r, g, b, f32::from(a) / 255.0)
+    }
+
+    /// Each channel out of 1, like LaTeX's `rgb` color model
+    pub fn rgb_fractions(self) -> [f32; 3] {
+        let [r, g, b, _] = self.0;
+        [r, g, b].map(|channel| f32::from(channel) / 255.0)
+    }
+}
+
+/// `color` if it's a css color: a name, `#` and 3, 4, 6, or 8 hex digits, `rgb()`, `rgba()`, or
+/// `hsl()`. LaTeX's dvipsnames that aren't also css names, eg `BurntOrange`, aren't colors here,
+/// since LaTeX's svgs are recolored with css.
+pub fn parse_color(color: &str) -> Option<ParsedColor> {
+    let svgtypes::Color { red, green, blue, alpha } = color.trim().parse().ok()?;
+    Some(ParsedColor([red, green, blue, alpha]))
+}
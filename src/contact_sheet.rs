@@ -0,0 +1,116 @@
+//! A single image summarizing every equation rendered this session, laid out in a grid
+
+use std::path::PathBuf;
+
+use image::RgbaImage;
+use image::imageops;
+use once_cell::sync::Lazy;
+use resvg::usvg::{self, fontdb, TreeParsing, TreeTextToPath};
+use serde::{Deserialize, Serialize};
+
+use crate::{clipboard, GuiError};
+
+/// Loading the system fonts is slow, so only do it once
+static FONTS: Lazy<fontdb::Database> = Lazy::new(|| {
+    let mut fonts = fontdb::Database::new();
+    fonts.load_system_fonts();
+    fonts
+});
+
+/// Height of the labels under each equation, in pixels
+const LABEL_HEIGHT: u32 = 24;
+
+/// A rendered equation, copied out of the cache so that later renders don't overwrite it
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    /// an svg or png
+    pub path: PathBuf,
+    /// the equation's source
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ContactSheet {
+    pub columns: u32,
+    /// transparent space around each equation, in pixels
+    pub padding: u32,
+    /// write each equation's source under it
+    pub labels: bool,
+}
+
+impl Default for ContactSheet {
+    fn default() -> Self {
+        Self {
+            columns: 3,
+            padding: 20,
+            labels: true,
+        }
+    }
+}
+
+/// Arranges `thumbnails` in a grid and writes it to `path` as a png, returning its size. Svgs are
+/// rasterized at `dpi`.
+pub async fn export(thumbnails: Vec<Thumbnail>, sheet: ContactSheet, dpi: usize, path: PathBuf) -> Result<u64, GuiError> {
+    tokio::task::spawn_blocking(move || {
+        let images = thumbnails.iter()
+            .map(|thumbnail| {
+                let path_err = || GuiError::ReadFile(thumbnail.path.to_string_lossy().to_string());
+                if thumbnail.path.extension().is_some_and(|ext| ext == "svg") {
+                    let svg = std::fs::read(&thumbnail.path).map_err(|_| path_err())?;
+                    clipboard::rasterize(&svg, dpi).ok_or_else(path_err)
+                } else {
+                    Ok(image::open(&thumbnail.path).map_err(|_| path_err())?.into_rgba8())
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let columns = sheet.columns.max(1);
+        let rows = (images.len() as u32).div_ceil(columns);
+        let label_height = if sheet.labels { LABEL_HEIGHT } else { 0 };
+        let cell_width = images.iter().map(RgbaImage::width).max().unwrap_or_default() + 2 * sheet.padding;
+        let cell_height = images.iter().map(RgbaImage::height).max().unwrap_or_default() + 2 * sheet.padding + label_height;
+
+        let mut canvas = RgbaImage::new(cell_width * columns, cell_height * rows);
+        for (i, (image, thumbnail)) in images.iter().zip(&thumbnails).enumerate() {
+            let x = (i as u32 % columns) * cell_width;
+            let y = (i as u32 / columns) * cell_height;
+            imageops::overlay(
+                &mut canvas,
+                image,
+                i64::from(x + (cell_width - image.width()) / 2),
+                i64::from(y + (cell_height - label_height - image.height()) / 2),
+            );
+            if let Some(label) = sheet.labels.then(|| label(&thumbnail.label, cell_width)).flatten() {
+                imageops::overlay(
+                    &mut canvas,
+                    &label,
+                    i64::from(x),
+                    i64::from(y + cell_height - label_height),
+                );
+            }
+        }
+
+        let path_err = path.to_string_lossy().to_string();
+        canvas.save(&path)
+            .map_err(|_| GuiError::WriteFile(path_err.clone().into()))?;
+        std::fs::metadata(&path)
+            .map(|metadata| metadata.len())
+            .map_err(|_| GuiError::ReadFile(path_err))
+    }).await
+        .expect("making the contact sheet doesn't panic")
+}
+
+/// `text` centered in a `width` wide strip, in a gray that shows up on light and dark backgrounds
+fn label(text: &str, width: u32) -> Option<RgbaImage> {
+    let text = text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    let svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{LABEL_HEIGHT}"><text x="{x}" y="{y}" font-family="Segoe UI, Helvetica, Arial, DejaVu Sans, sans-serif" font-size="14" fill="gray" text-anchor="middle">{text}</text></svg>"#,
+        x = width / 2,
+        y = LABEL_HEIGHT * 3 / 4,
+    );
+    let mut tree = usvg::Tree::from_str(&svg, &usvg::Options::default()).ok()?;
+    tree.convert_text(&FONTS);
+    clipboard::render(&tree, 1.0)
+}
@@ -1,43 +1,68 @@
-use std::{env, fs, io, mem};
+use std::{env, fs, io, iter, mem};
 use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
 use std::fmt::{Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use iced::{Alignment, Application, Command, ContentFit, Element, Event, font, keyboard, Subscription, Theme, widget};
+use clap::ValueEnum;
+use iced::{Alignment, Application, Color, Command, ContentFit, Element, Event, Font, font, keyboard, Point, Subscription, Theme, Vector, widget, window};
 use iced::alignment::{Horizontal, Vertical};
-use iced::keyboard::{Key, key::Named};
 use iced::Length::{Fill, FillPortion};
-use iced::widget::{button, container, Container, horizontal_rule, image, pick_list, scrollable, svg, text, text_input};
+use iced::widget::{button, checkbox, container, Container, horizontal_rule, image, pick_list, scrollable, slider, svg, text, text_editor, text_input, tooltip};
 use iced::widget::svg::Handle;
 use iced::widget::text_input::Id;
+use itertools::Itertools;
 use once_cell::sync::Lazy;
-use rfd::{AsyncFileDialog, FileHandle};
+use rfd::{AsyncFileDialog, AsyncMessageDialog, FileHandle, MessageButtons, MessageDialogResult, MessageLevel};
+use serde::{Deserialize, Serialize};
 use tempdir::TempDir;
 
-use crate::{col, easing, GuiError, ICON_FONT, ICON_FONT_BYTES, latex, row, typst};
-use crate::backends::Backend;
+use crate::{animation, baseline, check, clipboard, col, color, contact_sheet, easing, pdf, style, GuiError, history, html, ICON_FONT, ICON_FONT_BYTES, latex, rounding, row, shortcuts, theme_pair, translate, typst};
+use crate::animation::{Animation, AnimationFormat};
+use crate::backends::{self, Backend, Canvas, ColorProfile, CommandError, CURRENT_COLOR, ExtraArgs, Layout, LineAlignment, PngDepth, PostProcess, RanCommand, Shadow, SvgSize, Tool};
 use crate::circular::Circular;
+use crate::color::ParsedColor;
+use crate::style::ThemeMode;
+use crate::contact_sheet::{ContactSheet, Thumbnail};
+use crate::pdf::PdfOptions;
+use crate::html::HtmlStyle;
+use crate::history::History;
 use crate::icons::Icon;
+use crate::latex::SvgFonts;
+use crate::settings::{self, Settings};
+use crate::share::ShareMethod;
+use crate::sink::{LocalFile, OutputSink};
+use crate::theme_pair::{ThemePair, Variant};
+use crate::templates::Template;
+use crate::typst::MathFont;
+use crate::zoom::{self, Zoomable};
+use crate::utils::{self, file_size, set_svg_attribute, svg_attribute};
 
-#[derive(Default, Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Default, Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
 pub enum ImageFormat {
     #[default]
     Svg,
     Png,
+    /// previewed as the svg it's compiled alongside, since iced can't show pdfs
+    Pdf,
 }
 
 impl ImageFormat {
-    pub const ALL: [Self; 2] = [
+    pub const ALL: [Self; 3] = [
         Self::Svg,
         Self::Png,
+        Self::Pdf,
     ];
 
     pub const fn default_file_name(self) -> &'static str {
         match self {
             Self::Svg => "eq.svg",
             Self::Png => "eq.png",
+            Self::Pdf => "eq.pdf",
         }
     }
 }
@@ -47,27 +72,171 @@ impl Display for ImageFormat {
         f.write_str(match self {
             Self::Svg => "svg",
             Self::Png => "png",
+            Self::Pdf => "pdf",
         })
     }
 }
 
 #[derive(Clone, Debug)]
 pub enum Message {
-    FontLoaded,
+    FontLoaded(Result<(), font::Error>),
+    LatexVersions(String),
+    TypstVersion(String),
     EditEquation(String),
+    EditorAction(text_editor::Action),
+    ModifiersChanged(keyboard::Modifiers),
+    InsertTemplate(Template),
     Name(String),
     Color(String),
+    ToggleColorPicker,
+    /// a channel (r, g, b, or a) of the picker, and its new value
+    PickColor(usize, u8),
+    PickSwatch(usize),
+    AddSwatch,
+    RemoveSwatch,
     Compile,
+    /// a compile the user asked for, which is recorded in the recent equations and the history
+    Render,
     SvgGenerated(Result<(), GuiError>),
     PngGenerated(Result<(), GuiError>),
+    PostProcessed(Result<(), GuiError>),
+    SecondaryGenerated(Result<(), GuiError>),
     FocusNext,
     FocusPrevious,
     Format(ImageFormat),
     SetDpi(String),
+    SetScale(String),
+    SetAlignment(LineAlignment),
+    SetExactBbox(bool),
+    SetTypstFont(MathFont),
+    SetTypstFontSize(String),
     OutDir(String),
+    AddOutDir,
+    EditOutDir(usize, String),
+    RemoveOutDir(usize),
+    SetPinOutDir(bool),
     OpenExplorer,
+    OpenLastExport,
+    /// show the output directory in the file manager
+    RevealOutput,
+    ShareLastExport,
+    SetShareMethod(ShareMethod),
+    Opened(Result<(), GuiError>),
     PickedDir(Option<PathBuf>),
+    OpenSaveAs,
+    /// the file picked in the save dialog, if one was
+    SaveAs(Option<PathBuf>),
     SetBackend(Backend),
+    SwapBackend,
+    /// move the equation to the other backend, since it looks like it's written for it
+    SwitchSyntax(Backend),
+    SetInvert(bool),
+    SetStripProlog(bool),
+    SetSvgSize(SvgSize),
+    SetDepth(PngDepth),
+    SetProfile(ColorProfile),
+    SetIccPath(String),
+    SetAspect(String),
+    SetCrop(bool),
+    SetCropPadding(String),
+    SetCanvasSize(String),
+    SetCanvasOffset(String),
+    SetShadow(bool),
+    SetShadowOffset(String),
+    SetShadowBlur(String),
+    SetShadowColor(String),
+    SetBackground(String),
+    SetCurrentColor(bool),
+    SetSecondaryPng(bool),
+    SetSvgFonts(SvgFonts),
+    ToggleSettings,
+    CacheSize(Option<u64>),
+    SetCacheBudget(String),
+    ClearCache,
+    ConfirmClearCache(MessageDialogResult),
+    CacheCleared(Result<(), GuiError>),
+    ToggleAnimation,
+    EditFrame(usize, String),
+    AddFrame,
+    RemoveFrame(usize),
+    SetFrameDuration(String),
+    SetAnimationFormat(AnimationFormat),
+    ExportAnimation,
+    AnimationExported(PathBuf, Result<u64, GuiError>),
+    ToggleShortcuts,
+    HideShortcuts,
+    SetExtraArgs(Tool, String),
+    SetPrefill(bool),
+    SetShowCommand(bool),
+    SetSafeMode(bool),
+    SetHighContrast(bool),
+    SetTheme(ThemeMode),
+    /// whether the OS is in dark mode
+    SystemTheme(bool),
+    SetScratchDir(String),
+    SetRoundNumbers(bool),
+    SetSigFigs(String),
+    SetMaxPngSize(String),
+    SetMaxWorkers(String),
+    WorkersSet,
+    SetKeepLastRender(bool),
+    SetCopySvgMarkup(bool),
+    SetLatexLive(bool),
+    SetLatexIdle(String),
+    /// checks whether editing has paused long enough to compile, see [`Gui::edited_at`]
+    EditIdle,
+    SetDimWhileCompiling(bool),
+    ShowStaleError,
+    EditCommand(String),
+    RerunCommand,
+    CommandRerun(Result<String, CommandError>),
+    SetPairColor(Variant, String),
+    SetPairTemplate(String),
+    ExportThemePair,
+    ThemePairExported(Result<Vec<PathBuf>, GuiError>),
+    LoadRecent(usize),
+    ToggleHistory,
+    LoadHistory(usize),
+    SetSheetColumns(String),
+    SetSheetPadding(String),
+    SetSheetLabels(bool),
+    ExportContactSheet,
+    ContactSheetExported(PathBuf, Result<u64, GuiError>),
+    SetPdfCaptions(bool),
+    SetPdfDarkPages(bool),
+    ExportPdf,
+    CopyBothSyntaxes,
+    CopyImage,
+    CompileAndCopy,
+    HideToast,
+    /// redraws the fading notification, and hides it once it's been shown for long enough
+    NotificationExpire,
+    CopyHtml,
+    SetIncludeWidth(String),
+    CopyInclude,
+    CopyTikz,
+    SetHtmlStyle(HtmlStyle),
+    SetAlignBaseline(bool),
+    ImageCopied(Result<(), GuiError>),
+    CloseRequested,
+    SpinnerDelay,
+    ToggleErrorDetails,
+    /// the factor to zoom the preview by, around the cursor's position in it
+    Zoom(f32, Point),
+    Pan(Vector),
+    ResetZoom,
+    TogglePreamble,
+    /// a `.tex` or `.typ` file was dropped on the window
+    FileDropped(PathBuf),
+    FileLoaded(Backend, Result<String, GuiError>),
+    PreambleAction(text_editor::Action),
+    ResetPreamble,
+    /// stops the running compile and goes back to what was shown before it
+    CancelCompile,
+    DismissMissingTools,
+    /// checks whether the settings changed, see [`Gui::save_settings`]
+    SaveSettings,
+    ConfirmQuit(MessageDialogResult),
 }
 
 pub type Dir = PathBuf;
@@ -75,7 +244,8 @@ pub type Dir = PathBuf;
 #[derive(Debug, Clone)]
 pub enum State {
     Compiling {
-        previous: Box<State>
+        previous: Box<Self>,
+        since: Instant,
     },
     Svg(Dir),
     Png(Dir),
@@ -89,10 +259,19 @@ impl Default for State {
 }
 
 impl State {
-    fn content(&self, compiled_color: &str, backend: Backend) -> Container<'_, Message> {
+    /// With `dim_while_compiling`, slow compiles show the last image dimmed instead of the spinner.
+    /// With `show_details`, errors show everything the compiler printed under the error. Images are
+    /// zoomed into as in `view`.
+    fn content(&self, stem: &str, dim_while_compiling: bool, show_details: bool, view: zoom::View) -> Container<'_, Message> {
         match self {
-            // typst renders fast enough that we don't show the loading spinner
-            State::Compiling { .. } if backend == Backend::LaTeX => {
+            // keeps the layout from jumping while the new image renders
+            Self::Compiling { since, previous } if dim_while_compiling
+                && since.elapsed() >= SPINNER_DELAY
+                && matches!(**previous, Self::Svg(_) | Self::Png(_)) => {
+                previous.image(stem, true, view)
+            }
+            // most compiles finish quickly enough that flashing the spinner would just be noise
+            Self::Compiling { since, .. } if since.elapsed() >= SPINNER_DELAY => {
                 let spinner = Circular::new()
                     .size(200.0)
                     .bar_height(20.0)
@@ -100,64 +279,481 @@ impl State {
                     .cycle_duration(Duration::from_secs_f32(2.0));
                 container(spinner)
             }
-            State::Compiling { previous } => {
-                previous.content(compiled_color, backend)
+            Self::Compiling { previous, .. } => {
+                previous.content(stem, dim_while_compiling, show_details, view)
+            }
+            Self::Svg(_) | Self::Png(_) => self.image(stem, false, view),
+            Self::Errored(e) => {
+                let error = scrollable(text(e).size(40));
+                match e.details() {
+                    Some(details) => {
+                        let toggle = button(text(if show_details { "Hide details" } else { "Show details" }).size(14))
+                            .style(iced::theme::Button::Secondary)
+                            .on_press(Message::ToggleErrorDetails);
+                        let details = if show_details {
+                            container(scrollable(
+                                text(details)
+                                    .size(12)
+                                    .font(Font::MONOSPACE)
+                            )).padding(8)
+                                .style(iced::theme::Container::Box)
+                        } else {
+                            container(text(""))
+                        };
+                        container(col![error, toggle, details]
+                            .spacing(8)
+                            .align_items(Alignment::Center))
+                    }
+                    None => container(error),
+                }
             }
-            State::Svg(dir) => {
+        }.align_x(Horizontal::Center)
+            .align_y(Vertical::Center)
+            .height(Fill)
+            .width(Fill)
+    }
+
+    /// The rendered image, at half opacity if `dimmed`
+    fn image(&self, stem: &str, dimmed: bool, view: zoom::View) -> Container<'_, Message> {
+        match self {
+            Self::Svg(dir) => {
                 // have to read the svg manually because otherwise it won't update the image
                 //  if the same path is used
                 // println!("dir = {:?}", dir);
                 let file_name = format!(
-                    "{}_eq.svg",
-                    compiled_color,
+                    "{stem}_eq.svg",
                 );
-                let data = fs::read(dir.join(file_name)).unwrap();
-                let svg = svg::<Theme>(Handle::from_memory(data))
+                let mut data = fs::read(dir.join(file_name)).unwrap();
+                if dimmed {
+                    data = dim_svg(&data);
+                }
+                let mut svg = svg::<Theme>(Handle::from_memory(data))
                     .height(Fill)
                     .content_fit(ContentFit::Contain);
-                container(svg)
+                if stem.starts_with(CURRENT_COLOR) {
+                    // `currentColor` would otherwise render as black
+                    svg = svg.style(iced::theme::Svg::custom_fn(|theme| svg::Appearance {
+                        color: Some(theme.palette().text),
+                    }));
+                }
+                container(Zoomable::new(svg, view, Message::Zoom, Message::Pan))
                     .padding(8)
             }
-            State::Png(dir) => {
+            Self::Png(dir) => {
                 // have to read the png manually because otherwise it won't update the image
                 //  if the same path is used
                 let file_name = format!(
-                    "{}_eq.png",
-                    compiled_color,
+                    "{stem}_eq.png",
                 );
                 let data = fs::read(dir.join(file_name)).unwrap();
-                let png = image(image::Handle::from_memory(data))
+                let handle = if dimmed {
+                    dim_png(&data).unwrap_or_else(|| image::Handle::from_memory(data))
+                } else {
+                    image::Handle::from_memory(data)
+                };
+                let png = image(handle)
                     .height(Fill)
                     .content_fit(ContentFit::Contain);
-                container(png)
+                container(Zoomable::new(png, view, Message::Zoom, Message::Pan))
                     .padding(8)
             }
-            State::Errored(e) => container(scrollable(
-                text(e).size(40)
-            )),
-        }.align_x(Horizontal::Center)
-            .align_y(Vertical::Center)
-            .height(Fill)
-            .width(Fill)
+            Self::Compiling { .. } | Self::Errored(_) => container(text("")),
+        }
+    }
+}
+
+/// `svg` at half opacity
+fn dim_svg(svg: &[u8]) -> Vec<u8> {
+    let svg = String::from_utf8_lossy(svg);
+    let start = svg.find("<svg").unwrap_or_default();
+    let tag_end = svg[start..].find('>').map_or(svg.len(), |end| start + end + 1);
+    let root = set_svg_attribute(&svg[start..tag_end], "opacity", "0.5");
+    format!("{}{root}{}", &svg[..start], &svg[tag_end..]).into_bytes()
+}
+
+/// `png` at half opacity, if it can be decoded
+fn dim_png(png: &[u8]) -> Option<image::Handle> {
+    let mut png = ::image::load_from_memory(png).ok()?.into_rgba8();
+    for pixel in png.pixels_mut() {
+        pixel[3] /= 2;
+    }
+    Some(image::Handle::from_pixels(png.width(), png.height(), png.into_raw()))
+}
+
+/// An equation that compiled successfully this session, shown in the quick-bar under the editor
+#[derive(Debug, Clone)]
+pub struct Recent {
+    eq: String,
+    backend: Backend,
+    color: Option<String>,
+}
+
+impl Recent {
+    /// How many recent equations are kept
+    const CAPACITY: usize = 5;
+    /// Chips longer than this are truncated with an ellipsis
+    const LABEL_LEN: usize = 20;
+
+    fn label(&self) -> String {
+        if self.eq.chars().count() > Self::LABEL_LEN {
+            let eq: String = self.eq.chars().take(Self::LABEL_LEN - 1).collect();
+            format!("{eq}…")
+        } else {
+            self.eq.clone()
+        }
+    }
+}
+
+/// A color in the quick palette next to the color field
+#[derive(Debug, Clone)]
+pub struct Swatch {
+    color: String,
+    /// what the swatch is filled with, if `color` is a css color. Otherwise its name is shown
+    fill: Option<Color>,
+}
+
+impl Swatch {
+    fn new(color: String) -> Self {
+        let fill = color::parse_color(&color)
+            .map(ParsedColor::iced);
+        Self { color, fill }
     }
 }
 
+/// A file written by [`Gui::copy_to_dest`] or one of the other exports
+#[derive(Debug, Clone)]
+pub struct Export {
+    path: PathBuf,
+    size: u64,
+}
+
+// each bool is a separate toggle in the window, not a state machine in disguise
+#[allow(clippy::struct_excessive_bools)]
 pub struct Gui {
     latex_eq: String,
     typst_eq: String,
+    /// what's in the editor, which is whichever of `latex_eq` or `typst_eq` is being used, see
+    /// [`Gui::sync_editor`]
+    editor: text_editor::Content,
+    /// what goes between `\documentclass` and `\begin{document}`, see [`latex::gen_svg`]
+    preamble: text_editor::Content,
+    /// what's imported before the equation, see [`typst::gen_svg`]
+    typst_imports: text_editor::Content,
+    /// of whichever backend is being used
+    show_preamble: bool,
+    /// held down right now, to tell Enter from Ctrl+Enter in the editor
+    modifiers: keyboard::Modifiers,
     name: Option<String>,
     color: Option<String>,
     compiled_color: String,
+    /// render svgs with `fill="currentColor"` so their color can be set with css. the preview uses
+    ///  the theme's text color for these
+    current_color: bool,
+    post: PostProcess,
+    compiled_post: PostProcess,
     format: ImageFormat,
+    /// when exporting an svg, also export a png rasterized from it, see [`Gui::copy_secondary`]
+    secondary_png: bool,
     dpi: usize,
+    /// crop pngs to the equation, with this many pixels of padding as typed, see `post.crop`
+    crop: bool,
+    crop_padding: String,
+    /// as typed, eg `16:9`, parsed into `post.aspect`
+    aspect: String,
+    /// as typed, eg `800x600` and `10,20`, parsed into `post.canvas`
+    canvas_size: String,
+    canvas_offset: String,
+    /// draw a drop shadow under the equation, see [`Shadow`]
+    shadow: bool,
+    shadow_offset: String,
+    shadow_blur: String,
+    shadow_color: String,
+    /// as typed, see [`Gui::scale`]
+    scale: String,
+    alignment: LineAlignment,
+    /// see [`Layout::exact_bbox`]
+    exact_bbox: bool,
+    typst_font: MathFont,
+    /// as typed, see [`Gui::font_size`]
+    typst_font_size: String,
     out_dir: PathBuf,
+    /// `out_dir` can't be changed by typing or the folder picker
+    pin_out_dir: bool,
+    /// more directories that each export is also copied to, as typed
+    extra_out_dirs: Vec<String>,
     state: State,
     folder_icon: Icon,
+    /// of [`latex::TOOLS`], once they've been checked
+    latex_versions: Option<String>,
+    /// the folder picker or save dialog is open, so another one shouldn't be opened
+    picking_dir: bool,
+    /// the icon font couldn't be loaded, so icon buttons use text labels instead
+    icon_font_failed: bool,
+    /// of typst, once it's been checked
+    typst_version: Option<String>,
     backend: Backend,
+    /// where Typst compiles if its directory in the cache can't be created, see [`Gui::cache_dir`]
     typst_dir: TempDir,
+    recent: VecDeque<Recent>,
+    /// every backend's last compiled equations, kept between sessions
+    history: History,
+    saved_history: History,
+    show_history: bool,
+    /// the red, green, blue, and alpha of the color picker, if it's open
+    color_picker: Option<[u8; 4]>,
+    /// colors that can be picked with one click
+    swatches: Vec<Swatch>,
+    /// every equation exported this session, for the contact sheet
+    thumbnails: Vec<Thumbnail>,
+    /// where `thumbnails` are copied to
+    session_dir: TempDir,
+    contact_sheet: ContactSheet,
+    pdf: PdfOptions,
+    extra_args: ExtraArgs,
+    show_settings: bool,
+    /// of everything in [`CACHE_DIR`], measured when the settings are opened
+    cache_size: Option<u64>,
+    clearing_cache: bool,
+    /// in megabytes, the least recently used equations are deleted once the cache is bigger. 0 is
+    /// unlimited
+    cache_budget_mb: u64,
+    animation: Animation,
+    show_animation: bool,
+    show_shortcuts: bool,
+    /// show everything the compiler printed with errors, not just the error
+    show_error_details: bool,
+    /// how far the preview is zoomed in, see [`Zoomable`]
+    zoom: zoom::View,
+    /// start with [`Backend::example`] in the editor
+    prefill: bool,
+    /// a compile is running, so new ones have to wait for it to finish, see [`Gui::compile_done`]
+    compiling: bool,
+    /// another compile was requested while `compiling`, or before the backend's version was known
+    compile_pending: bool,
+    /// the running compile was cancelled, so its result is ignored
+    cancelled: bool,
+    /// that the backend and format need, shown in a banner until dismissed, see
+    /// [`Gui::check_tools`]
+    missing_tools: Vec<Tool>,
+    /// on errors, keep showing the last image that rendered, see [`Gui::compile_failed`]
+    keep_last_render: bool,
+    /// copy svgs to the clipboard as their markup, instead of as a png
+    copy_svg_markup: bool,
+    /// compile LaTeX once editing pauses for `latex_idle`, like Typst does after [`TYPST_DEBOUNCE`]
+    latex_live: bool,
+    latex_idle: Duration,
+    /// the last edit that hasn't been compiled yet, by Typst or `latex_live`
+    edited_at: Option<Instant>,
+    /// show the last image dimmed during slow compiles, instead of the spinner
+    dim_while_compiling: bool,
+    /// the error hidden behind the last image by `keep_last_render`
+    stale_error: Option<GuiError>,
+    /// `compiled_color` and `compiled_post` of the last image that rendered
+    last_render: Option<(String, PostProcess)>,
+    /// the first render is of the prefilled example, which shouldn't be written to `out_dir`
+    rendering_example: bool,
+    /// set while rendering the prefilled example
+    preview_only: bool,
+    /// the user rendered or saved, but that compile hasn't started yet. See [`Gui::record_compile`]
+    record_requested: bool,
+    /// the running compile was asked for by the user, so it's added to the recent equations, the
+    ///  history and the contact sheet once it's exported. Live previews would fill them with partial
+    ///  equations
+    record_compile: bool,
+    /// likely syntax error in the equation, from [`check::check`]
+    warning: Option<String>,
+    /// the files written by the most recent export
+    last_exports: Vec<Export>,
+    /// copies of the last export that couldn't be written, one per directory
+    failed_exports: Vec<GuiError>,
+    /// copy the image to the clipboard once the current compile succeeds
+    copy_after_compile: bool,
+    /// a short confirmation shown in the status row for [`TOAST_DURATION`]
+    toast: Option<&'static str>,
+    /// shown over the preview after exporting, see [`NOTIFICATION_DURATION`]
+    notification: Option<(String, Instant)>,
+    theme_pair: ThemePair,
+    html_style: HtmlStyle,
+    /// whether LaTeX's text is kept as text, in subsetted woff2 fonts
+    svg_fonts: SvgFonts,
+    share_method: ShareMethod,
+    /// add the baseline to html snippets, so they line up with the text around them
+    align_baseline: bool,
+    /// of the rendered svg, see [`baseline::depth`]
+    baseline: Option<f64>,
+    /// width in the `\includegraphics` snippet, eg `0.5\linewidth`. the image's own width if empty
+    include_width: String,
+    /// round long decimals before rendering, see [`rounding::round_numbers`]
+    round_numbers: bool,
+    sig_figs: u32,
+    /// see [`backends::set_max_png_size`]
+    max_png_size: u32,
+    /// see [`backends::set_max_workers`]
+    max_workers: usize,
+    /// as typed, see [`Gui::scratch_dir`]
+    scratch_dir: String,
+    /// use [`style::HIGH_CONTRAST`] for the app's own controls
+    high_contrast: bool,
+    theme_mode: ThemeMode,
+    /// the OS's theme, for [`ThemeMode::System`]
+    system_dark: bool,
+    /// see [`backends::safe_mode`]
+    safe_mode: bool,
+    /// debugging panel showing [`backends::last_command`]
+    show_command: bool,
+    last_command: Option<RanCommand>,
+    /// the last command as edited in the debugging panel
+    command_line: String,
+    /// output of the edited command, or its error
+    rerun_output: Option<String>,
+    /// there are edits that haven't been exported yet
+    dirty: bool,
+    quit_after_export: bool,
+    /// what's in the settings file, see [`Gui::save_settings`]
+    saved_settings: Settings,
 }
 
 impl Gui {
+    /// The window as `settings` left it. Doesn't load anything or set the global settings, which
+    /// [`Application::new`] does
+    fn from_settings(settings: Settings, history: History) -> Self {
+        let prefill = settings.prefill;
+        let shadow = settings.shadow
+            .then(|| Shadow::parse(&settings.shadow_offset, &settings.shadow_blur, &settings.shadow_color))
+            .flatten();
+        let post = PostProcess {
+            invert: settings.invert,
+            depth: settings.png_depth,
+            profile: settings.color_profile,
+            icc_path: settings.icc_path.clone(),
+            crop: settings.crop
+                .then(|| parse_padding(&settings.crop_padding))
+                .flatten(),
+            aspect: parse_aspect(&settings.aspect),
+            canvas: Canvas::parse(&settings.canvas_size, &settings.canvas_offset),
+            shadow,
+            background: settings.background.clone(),
+            size: settings.svg_size,
+            strip_prolog: settings.strip_prolog,
+        };
+        let eq = |backend: Backend| if prefill {
+            backend.example().to_string()
+        } else {
+            String::new()
+        };
+        Self {
+            latex_eq: eq(Backend::LaTeX),
+            typst_eq: eq(Backend::Typst),
+            editor: text_editor::Content::new(),
+            preamble: text_editor::Content::with_text(&settings.latex_preamble),
+            typst_imports: text_editor::Content::with_text(&settings.typst_imports),
+            show_preamble: false,
+            modifiers: keyboard::Modifiers::empty(),
+            name: None,
+            color: settings.color.clone(),
+            compiled_color: DEFAULT_COLOR.to_string(),
+            current_color: settings.current_color,
+            post,
+            compiled_post: PostProcess::default(),
+            format: settings.format,
+            secondary_png: settings.secondary_png,
+            dpi: settings.dpi,
+            scale: settings.scale.clone(),
+            exact_bbox: settings.exact_bbox,
+            typst_font: settings.typst_font,
+            typst_font_size: settings.typst_font_size.clone(),
+            alignment: settings.alignment,
+            crop: settings.crop,
+            crop_padding: settings.crop_padding.clone(),
+            aspect: settings.aspect.clone(),
+            canvas_size: settings.canvas_size.clone(),
+            canvas_offset: settings.canvas_offset.clone(),
+            shadow: settings.shadow,
+            shadow_offset: settings.shadow_offset.clone(),
+            shadow_blur: settings.shadow_blur.clone(),
+            shadow_color: settings.shadow_color.clone(),
+            out_dir: settings.out_dir.clone()
+                .filter(|dir| dir.is_dir())
+                .unwrap_or_else(|| env::current_dir().unwrap()),
+            pin_out_dir: settings.pin_out_dir,
+            extra_out_dirs: settings.extra_out_dirs.clone(),
+            state: Default::default(),
+            folder_icon: Icon::Folder2,
+            picking_dir: false,
+            icon_font_failed: false,
+            backend: settings.backend,
+            typst_dir: TempDir::new("typst_").unwrap(),
+            recent: VecDeque::with_capacity(Recent::CAPACITY),
+            history: history.clone(),
+            saved_history: history,
+            show_history: false,
+            color_picker: None,
+            swatches: settings.swatches.iter().cloned().map(Swatch::new).collect(),
+            thumbnails: Vec::new(),
+            session_dir: TempDir::new("session_").unwrap(),
+            contact_sheet: settings.contact_sheet.clone(),
+            pdf: settings.pdf.clone(),
+            extra_args: settings.extra_args.clone(),
+            show_settings: false,
+            cache_size: None,
+            clearing_cache: false,
+            cache_budget_mb: settings.cache_budget_mb,
+            animation: Animation::default(),
+            show_animation: false,
+            show_shortcuts: false,
+            show_error_details: false,
+            zoom: zoom::View::default(),
+            prefill,
+            compiling: false,
+            keep_last_render: settings.keep_last_render,
+            copy_svg_markup: settings.copy_svg_markup,
+            latex_live: settings.latex_live,
+            latex_idle: Duration::from_millis(settings.latex_idle_ms),
+            edited_at: None,
+            dim_while_compiling: settings.dim_while_compiling,
+            stale_error: None,
+            last_render: None,
+            compile_pending: false,
+            cancelled: false,
+            missing_tools: Vec::new(),
+            rendering_example: prefill,
+            preview_only: false,
+            record_requested: false,
+            record_compile: false,
+            warning: None,
+            last_exports: Vec::new(),
+            failed_exports: Vec::new(),
+            copy_after_compile: false,
+            toast: None,
+            notification: None,
+            theme_pair: settings.theme_pair.clone(),
+            html_style: settings.html_style,
+            svg_fonts: settings.svg_fonts,
+            share_method: settings.share_method,
+            align_baseline: settings.align_baseline,
+            baseline: None,
+            include_width: settings.include_width.clone(),
+            round_numbers: settings.round_numbers,
+            sig_figs: settings.sig_figs,
+            max_png_size: settings.max_png_size,
+            max_workers: settings.max_workers,
+            scratch_dir: settings.scratch_dir.clone(),
+            high_contrast: settings.high_contrast,
+            theme_mode: settings.theme_mode,
+            system_dark: true,
+            safe_mode: settings.safe_mode,
+            show_command: false,
+            last_command: None,
+            command_line: String::new(),
+            rerun_output: None,
+            dirty: false,
+            quit_after_export: false,
+            latex_versions: None,
+            typst_version: None,
+            saved_settings: settings,
+        }
+    }
+
     fn eq(&self) -> &str {
         match self.backend {
             Backend::LaTeX => &self.latex_eq,
@@ -165,140 +761,1020 @@ impl Gui {
         }
     }
 
-    fn eq_mut(&mut self) -> &mut String {
+    const fn eq_mut(&mut self) -> &mut String {
         match self.backend {
             Backend::LaTeX => &mut self.latex_eq,
             Backend::Typst => &mut self.typst_eq,
         }
     }
 
+    /// Shows [`Gui::eq`] in the editor, after it was changed by something other than typing
+    fn sync_editor(&mut self) {
+        self.editor = text_editor::Content::with_text(self.eq());
+    }
+
+    /// Looks for the tools the current backend and format need, so the ones that are missing can
+    /// be shown before compiling fails
+    fn check_tools(&mut self) {
+        self.missing_tools = Tool::missing(self.backend, self.format);
+    }
+
+    /// `eq` as it is actually rendered
+    fn rendered(&self, eq: &str) -> String {
+        if self.round_numbers {
+            rounding::round_numbers(eq, self.sig_figs)
+        } else {
+            eq.to_string()
+        }
+    }
+
+    /// Identifies everything that went into rendering the image, for its directory in the cache,
+    /// see [`get_dir`]. The color and format aren't included, since they're in the names of the
+    /// files in that directory and a compiled equation can be recolored without compiling again.
     fn equation_hash(&self) -> u64 {
         let mut hash = DefaultHasher::default();
-        self.latex_eq.hash(&mut hash);
+        self.backend.hash(&mut hash);
+        self.rendered(self.eq()).hash(&mut hash);
+        self.alignment.hash(&mut hash);
+        self.layout().scale.to_bits().hash(&mut hash);
+        match self.backend {
+            Backend::LaTeX => {
+                self.extra_args.latex.hash(&mut hash);
+                self.extra_args.dvisvgm.hash(&mut hash);
+                self.exact_bbox.hash(&mut hash);
+                self.preamble().hash(&mut hash);
+                self.svg_fonts().hash(&mut hash);
+                // so that upgrading latex or dvisvgm renders everything again
+                self.latex_versions.hash(&mut hash);
+            }
+            Backend::Typst => {
+                self.extra_args.typst.hash(&mut hash);
+                self.typst_font.hash(&mut hash);
+                self.font_size().to_bits().hash(&mut hash);
+                self.preamble().hash(&mut hash);
+                self.typst_version.hash(&mut hash);
+            }
+        }
         hash.finish()
     }
 
+    /// LaTeX's preamble or Typst's imports, whichever backend is being used
+    fn preamble(&self) -> String {
+        match self.backend {
+            Backend::LaTeX => self.preamble.text(),
+            Backend::Typst => self.typst_imports.text(),
+        }
+    }
+
+    /// Whether LaTeX's preamble is well formed. Typst's imports are just more Typst before the
+    /// equation, so there's nothing to check about how they're laid out
+    fn check_preamble(&self) -> Result<(), GuiError> {
+        match self.backend {
+            Backend::LaTeX => latex::check_preamble(&self.preamble()),
+            Backend::Typst => Ok(()),
+        }
+    }
+
     fn color(&self) -> &str {
-        self.color.as_deref().unwrap_or(DEFAULT_COLOR)
+        self.color.as_deref().unwrap_or_else(|| self.default_color())
+    }
+
+    /// Whatever shows up on the window's background, which light documents usually match too
+    const fn default_color(&self) -> &'static str {
+        if self.theme_mode.is_dark(self.system_dark) {
+            DEFAULT_COLOR
+        } else {
+            "black"
+        }
+    }
+
+    /// How much bigger to make the image, whichever backend and format is used
+    fn scale(&self) -> f64 {
+        self.scale.parse()
+            .ok()
+            .filter(|&scale| scale > 0.0)
+            .unwrap_or(1.0)
+    }
+
+    /// Pngs are scaled by their density instead, see [`Gui::density`]
+    fn layout(&self) -> Layout {
+        Layout {
+            alignment: self.alignment,
+            scale: match self.format {
+                ImageFormat::Svg | ImageFormat::Pdf => self.scale(),
+                ImageFormat::Png => 1.0,
+            },
+            font: self.typst_font,
+            font_size: self.font_size(),
+            exact_bbox: self.exact_bbox,
+        }
+    }
+
+    /// Typst's text size in points
+    fn font_size(&self) -> f64 {
+        self.typst_font_size.parse()
+            .ok()
+            .filter(|&size| size > 0.0)
+            .unwrap_or(typst::DEFAULT_FONT_SIZE)
+    }
+
+    /// The dpi pngs are rendered at, including [`Gui::scale`]
+    fn density(&self) -> usize {
+        (self.dpi as f64 * self.scale()).round() as usize
+    }
+
+    /// The color the image is actually rendered in, see [`Gui::current_color`]
+    fn render_color(&self) -> &str {
+        if self.current_color && self.format == ImageFormat::Svg {
+            CURRENT_COLOR
+        } else {
+            self.color()
+        }
+    }
+
+    /// Where LaTeX does its intermediate work, before just the svg is copied into the cache. The
+    /// system's temporary directory by default
+    fn scratch_dir(&self) -> PathBuf {
+        if self.scratch_dir.trim().is_empty() {
+            env::temp_dir()
+        } else {
+            PathBuf::from(self.scratch_dir.trim())
+        }
     }
 
     fn cache_dir(&self) -> Dir {
         match self.backend {
             Backend::LaTeX => get_dir(self.equation_hash()),
-            Backend::Typst => self.typst_dir.path().to_owned(),
+            Backend::Typst => {
+                // it's created when compiling, see `Message::Compile`
+                let dir = get_typst_dir(self.equation_hash());
+                if dir.is_dir() {
+                    dir
+                } else {
+                    self.typst_dir.path().to_owned()
+                }
+            }
         }
     }
 
-    fn copy_to_dest(&self) -> io::Result<()> {
-        let dir = self.cache_dir();
-        let from_name = format!(
-            "{}_eq.{}",
-            self.compiled_color,
-            self.format,
-        );
-        let to_name = self.name
+    /// Records the current equation at the front of the recently used list and in the history. Only
+    /// compiles the user asked for are recorded, see [`Gui::record_compile`]
+    fn push_recent(&mut self) {
+        let recent = Recent {
+            eq: self.eq().to_string(),
+            backend: self.backend,
+            color: self.color.clone(),
+        };
+        self.recent.retain(|r| r.backend != recent.backend || r.eq != recent.eq);
+        self.recent.push_front(recent);
+        self.recent.truncate(Recent::CAPACITY);
+        self.history.push(history::Entry {
+            eq: self.eq().to_string(),
+            backend: self.backend,
+            color: self.color.clone(),
+            format: self.format,
+        });
+    }
+
+    /// The current color's red, green, blue, and alpha, if it's simple enough to tell
+    fn color_rgba(&self) -> Option<[u8; 4]> {
+        self.parsed_color().map(|parsed| parsed.0)
+    }
+
+    /// The color field as a css color, if it is one. Typst's colors are converted first, see
+    /// [`typst::css_color`]
+    fn parsed_color(&self) -> Option<ParsedColor> {
+        match self.backend {
+            Backend::LaTeX => color::parse_color(self.color()),
+            Backend::Typst => color::parse_color(&typst::css_color(self.color())),
+        }
+    }
+
+    /// Whether the color field can be rendered. The color is ignored for `current_color` svgs. Typst
+    /// takes any expression, eg `luma(50%)` or `red.darken(20%)`, so only Typst can tell whether it's
+    /// a color and it reports the error itself
+    fn color_is_valid(&self) -> bool {
+        self.renders_color(self.render_color())
+    }
+
+    /// Whether `color` can be rendered by the current backend, see [`Gui::color_is_valid`]
+    fn renders_color(&self, color: &str) -> bool {
+        color == CURRENT_COLOR
+            || self.backend == Backend::Typst && !color.trim().is_empty()
+            || color::parse_color(color).is_some()
+    }
+
+    /// Whether both of the light & dark colors can be rendered, see [`Gui::color_is_valid`]
+    fn check_pair_colors(&self) -> Result<(), GuiError> {
+        Variant::ALL.into_iter()
+            .map(|variant| self.theme_pair.get(variant))
+            .find(|color| !self.renders_color(color))
+            .map_or(Ok(()), |color| Err(GuiError::InvalidColor(color.to_string())))
+    }
+
+    /// Whether the color fields can be rendered, see [`Gui::color_is_valid`]
+    fn check_color(&self) -> Result<(), GuiError> {
+        if !self.color_is_valid() {
+            return Err(GuiError::InvalidColor(self.color().to_string()));
+        }
+        match &self.post.background {
+            Some(background) if color::parse_color(background).is_none() => Err(GuiError::InvalidColor(background.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// The settings to keep for next time
+    fn settings(&self) -> Settings {
+        Settings {
+            backend: self.backend,
+            format: self.format,
+            dpi: self.dpi,
+            color: self.color.clone(),
+            out_dir: Some(self.out_dir.clone()),
+            pin_out_dir: self.pin_out_dir,
+            extra_out_dirs: self.extra_out_dirs.clone(),
+            current_color: self.current_color,
+            secondary_png: self.secondary_png,
+            scale: self.scale.clone(),
+            alignment: self.alignment,
+            exact_bbox: self.exact_bbox,
+            typst_font: self.typst_font,
+            typst_font_size: self.typst_font_size.clone(),
+            crop: self.crop,
+            crop_padding: self.crop_padding.clone(),
+            aspect: self.aspect.clone(),
+            canvas_size: self.canvas_size.clone(),
+            canvas_offset: self.canvas_offset.clone(),
+            shadow: self.shadow,
+            shadow_offset: self.shadow_offset.clone(),
+            shadow_blur: self.shadow_blur.clone(),
+            shadow_color: self.shadow_color.clone(),
+            invert: self.post.invert,
+            background: self.post.background.clone(),
+            svg_size: self.post.size,
+            strip_prolog: self.post.strip_prolog,
+            png_depth: self.post.depth,
+            color_profile: self.post.profile,
+            icc_path: self.post.icc_path.clone(),
+            swatches: self.swatches.iter().map(|swatch| swatch.color.clone()).collect(),
+            contact_sheet: self.contact_sheet.clone(),
+            pdf: self.pdf.clone(),
+            extra_args: self.extra_args.clone(),
+            latex_preamble: self.preamble.text(),
+            typst_imports: self.typst_imports.text(),
+            prefill: self.prefill,
+            keep_last_render: self.keep_last_render,
+            copy_svg_markup: self.copy_svg_markup,
+            latex_live: self.latex_live,
+            latex_idle_ms: self.latex_idle.as_millis() as u64,
+            dim_while_compiling: self.dim_while_compiling,
+            theme_pair: self.theme_pair.clone(),
+            html_style: self.html_style,
+            svg_fonts: self.svg_fonts,
+            share_method: self.share_method,
+            align_baseline: self.align_baseline,
+            include_width: self.include_width.clone(),
+            round_numbers: self.round_numbers,
+            sig_figs: self.sig_figs,
+            max_png_size: self.max_png_size,
+            cache_budget_mb: self.cache_budget_mb,
+            max_workers: self.max_workers,
+            scratch_dir: self.scratch_dir.clone(),
+            high_contrast: self.high_contrast,
+            theme_mode: self.theme_mode,
+            safe_mode: self.safe_mode,
+        }
+    }
+
+    /// Writes the settings and history files if they have changed since they were last written
+    fn save_settings(&mut self) {
+        let current = self.settings();
+        if current != self.saved_settings {
+            settings::save(&current);
+            self.saved_settings = current;
+        }
+        if self.history != self.saved_history {
+            history::save(&self.history);
+            self.saved_history = self.history.clone();
+        }
+    }
+
+    /// Keeps a copy of the compiled image for the contact sheet. Like the recent list, only compiles
+    /// the user asked for are kept, see [`Gui::record_compile`]
+    fn push_thumbnail(&mut self) {
+        let label = self.eq().to_string();
+        let preview = self.preview_path();
+        let extension = preview.extension().unwrap_or_default().to_string_lossy();
+        let path = self.session_dir.path().join(format!("{}.{extension}", self.thumbnails.len()));
+        if fs::copy(&preview, &path).is_ok() {
+            self.thumbnails.push(Thumbnail { path, label });
+        }
+    }
+
+    /// Shows the spinner if compiling takes longer than [`SPINNER_DELAY`]
+    fn start_compiling(&mut self) {
+        if !matches!(self.state, State::Compiling { .. }) {
+            self.state = State::Compiling {
+                previous: Box::new(mem::take(&mut self.state)),
+                since: Instant::now(),
+            };
+        }
+    }
+
+    /// How long editing has to pause before compiling
+    const fn idle(&self) -> Duration {
+        match self.backend {
+            Backend::LaTeX => self.latex_idle,
+            Backend::Typst => TYPST_DEBOUNCE,
+        }
+    }
+
+    /// The compiled image's file name without `_eq.{format}`, see [`PostProcess::tag`]
+    fn compiled_stem(&self) -> String {
+        format!("{}{}", self.compiled_color, self.compiled_post.tag())
+    }
+
+    /// Where the compiled image is in the cache
+    fn compiled_path(&self) -> PathBuf {
+        self.cache_dir().join(format!("{}_eq.{}", self.compiled_stem(), self.format))
+    }
+
+    /// The image that the preview shows, which for pdfs is the svg compiled alongside it
+    fn preview_path(&self) -> PathBuf {
+        let format = match self.format {
+            ImageFormat::Pdf => ImageFormat::Svg,
+            format => format,
+        };
+        self.cache_dir().join(format!("{}_eq.{format}", self.compiled_stem()))
+    }
+
+    /// Applies any post-processing to the compiled image, then shows it
+    fn image_generated(&mut self) -> Command<Message> {
+        if self.compiled_post.is_identity() {
+            self.update(Message::PostProcessed(Ok(())))
+        } else {
+            Command::perform(
+                backends::post_process(
+                    self.cache_dir(),
+                    self.compiled_color.clone(),
+                    self.format,
+                    self.compiled_post.clone(),
+                ),
+                Message::PostProcessed,
+            )
+        }
+    }
+
+    /// Parses the canvas inputs into `post.canvas`, recompiling once they're valid or cleared
+    fn update_canvas(&mut self) -> Command<Message> {
+        let parsed = Canvas::parse(&self.canvas_size, &self.canvas_offset);
+        if parsed != self.post.canvas && (parsed.is_some() || self.canvas_size.is_empty()) {
+            self.post.canvas = parsed;
+            self.update(Message::Compile)
+        } else {
+            Command::none()
+        }
+    }
+
+    /// Parses the crop padding into `post.crop`, recompiling once it's valid or cropping is turned off
+    fn update_crop(&mut self) -> Command<Message> {
+        let parsed = self.crop
+            .then(|| parse_padding(&self.crop_padding))
+            .flatten();
+        if parsed != self.post.crop && (parsed.is_some() || !self.crop) {
+            self.post.crop = parsed;
+            self.update(Message::Compile)
+        } else {
+            Command::none()
+        }
+    }
+
+    /// Parses the shadow inputs into `post.shadow`, recompiling once they're valid or it's turned off
+    fn update_shadow(&mut self) -> Command<Message> {
+        let parsed = self.shadow
+            .then(|| Shadow::parse(&self.shadow_offset, &self.shadow_blur, &self.shadow_color))
+            .flatten();
+        if parsed != self.post.shadow && (parsed.is_some() || !self.shadow) {
+            self.post.shadow = parsed;
+            self.update(Message::Compile)
+        } else {
+            Command::none()
+        }
+    }
+
+    /// Exports the freshly compiled image, and quits if that was requested when closing the window
+    fn finish_compile(&mut self) -> Command<Message> {
+        let evict = self.evict_cache();
+        if mem::take(&mut self.preview_only) {
+            return evict;
+        }
+        let mut results = self.copy_to_dest();
+        if self.writes_secondary() {
+            results.extend(self.copy_secondary());
+        }
+        if self.writes_font_files() {
+            results.extend(self.copy_font_files());
+        }
+        (self.last_exports, self.failed_exports) = results.into_iter().partition_result();
+        if !self.last_exports.is_empty() {
+            let paths = self.last_exports.iter()
+                .map(|export| export.path.display().to_string())
+                .join(", ");
+            let message = format!("Saved to {paths}");
+            // live rendering exports after every edit, which shouldn't keep the notification up
+            if self.notification.as_ref().is_none_or(|(shown, _)| *shown != message) {
+                self.notification = Some((message, Instant::now()));
+            }
+        }
+        if mem::take(&mut self.record_compile) {
+            self.push_recent();
+            self.push_thumbnail();
+        }
+        self.dirty = false;
+        // a pending compile has newer edits, so wait to quit until that one is exported
+        if self.quit_after_export && !self.compile_pending {
+            window::close(window::Id::MAIN)
+        } else {
+            evict
+        }
+    }
+
+    /// Marks the compiled equation as just used and, in the background, keeps the cache under
+    /// [`Gui::cache_budget_mb`], see [`evict_cache`]
+    fn evict_cache(&self) -> Command<Message> {
+        let used = self.cache_dir();
+        let budget = self.cache_budget_mb.saturating_mul(1024 * 1024);
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || evict_cache(&used, budget))
+                    .await
+                    .expect("evicting from the cache doesn't panic")
+                    .ok()
+            },
+            Message::CacheSize,
+        )
+    }
+
+    /// Shows why the equation can't be compiled at all. What was asked of the compile, like recording
+    /// or copying it, or quitting once it's exported, isn't left over for whichever compile runs next
+    fn reject_compile(&mut self, e: GuiError) {
+        self.record_requested = false;
+        self.copy_after_compile = false;
+        self.quit_after_export = false;
+        self.state = State::Errored(e);
+    }
+
+    /// Shows `e`, or with `keep_last_render` keeps showing the previous image with an error indicator
+    fn compile_failed(&mut self, e: GuiError) {
+        self.quit_after_export = false;
+        self.copy_after_compile = false;
+        if let (true, State::Compiling { previous, .. }, Some((color, post))) = (self.keep_last_render, &self.state, &self.last_render) {
+            if matches!(**previous, State::Svg(_) | State::Png(_)) {
+                // the previous image's file name depends on what it was compiled with
+                self.compiled_color.clone_from(color);
+                self.compiled_post = post.clone();
+                self.state = *previous.clone();
+                self.stale_error = Some(e);
+                return;
+            }
+        }
+        self.stale_error = None;
+        self.state = State::Errored(e);
+    }
+
+    /// Only renders again if the theme changed the color the equation is rendered in, ie when no
+    /// color is set
+    fn theme_changed(&mut self, color_before: &str) -> Command<Message> {
+        if self.color() != color_before && !is_blank(self.eq(), self.backend) {
+            self.update(Message::Compile)
+        } else {
+            Command::none()
+        }
+    }
+
+    /// Whether the current backend's version has been checked, see [`Gui::equation_hash`]
+    const fn version_known(&self) -> bool {
+        match self.backend {
+            Backend::LaTeX => self.latex_versions.is_some(),
+            Backend::Typst => self.typst_version.is_some(),
+        }
+    }
+
+    /// Starts the compile that was waiting for the backend's version, see [`Message::Compile`]
+    fn compile_if_pending(&mut self) -> Command<Message> {
+        if !self.compiling && self.version_known() && mem::take(&mut self.compile_pending) {
+            self.update(Message::Compile)
+        } else {
+            Command::none()
+        }
+    }
+
+    /// Marks the running compile as done, starting the next one if it was requested in the meantime
+    fn compile_done(&mut self, command: Command<Message>) -> Command<Message> {
+        self.compiling = false;
+        self.update_last_command();
+        if mem::take(&mut self.compile_pending) {
+            Command::batch([command, self.update(Message::Compile)])
+        } else {
+            command
+        }
+    }
+
+    /// Shows the most recently run command in the debugging panel, if it changed
+    fn update_last_command(&mut self) {
+        let last_command = backends::last_command();
+        if last_command != self.last_command {
+            self.command_line = last_command.as_ref()
+                .map(RanCommand::command_line)
+                .unwrap_or_default();
+            self.rerun_output = None;
+            self.last_command = last_command;
+        }
+    }
+
+    /// The file name the compiled image is exported as
+    fn file_name(&self) -> PathBuf {
+        self.name
             .as_ref()
             .map_or_else(
                 || self.format.default_file_name().into(),
                 |s| {
+                    let s = self.expand_name(s);
                     let p: &Path = s.as_ref();
                     p.with_extension(self.format.to_string())
                 },
-            );
-        fs::copy(
-            dir.join(from_name),
-            self.out_dir.join(to_name),
-        ).map(|_| ())
+            )
     }
-}
 
-fn not_empty(s: &String) -> bool {
-    !s.is_empty()
-}
+    /// [`Gui::name`] with its `{color}`, `{format}`, `{backend}`, and `{hash}` placeholders filled in
+    /// for the compiled image, eg `integral_{color}.png`
+    fn expand_name(&self, name: &str) -> String {
+        let format = self.format.to_string();
+        let backend = match self.backend {
+            Backend::LaTeX => "latex",
+            Backend::Typst => "typst",
+        };
+        let hash = format!("{:x}", self.equation_hash());
+        utils::expand_file_name(name, &[
+            ("color", &self.compiled_color),
+            ("format", &format),
+            ("backend", backend),
+            ("hash", &hash),
+        ])
+    }
 
-const DEFAULT_COLOR: &str = "white";
+    /// [`Gui::file_name`] without the extension
+    fn file_stem(&self) -> String {
+        self.name.as_deref()
+            .map(|name| self.expand_name(name))
+            .as_deref()
+            .and_then(|name| Path::new(name).file_stem())
+            .map_or_else(|| "eq".into(), |stem| stem.to_string_lossy().to_string())
+    }
 
-fn eq_editor_id() -> Id {
-    Id::new("latex")
-}
+    /// The rendered image's width, in a unit LaTeX understands
+    fn intrinsic_width(&self) -> Result<String, GuiError> {
+        // a pdf is the same size as its svg
+        let path = self.preview_path();
+        let read_err = || GuiError::ReadFile(path.to_string_lossy().to_string());
+        match self.state {
+            State::Svg(_) => {
+                let svg = fs::read_to_string(&path).map_err(|_| read_err())?;
+                let root_end = svg.find("<svg")
+                    .and_then(|start| svg[start..].find('>').map(|end| start + end))
+                    .ok_or_else(read_err)?;
+                // dvisvgm and typst both write the size in pt
+                svg_attribute(&svg[..root_end], "width")
+                    .map(str::to_string)
+                    .ok_or_else(read_err)
+            }
+            State::Png(_) => {
+                let (width, _) = ::image::image_dimensions(&path).map_err(|_| read_err())?;
+                Ok(format!("{:.3}in", f64::from(width) / self.density() as f64))
+            }
+            State::Compiling { .. } | State::Errored(_) => Err(GuiError::NoEquation(self.backend.stylized())),
+        }
+    }
 
-fn color_id() -> Id {
-    Id::new("color")
-}
+    /// An html snippet embedding the exported image, in the style of `html_style`
+    fn html_snippet(&self) -> Result<String, GuiError> {
+        let alt = self.eq();
+        let depth = self.baseline.filter(|_| self.align_baseline);
+        match self.html_style {
+            HtmlStyle::Img => Ok(html::img(&self.file_name().to_string_lossy(), alt, depth)),
+            HtmlStyle::InlineSvg => {
+                if !matches!(self.state, State::Svg(_)) {
+                    return Err(GuiError::Incompatible("an inline svg needs a rendered svg"));
+                }
+                let path = self.preview_path();
+                let svg = fs::read_to_string(&path)
+                    .map_err(|_| GuiError::ReadFile(path.to_string_lossy().to_string()))?;
+                Ok(html::inline_svg(&svg, alt, depth))
+            }
+            HtmlStyle::Picture => {
+                let stem = self.file_stem();
+                Ok(html::picture(
+                    &self.theme_pair.file_name(&stem, Variant::Light, self.format),
+                    &self.theme_pair.file_name(&stem, Variant::Dark, self.format),
+                    alt,
+                    depth,
+                ))
+            }
+        }
+    }
 
-fn file_id() -> Id {
-    Id::new("file")
-}
+    /// What LaTeX svgs do with their text, see [`Gui::svg_fonts`]. Pngs are converted from the svg
+    /// by a program that can't load fonts, so their text is always paths.
+    fn svg_fonts(&self) -> SvgFonts {
+        if self.backend == Backend::LaTeX && self.format == ImageFormat::Svg {
+            self.svg_fonts
+        } else {
+            SvgFonts::Paths
+        }
+    }
 
-fn out_dir_id() -> Id {
-    Id::new("out_dir")
-}
+    /// Whether LaTeX svgs keep their text in separate font files
+    fn writes_font_files(&self) -> bool {
+        self.svg_fonts() == SvgFonts::Files
+    }
 
-impl Application for Gui {
-    type Executor = iced::executor::Default;
-    type Message = Message;
-    type Theme = Theme;
-    type Flags = ();
+    /// Copies the font files the compiled svg uses to each output directory
+    fn copy_font_files(&self) -> Vec<Result<Export, GuiError>> {
+        let path = self.compiled_path();
+        let Ok(svg) = fs::read_to_string(&path) else {
+            return vec![Err(GuiError::ReadFile(path.to_string_lossy().to_string()))];
+        };
+        latex::font_files(&svg)
+            .into_iter()
+            .flat_map(|font| self.write_to_sinks(&self.cache_dir().join(font), font.as_ref()))
+            .collect()
+    }
 
-    fn new((): ()) -> (Self, Command<Message>) {
-        (
-            Self {
-                latex_eq: String::new(),
-                typst_eq: String::new(),
-                name: None,
-                color: None,
-                compiled_color: DEFAULT_COLOR.to_string(),
-                format: ImageFormat::default(),
-                dpi: 1000,
-                out_dir: env::current_dir().unwrap(),
-                state: Default::default(),
-                folder_icon: Icon::Folder,
-                backend: Default::default(),
-                typst_dir: TempDir::new("typst_").unwrap(),
-            },
-            Command::batch([
-                text_input::focus(eq_editor_id()),
-                font::load(ICON_FONT_BYTES)
-                    .map(|_| Message::FontLoaded),
-            ])
-        )
+    /// Whether a png is written alongside the svg, see [`Gui::secondary_png`]
+    fn writes_secondary(&self) -> bool {
+        self.secondary_png && self.format == ImageFormat::Svg
     }
 
-    fn title(&self) -> String {
-        "Equation Maker".into()
+    /// Copies the png made from the compiled svg to each output directory, next to the svg
+    fn copy_secondary(&self) -> Vec<Result<Export, GuiError>> {
+        let from = self.cache_dir().join(format!(
+            "{}{}_eq.png",
+            self.compiled_color,
+            self.post.for_format(ImageFormat::Png).tag(),
+        ));
+        self.write_to_sinks(&from, &self.file_name().with_extension("png"))
     }
 
-    fn update(&mut self, message: Self::Message) -> Command<Message> {
-        match message {
-            Message::EditEquation(equation) => {
-                *self.eq_mut() = equation;
-                if self.backend == Backend::Typst {
-                    self.update(Message::Compile)
-                } else {
-                    Command::none()
-                }
-            }
-            Message::Name(name) => {
-                self.name = Some(name).filter(not_empty);
+    /// Where exports are written: `out_dir`, then each of `extra_out_dirs`
+    fn sinks(&self) -> Vec<Box<dyn OutputSink>> {
+        iter::once(self.out_dir.clone())
+            .chain(self.extra_out_dirs.iter()
+                .map(|dir| dir.trim())
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from))
+            .map(|dir| Box::new(LocalFile { dir }) as Box<dyn OutputSink>)
+            .collect()
+    }
+
+    /// Copies the compiled image to each output directory, returning where it was written and its
+    /// size in bytes, or why it couldn't be
+    fn copy_to_dest(&self) -> Vec<Result<Export, GuiError>> {
+        self.write_to_sinks(&self.compiled_path(), &self.file_name())
+    }
+
+    /// Writes `from` as `name` to each of [`Gui::sinks`]
+    fn write_to_sinks(&self, from: &Path, name: &Path) -> Vec<Result<Export, GuiError>> {
+        write_to(&self.sinks(), from, name)
+    }
+
+    /// Where the contact sheet, the pdf, animations and light & dark exports are made, before they're
+    /// written to each output directory like the compiled image, see [`Gui::export_staged`]
+    fn staging_dir(&self) -> PathBuf {
+        self.session_dir.path().join("exports")
+    }
+
+    /// [`Gui::staging_dir`], creating it if it doesn't exist yet
+    fn create_staging_dir(&self) -> Result<PathBuf, GuiError> {
+        let dir = self.staging_dir();
+        fs::create_dir_all(&dir)
+            .map(|()| dir.clone())
+            .map_err(|_| GuiError::WriteFile(dir.to_string_lossy().to_string().into()))
+    }
+
+    /// Writes each of `staged`, made in [`Gui::staging_dir`], to each of [`Gui::sinks`] under the same
+    /// relative path
+    fn export_staged(&mut self, staged: &[PathBuf]) {
+        let staging = self.staging_dir();
+        (self.last_exports, self.failed_exports) = staged.iter()
+            .flat_map(|from| self.write_to_sinks(from, from.strip_prefix(&staging).unwrap_or(from)))
+            .partition_result();
+    }
+}
+
+/// Writes `from` as `name` to each of `sinks`, returning where it was written and its size in bytes,
+/// or why it couldn't be
+fn write_to(sinks: &[Box<dyn OutputSink>], from: &Path, name: &Path) -> Vec<Result<Export, GuiError>> {
+    sinks.iter()
+        .map(|sink| sink.write(from, name)
+            .map(|(path, size)| Export { path, size })
+            .map_err(|_| GuiError::CopyFile(
+                from.to_string_lossy().to_string(),
+                sink.destination(name).to_string_lossy().to_string(),
+            )))
+        .collect()
+}
+
+// takes `&String` so it can be passed directly to `Option<String>::filter`
+#[allow(clippy::ptr_arg)]
+const fn not_empty(s: &String) -> bool {
+    !s.is_empty()
+}
+
+/// Whether there's nothing to render in `eq`, since an equation of only spaces, newlines, and
+/// comments renders as an empty image or fails to compile
+fn is_blank(eq: &str, backend: Backend) -> bool {
+    let mut chars = eq.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (backend, c) {
+            (_, c) if c.is_whitespace() => {}
+            (Backend::LaTeX, '%') => {
+                // comment until the end of the line
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            (Backend::Typst, '/') if chars.next_if_eq(&'/').is_some() => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            (Backend::Typst, '/') if chars.next_if_eq(&'*').is_some() => {
+                // block comments nest in Typst
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        Some('/') if chars.next_if_eq(&'*').is_some() => depth += 1,
+                        Some('*') if chars.next_if_eq(&'/').is_some() => depth -= 1,
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Pixels of padding left by cropping, none if it's empty
+fn parse_padding(padding: &str) -> Option<u32> {
+    let padding = padding.trim();
+    if padding.is_empty() {
+        Some(0)
+    } else {
+        padding.parse().ok()
+    }
+}
+
+/// A ratio like `16:9`
+fn parse_aspect(aspect: &str) -> Option<(u32, u32)> {
+    aspect.split_once(':')
+        .and_then(|(width, height)| Some((width.trim().parse().ok()?, height.trim().parse().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+}
+
+pub const DEFAULT_COLOR: &str = "white";
+
+/// How often changed settings are saved
+const SETTINGS_SAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long after the last edit Typst compiles
+const TYPST_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// How long a compile can take before the loading spinner is shown
+const SPINNER_DELAY: Duration = Duration::from_millis(150);
+
+/// Opens the directory it's given in the platform's file manager
+const FILE_MANAGER: &str = if cfg!(windows) {
+    "explorer"
+} else if cfg!(target_os = "macos") {
+    "open"
+} else {
+    "xdg-open"
+};
+
+/// How long [`Gui::toast`] is shown for
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+
+/// How long [`Gui::notification`] is shown for, the last [`NOTIFICATION_FADE`] of which it fades
+/// out over
+const NOTIFICATION_DURATION: Duration = Duration::from_secs(4);
+const NOTIFICATION_FADE: Duration = Duration::from_secs(1);
+/// How often the notification is redrawn, so that it fades smoothly
+const NOTIFICATION_FRAME: Duration = Duration::from_millis(33);
+
+fn color_id() -> Id {
+    Id::new("color")
+}
+
+fn file_id() -> Id {
+    Id::new("file")
+}
+
+fn out_dir_id() -> Id {
+    Id::new("out_dir")
+}
+
+impl Application for Gui {
+    type Executor = iced::executor::Default;
+    type Message = Message;
+    type Theme = Theme;
+    type Flags = ();
+
+    fn new((): ()) -> (Self, Command<Message>) {
+        let settings = settings::load();
+        backends::set_safe_mode(settings.safe_mode);
+        backends::set_max_png_size(settings.max_png_size);
+        let mut gui = Self::from_settings(settings, history::load());
+        let render = if gui.rendering_example {
+            gui.update(Message::Compile)
+        } else {
+            Command::none()
+        };
+        gui.sync_editor();
+        gui.check_tools();
+        let workers = Command::perform(
+            backends::set_max_workers(gui.max_workers),
+            |()| Message::WorkersSet,
+        );
+        (
+            gui,
+            Command::batch([
+                font::load(ICON_FONT_BYTES)
+                    .map(Message::FontLoaded),
+                Command::perform(
+                    backends::tool_versions(&latex::TOOLS),
+                    Message::LatexVersions,
+                ),
+                Command::perform(
+                    typst::version(),
+                    Message::TypstVersion,
+                ),
+                Command::perform(
+                    style::system_is_dark(),
+                    Message::SystemTheme,
+                ),
+                workers,
+                render,
+            ])
+        )
+    }
+
+    fn title(&self) -> String {
+        "Equation Maker".into()
+    }
+
+    fn update(&mut self, message: Self::Message) -> Command<Message> {
+        match message {
+            // whichever step of the compile was running when it was cancelled
+            Message::SvgGenerated(_) | Message::PngGenerated(_) | Message::PostProcessed(_) if self.cancelled => {
+                self.cancelled = false;
+                self.compile_done(Command::none())
+            }
+            Message::EditEquation(equation) => {
+                *self.eq_mut() = equation;
+                self.dirty = true;
+                self.warning = check::check(self.eq(), self.backend);
+                // rather than compiling on every key, which queues up a compile per key while typing
+                if self.backend == Backend::Typst || self.latex_live {
+                    self.edited_at = Some(Instant::now());
+                }
+                Command::none()
+            }
+            Message::EditorAction(action) => {
+                // Ctrl+Enter renders, see `shortcuts::SHORTCUTS`
+                if action == text_editor::Action::Edit(text_editor::Edit::Enter) && self.modifiers.command() {
+                    return Command::none();
+                }
+                let is_edit = action.is_edit();
+                self.editor.perform(action);
+                if is_edit {
+                    // `text` always ends in a newline
+                    let text = self.editor.text();
+                    let equation = text.strip_suffix('\n').unwrap_or(&text).to_string();
+                    self.update(Message::EditEquation(equation))
+                } else {
+                    Command::none()
+                }
+            }
+            Message::InsertTemplate(template) => {
+                // at the cursor, replacing the selection like pasting would
+                let source = Arc::new(template.source(self.backend).to_string());
+                self.update(Message::EditorAction(text_editor::Action::Edit(text_editor::Edit::Paste(source))))
+            }
+            Message::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+                Command::none()
+            }
+            Message::Name(name) => {
+                self.name = Some(name).filter(not_empty);
+                self.dirty = true;
                 Command::none()
             }
             Message::Color(color) => {
                 self.color = Some(color).filter(not_empty);
+                self.dirty = true;
+                // keep the picker in sync with what's typed
+                if self.color_picker.is_some() {
+                    self.color_picker = self.color_rgba().or(self.color_picker);
+                }
                 Command::none()
             }
-            Message::Compile => {
-                if self.eq().is_empty() {
-                    self.state = State::Errored(GuiError::NoEquation(self.backend.stylized()));
+            Message::ToggleColorPicker => {
+                self.color_picker = match self.color_picker {
+                    Some(_) => None,
+                    None => Some(self.color_rgba().unwrap_or([255; 4])),
+                };
+                Command::none()
+            }
+            Message::PickColor(channel, value) => {
+                let Some(picker) = &mut self.color_picker else {
+                    return Command::none();
+                };
+                picker[channel] = value;
+                let [r, g, b, a] = *picker;
+                // in whatever syntax the backend understands, keeping the transparency
+                let color = match (self.backend, a) {
+                    (Backend::LaTeX, 255) => format!("#{r:02x}{g:02x}{b:02x}"),
+                    (Backend::LaTeX, _) => format!("rgba({r}, {g}, {b}, {:.3})", f64::from(a) / 255.0),
+                    (Backend::Typst, 255) => format!("rgb({r}, {g}, {b})"),
+                    (Backend::Typst, _) => format!("rgb({r}, {g}, {b}, {a})"),
+                };
+                self.color = Some(color);
+                self.dirty = true;
+                // compiled when the slider is let go
+                Command::none()
+            }
+            Message::PickSwatch(i) => {
+                let Some(swatch) = self.swatches.get(i) else {
                     return Command::none();
+                };
+                self.color = Some(swatch.color.clone());
+                self.dirty = true;
+                self.update(Message::Compile)
+            }
+            Message::AddSwatch => {
+                let color = self.color().to_string();
+                if !self.swatches.iter().any(|swatch| swatch.color == color) {
+                    self.swatches.push(Swatch::new(color));
                 }
-                self.state = State::Compiling { previous: Box::new(mem::take(&mut self.state)) };
+                Command::none()
+            }
+            Message::RemoveSwatch => {
                 let color = self.color().to_string();
-                self.compiled_color = color.clone();
+                self.swatches.retain(|swatch| swatch.color != color);
+                Command::none()
+            }
+            Message::Render => {
+                // cleared if the equation can't be compiled, see `Gui::reject_compile`
+                self.record_requested = true;
+                self.update(Message::Compile)
+            }
+            Message::Compile => {
+                self.preview_only = mem::take(&mut self.rendering_example);
+                // compiling now anyway
+                self.edited_at = None;
+                if is_blank(self.eq(), self.backend) {
+                    self.reject_compile(GuiError::NoEquation(self.backend.stylized()));
+                    return Command::none();
+                }
+                self.warning = check::check(self.eq(), self.backend);
+                if let Err(e) = self.extra_args.validate()
+                    .and_then(|()| self.extra_args.check_compatible(self.backend))
+                    .and_then(|()| self.check_preamble())
+                    .and_then(|()| self.check_color())
+                    .and_then(|()| backends::check_safe(self.eq(), self.backend)) {
+                    self.reject_compile(e);
+                    return Command::none();
+                }
+                if self.compiling {
+                    // the compiles would race each other in the same directory, so just remember to
+                    //  compile whatever the latest equation is once this one is done
+                    self.compile_pending = true;
+                    return Command::none();
+                }
+                if !self.version_known() {
+                    // the version is part of the cache directory, so compiling now could reuse an
+                    //  image from before an upgrade. Compile once it's known instead
+                    self.compile_pending = true;
+                    return Command::none();
+                }
+                self.compiling = true;
+                self.record_compile = mem::take(&mut self.record_requested);
+                let color = self.render_color().to_string();
+                self.compiled_color.clone_from(&color);
+                self.compiled_post = self.post.for_format(self.format);
                 match self.backend {
                     Backend::LaTeX => {
                         let hash = self.equation_hash();
                         let dir = get_dir(hash);
-                        println!("dir = {dir:?}");
+                        println!("dir = {}", dir.display());
                         if dir.exists() {
                             println!("dir exists!");
                             let img = dir.join(format!(
@@ -310,6 +1786,7 @@ impl Application for Gui {
                             if img.exists() && self.format == ImageFormat::Svg {
                                 self.update(Message::SvgGenerated(Ok(())))
                             } else {
+                                self.start_compiling();
                                 Command::perform(
                                     latex::set_color(
                                         dir,
@@ -320,107 +1797,1121 @@ impl Application for Gui {
                             }
                         } else {
                             println!("doesn't exist, performing `latex::gen_svg`");
+                            self.start_compiling();
                             Command::perform(
-                                latex::gen_svg(
-                                    self.latex_eq.clone(),
-                                    dir,
-                                    color,
+                                backends::cancellable(
+                                    latex::gen_svg(
+                                        self.rendered(&self.latex_eq),
+                                        self.preamble(),
+                                        dir,
+                                        color,
+                                        self.layout(),
+                                        self.extra_args.clone(),
+                                        self.scratch_dir(),
+                                        self.svg_fonts(),
+                                    ),
+                                ),
+                                Message::SvgGenerated,
+                            )
+                        }
+                    }
+                    Backend::Typst => {
+                        // otherwise `cache_dir` falls back to `typst_dir`
+                        let cached = fs::create_dir_all(get_typst_dir(self.equation_hash())).is_ok();
+                        let dir = self.cache_dir();
+                        // `typst_dir` is shared between equations, so its files might be from a
+                        //  previous one
+                        if cached && self.format == ImageFormat::Svg && dir.join(format!("{color}_eq.svg")).exists() {
+                            self.update(Message::SvgGenerated(Ok(())))
+                        } else if cached && typst::svg_color(&color).is_some() && dir.join("eq.svg").exists() {
+                            // only the color changed, so recolor the existing svg instead of
+                            //  recompiling
+                            self.start_compiling();
+                            Command::perform(
+                                typst::set_color(dir, color),
+                                Message::SvgGenerated,
+                            )
+                        } else {
+                            self.start_compiling();
+                            Command::perform(
+                                backends::cancellable(
+                                    typst::gen_svg(
+                                        self.rendered(&self.typst_eq),
+                                        self.preamble(),
+                                        dir,
+                                        color,
+                                        self.layout(),
+                                        self.extra_args.clone(),
+                                    ),
                                 ),
                                 Message::SvgGenerated,
                             )
                         }
                     }
-                    Backend::Typst => Command::perform(
-                        typst::gen_svg(
-                            self.typst_eq.clone(),
-                            self.typst_dir.path().to_owned(),
-                            color,
-                        ),
-                        Message::SvgGenerated,
-                    ),
                 }
             }
             Message::SvgGenerated(dir) => {
                 match dir {
+                    // the equation was edited since this compile started, so skip straight to the
+                    //  next compile instead of showing an outdated image
+                    Ok(()) if self.compile_pending || self.edited_at.is_some() => {
+                        self.compile_done(Command::none())
+                    }
                     Ok(()) => {
                         let dir = self.cache_dir();
                         match self.format {
-                            ImageFormat::Svg => {
-                                self.state = State::Svg(dir);
-                                self.copy_to_dest().unwrap();
-                                Command::none()
-                            }
+                            ImageFormat::Svg => self.image_generated(),
                             ImageFormat::Png => Command::perform(
-                                self.backend.gen_png(
-                                    self.eq().to_string(),
-                                    dir,
+                                backends::cancellable(
+                                    self.backend.gen_png(
+                                        self.rendered(self.eq()),
+                                        self.preamble(),
+                                        dir,
+                                        self.color().to_string(),
+                                        self.density(),
+                                        self.layout(),
+                                        self.extra_args.clone(),
+                                    ),
+                                ),
+                                Message::PngGenerated,
+                            ),
+                            ImageFormat::Pdf => Command::perform(
+                                backends::cancellable(
+                                    self.backend.gen_pdf(
+                                        self.rendered(self.eq()),
+                                        self.preamble(),
+                                        dir,
+                                        self.color().to_string(),
+                                        self.layout(),
+                                        self.extra_args.clone(),
+                                        self.scratch_dir(),
+                                    ),
+                                ),
+                                Message::PngGenerated,
+                            ),
+                        }
+                    }
+                    Err(e) => {
+                        self.compile_failed(e);
+                        self.compile_done(Command::none())
+                    }
+                }
+            }
+            Message::PngGenerated(res) => {
+                match res {
+                    Ok(()) => self.image_generated(),
+                    Err(e) => {
+                        self.compile_failed(e);
+                        self.compile_done(Command::none())
+                    }
+                }
+            }
+            Message::PostProcessed(res) => {
+                match res {
+                    Ok(()) => {
+                        let dir = self.cache_dir();
+                        self.state = match self.format {
+                            ImageFormat::Svg | ImageFormat::Pdf => State::Svg(dir),
+                            ImageFormat::Png => State::Png(dir),
+                        };
+                        // only svgs have the glyph positions to find it from
+                        self.baseline = match self.format {
+                            ImageFormat::Svg => fs::read(self.compiled_path())
+                                .ok()
+                                .and_then(|svg| baseline::depth(&svg)),
+                            ImageFormat::Png | ImageFormat::Pdf => None,
+                        };
+                        self.stale_error = None;
+                        self.last_render = Some((self.compiled_color.clone(), self.compiled_post.clone()));
+                        let copy = if mem::take(&mut self.copy_after_compile) {
+                            self.update(Message::CopyImage)
+                        } else {
+                            Command::none()
+                        };
+                        if self.writes_secondary() && !self.preview_only {
+                            // the svg already has the scale applied, so don't use `density`
+                            let secondary = Command::perform(
+                                backends::svg_to_png(
+                                    self.cache_dir(),
+                                    self.compiled_color.clone(),
                                     self.color().to_string(),
                                     self.dpi,
+                                    self.post.for_format(ImageFormat::Png),
                                 ),
-                                Message::PngGenerated,
-                            )
+                                Message::SecondaryGenerated,
+                            );
+                            return Command::batch([copy, secondary]);
                         }
+                        let finished = self.finish_compile();
+                        Command::batch([copy, self.compile_done(finished)])
+                    }
+                    Err(e) => {
+                        self.compile_failed(e);
+                        self.compile_done(Command::none())
+                    }
+                }
+            }
+            Message::SecondaryGenerated(res) => {
+                // the svg is fine, so keep showing it and still export it
+                if let Err(e) = res {
+                    self.stale_error = Some(e);
+                }
+                let finished = self.finish_compile();
+                self.compile_done(finished)
+            }
+            Message::FocusNext => widget::focus_next(),
+            Message::FocusPrevious => widget::focus_previous(),
+            Message::Format(f) => {
+                self.format = f;
+                // eg magick is only needed for LaTeX pngs
+                self.check_tools();
+                self.update(Message::Compile)
+            }
+            Message::SetDpi(dpi) => {
+                if dpi.is_empty() {
+                    self.dpi = 0;
+                } else if let Ok(dpi) = dpi.parse() {
+                    self.dpi = dpi;
+                }
+                self.update(Message::Compile)
+            }
+            Message::SetScale(scale) => {
+                self.scale = scale;
+                self.update(Message::Compile)
+            }
+            Message::SetAlignment(alignment) => {
+                self.alignment = alignment;
+                self.update(Message::Compile)
+            }
+            Message::SetExactBbox(exact_bbox) => {
+                self.exact_bbox = exact_bbox;
+                self.update(Message::Compile)
+            }
+            Message::SetTypstFont(font) => {
+                self.typst_font = font;
+                self.update(Message::Compile)
+            }
+            Message::SetTypstFontSize(size) => {
+                self.typst_font_size = size;
+                self.update(Message::Compile)
+            }
+            Message::OutDir(dir) => {
+                if self.pin_out_dir {
+                    return Command::none();
+                }
+                // println!("dir = {:?}", dir);
+                self.out_dir = dir.into();
+                self.dirty = true;
+                // don't copy the file eagerly, wait for user to request re-compile cuz otherwise it
+                //  will try to copy to each non-existent directory as they type the full thing in
+                //  and will successfully copy to each subdirectory which is no good
+                Command::none()
+            }
+            Message::AddOutDir => {
+                self.extra_out_dirs.push(String::new());
+                Command::none()
+            }
+            Message::EditOutDir(i, dir) => {
+                // like `out_dir`, only used on the next compile
+                self.extra_out_dirs[i] = dir;
+                self.dirty = true;
+                Command::none()
+            }
+            Message::RemoveOutDir(i) => {
+                self.extra_out_dirs.remove(i);
+                Command::none()
+            }
+            Message::SetPinOutDir(pin) => {
+                self.pin_out_dir = pin;
+                Command::none()
+            }
+            Message::OpenExplorer => {
+                if self.picking_dir || self.pin_out_dir {
+                    return Command::none();
+                }
+                self.picking_dir = true;
+                self.folder_icon = Icon::Folder2Open;
+                Command::perform(
+                    AsyncFileDialog::new().pick_folder(),
+                    |fh: Option<FileHandle>| Message::PickedDir(fh.map(|fh| fh.path().to_path_buf())),
+                )
+            }
+            Message::OpenSaveAs => {
+                // like the folder picker, a pinned directory can't be changed by saving elsewhere
+                if self.picking_dir || self.pin_out_dir {
+                    return Command::none();
+                }
+                self.picking_dir = true;
+                let format = self.format.to_string();
+                Command::perform(
+                    AsyncFileDialog::new()
+                        .set_directory(&self.out_dir)
+                        .set_file_name(self.file_name().to_string_lossy())
+                        .add_filter(&format, &[&format])
+                        .save_file(),
+                    |fh: Option<FileHandle>| Message::SaveAs(fh.map(|fh| fh.path().to_path_buf())),
+                )
+            }
+            Message::SaveAs(path) => {
+                // sent whether or not a file was picked
+                self.picking_dir = false;
+                let Some(path) = path.filter(|_| !self.pin_out_dir) else {
+                    return Command::none();
+                };
+                let format = path.extension()
+                    .and_then(|ext| ImageFormat::ALL.into_iter()
+                        .find(|format| ext.eq_ignore_ascii_case(format.to_string())));
+                if let Some(format) = format {
+                    self.format = format;
+                    self.check_tools();
+                }
+                if let Some(dir) = path.parent() {
+                    self.out_dir = dir.to_path_buf();
+                }
+                self.name = path.file_name()
+                    .map(|name| name.to_string_lossy().to_string());
+                // exports to the new location, and keeps doing so for later renders
+                self.update(Message::Render)
+            }
+            Message::RevealOutput => {
+                let dir = self.out_dir.clone();
+                Command::perform(
+                    async move {
+                        // nothing may have been exported there yet
+                        tokio::fs::create_dir_all(&dir)
+                            .await
+                            .map_err(|_| GuiError::WriteFile(dir.to_string_lossy().to_string().into()))?;
+                        match backends::run_command(FILE_MANAGER, [&dir]).await {
+                            // explorer exits with 1 even when it opened the folder
+                            Err(CommandError::Error { .. }) if cfg!(windows) => Ok(()),
+                            res => res.map(|_| ()).map_err(GuiError::from),
+                        }
+                    },
+                    Message::Opened,
+                )
+            }
+            Message::OpenLastExport => {
+                let Some(Export { path, .. }) = self.last_exports.first().cloned() else {
+                    return Command::none();
+                };
+                Command::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || opener::open(&path)
+                            .map_err(|e| GuiError::OpenFile(path.to_string_lossy().to_string(), e.to_string())))
+                            .await
+                            .expect("opening doesn't panic")
+                    },
+                    Message::Opened,
+                )
+            }
+            Message::ShareLastExport => {
+                let Some(Export { path, .. }) = self.last_exports.first().cloned() else {
+                    return Command::none();
+                };
+                let sharer = self.share_method.sharer();
+                Command::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || sharer.share(&path))
+                            .await
+                            .expect("sharing doesn't panic")
+                    },
+                    Message::Opened,
+                )
+            }
+            Message::SetShareMethod(method) => {
+                self.share_method = method;
+                Command::none()
+            }
+            Message::PickedDir(dir) => {
+                // sent whether or not a folder was picked
+                self.picking_dir = false;
+                self.folder_icon = Icon::Folder2;
+                if let Some(dir) = dir.filter(|_| !self.pin_out_dir) {
+                    self.out_dir = dir;
+                }
+                Command::none()
+            }
+            Message::LatexVersions(versions) => {
+                self.latex_versions = Some(versions);
+                self.compile_if_pending()
+            }
+            Message::TypstVersion(version) => {
+                self.typst_version = Some(version);
+                self.compile_if_pending()
+            }
+            Message::FontLoaded(res) => {
+                if let Err(e) = res {
+                    eprintln!("couldn't load the icon font: {e:?}");
+                    self.icon_font_failed = true;
+                }
+                Command::none()
+            }
+            Message::SetBackend(backend) => {
+                self.backend = backend;
+                self.sync_editor();
+                self.check_tools();
+                self.update(Message::Compile)
+            }
+            Message::SwapBackend => {
+                self.backend = self.backend.flip();
+                // the most recent equation exported with the other backend, if there is one
+                if let Some(recent) = self.recent.iter().find(|r| r.backend == self.backend).cloned() {
+                    *self.eq_mut() = recent.eq;
+                    self.color = recent.color;
+                }
+                self.sync_editor();
+                self.check_tools();
+                self.update(Message::Compile)
+            }
+            Message::SwitchSyntax(backend) => {
+                let eq = self.eq().to_string();
+                self.backend = backend;
+                *self.eq_mut() = eq;
+                self.sync_editor();
+                self.check_tools();
+                self.update(Message::Compile)
+            }
+            Message::SetInvert(invert) => {
+                self.post.invert = invert;
+                self.update(Message::Compile)
+            }
+            Message::SetStripProlog(strip) => {
+                self.post.strip_prolog = strip;
+                self.update(Message::Compile)
+            }
+            Message::SetSvgSize(size) => {
+                self.post.size = size;
+                self.update(Message::Compile)
+            }
+            Message::SetDepth(depth) => {
+                self.post.depth = depth;
+                self.update(Message::Compile)
+            }
+            Message::SetProfile(profile) => {
+                self.post.profile = profile;
+                self.update(Message::Compile)
+            }
+            Message::SetAspect(aspect) => {
+                let parsed = parse_aspect(&aspect);
+                self.aspect = aspect;
+                // only recompile once it is a valid ratio, or cleared
+                if parsed != self.post.aspect && (parsed.is_some() || self.aspect.is_empty()) {
+                    self.post.aspect = parsed;
+                    self.update(Message::Compile)
+                } else {
+                    Command::none()
+                }
+            }
+            Message::SetCrop(crop) => {
+                self.crop = crop;
+                self.update_crop()
+            }
+            Message::SetCropPadding(padding) => {
+                self.crop_padding = padding;
+                self.update_crop()
+            }
+            Message::SetCanvasSize(size) => {
+                self.canvas_size = size;
+                self.update_canvas()
+            }
+            Message::SetCanvasOffset(offset) => {
+                self.canvas_offset = offset;
+                self.update_canvas()
+            }
+            Message::SetShadow(shadow) => {
+                self.shadow = shadow;
+                self.update_shadow()
+            }
+            Message::SetShadowOffset(offset) => {
+                self.shadow_offset = offset;
+                self.update_shadow()
+            }
+            Message::SetShadowBlur(blur) => {
+                self.shadow_blur = blur;
+                self.update_shadow()
+            }
+            Message::SetShadowColor(color) => {
+                // like the background, recompiles on submit
+                self.shadow_color = color;
+                if let Some(shadow) = &mut self.post.shadow {
+                    shadow.color = if self.shadow_color.trim().is_empty() { "black" } else { self.shadow_color.trim() }.to_string();
+                }
+                Command::none()
+            }
+            Message::SetBackground(background) => {
+                // recompiles on submit, so the background isn't changed to every partial color
+                self.post.background = Some(background).filter(not_empty);
+                Command::none()
+            }
+            Message::SetIccPath(path) => {
+                self.post.icc_path = path.into();
+                Command::none()
+            }
+            Message::SetSvgFonts(svg_fonts) => {
+                self.svg_fonts = svg_fonts;
+                self.update(Message::Compile)
+            }
+            Message::SetSecondaryPng(secondary_png) => {
+                self.secondary_png = secondary_png;
+                Command::none()
+            }
+            Message::SetCurrentColor(current_color) => {
+                self.current_color = current_color;
+                self.update(Message::Compile)
+            }
+            Message::ToggleAnimation => {
+                self.show_animation = !self.show_animation;
+                Command::none()
+            }
+            Message::EditFrame(i, eq) => {
+                self.animation.frames[i] = eq;
+                Command::none()
+            }
+            Message::AddFrame => {
+                // most steps of a derivation only change a little from the last one
+                let last = self.animation.frames.last().cloned().unwrap_or_default();
+                self.animation.frames.push(last);
+                Command::none()
+            }
+            Message::RemoveFrame(i) => {
+                self.animation.frames.remove(i);
+                Command::none()
+            }
+            Message::SetFrameDuration(ms) => {
+                if ms.is_empty() {
+                    self.animation.frame_duration = Duration::ZERO;
+                } else if let Ok(ms) = ms.parse() {
+                    self.animation.frame_duration = Duration::from_millis(ms);
+                }
+                Command::none()
+            }
+            Message::SetAnimationFormat(format) => {
+                self.animation.format = format;
+                Command::none()
+            }
+            Message::ExportAnimation => {
+                if self.animation.frames.iter().any(|frame| is_blank(frame, self.backend)) {
+                    self.state = State::Errored(GuiError::NoEquation(self.backend.stylized()));
+                    return Command::none();
+                }
+                let safe = self.animation.frames.iter()
+                    .try_for_each(|frame| backends::check_safe(frame, self.backend));
+                if let Err(e) = self.extra_args.validate()
+                    .and(safe)
+                    .and_then(|()| self.check_preamble())
+                    .and_then(|()| self.check_color()) {
+                    self.state = State::Errored(e);
+                    return Command::none();
+                }
+                let staging = match self.create_staging_dir() {
+                    Ok(staging) => staging,
+                    Err(e) => {
+                        self.state = State::Errored(e);
+                        return Command::none();
                     }
+                };
+                let file_name = self.name.as_deref()
+                    .unwrap_or("animation");
+                let path = staging.join(file_name)
+                    .with_extension(self.animation.format.extension());
+                self.start_compiling();
+                let job = animation::Job {
+                    animation: Animation {
+                        frames: self.animation.frames.iter()
+                            .map(|frame| self.rendered(frame))
+                            .collect(),
+                        ..self.animation.clone()
+                    },
+                    backend: self.backend,
+                    color: self.color().to_string(),
+                    dpi: self.dpi,
+                    // the animation's format is independent of `self.format`, so always scale the
+                    //  frames with the backend
+                    layout: Layout { scale: self.scale(), ..self.layout() },
+                    extra_args: self.extra_args.clone(),
+                    preamble: self.preamble(),
+                    scratch: self.scratch_dir(),
+                    path: path.clone(),
+                };
+                Command::perform(
+                    animation::export(job),
+                    move |res| Message::AnimationExported(path, res),
+                )
+            }
+            Message::AnimationExported(path, res) => {
+                self.update_last_command();
+                // show whatever was there before the animation was rendered
+                if let State::Compiling { previous, .. } = mem::take(&mut self.state) {
+                    self.state = *previous;
+                }
+                match res {
+                    Ok(_) => self.export_staged(&[path]),
+                    Err(e) => self.state = State::Errored(e),
+                }
+                Command::none()
+            }
+            Message::ToggleSettings => {
+                self.show_settings = !self.show_settings;
+                if self.show_settings {
+                    measure_cache()
+                } else {
+                    Command::none()
+                }
+            }
+            Message::CacheSize(size) => {
+                self.cache_size = size;
+                Command::none()
+            }
+            Message::SetCacheBudget(budget) => {
+                let Ok(budget) = budget.parse() else {
+                    return Command::none();
+                };
+                self.cache_budget_mb = budget;
+                Command::none()
+            }
+            Message::ClearCache => Command::perform(
+                AsyncMessageDialog::new()
+                    .set_level(MessageLevel::Warning)
+                    .set_title("Clear the cache")
+                    .set_description(format!(
+                        "Delete every cached equation ({})? They'll be compiled again when they're next needed.",
+                        self.cache_size.map_or_else(|| "unknown size".into(), file_size),
+                    ))
+                    .set_buttons(MessageButtons::YesNo)
+                    .show(),
+                Message::ConfirmClearCache,
+            ),
+            Message::ConfirmClearCache(result) => {
+                if result != MessageDialogResult::Yes {
+                    return Command::none();
+                }
+                self.clearing_cache = true;
+                Command::perform(
+                    async {
+                        tokio::task::spawn_blocking(clear_cache)
+                            .await
+                            .expect("clearing the cache doesn't panic")
+                            .map_err(|e| GuiError::ClearCache(e.to_string()))
+                    },
+                    Message::CacheCleared,
+                )
+            }
+            Message::CacheCleared(result) => {
+                self.clearing_cache = false;
+                let measure = measure_cache();
+                match result {
+                    // the preview's files were deleted too
+                    Ok(()) => Command::batch([measure, self.update(Message::Compile)]),
+                    Err(e) => {
+                        self.state = State::Errored(e);
+                        measure
+                    }
+                }
+            }
+            Message::SetPairColor(variant, color) => {
+                *self.theme_pair.get_mut(variant) = color;
+                Command::none()
+            }
+            Message::SetPairTemplate(template) => {
+                self.theme_pair.template = template;
+                Command::none()
+            }
+            Message::ExportThemePair => {
+                if is_blank(self.eq(), self.backend) {
+                    self.state = State::Errored(GuiError::NoEquation(self.backend.stylized()));
+                    return Command::none();
+                }
+                if let Err(e) = self.extra_args.validate()
+                    .and_then(|()| self.extra_args.check_compatible(self.backend))
+                    .and_then(|()| self.check_preamble())
+                    .and_then(|()| self.check_color())
+                    .and_then(|()| self.check_pair_colors())
+                    .and_then(|()| backends::check_safe(self.eq(), self.backend)) {
+                    self.state = State::Errored(e);
+                    return Command::none();
+                }
+                let staging = match self.create_staging_dir() {
+                    Ok(staging) => staging,
+                    Err(e) => {
+                        self.state = State::Errored(e);
+                        return Command::none();
+                    }
+                };
+                let name = self.file_stem();
+                let job = theme_pair::Job {
+                    pair: self.theme_pair.clone(),
+                    eq: self.rendered(self.eq()),
+                    backend: self.backend,
+                    format: self.format,
+                    dpi: self.density(),
+                    layout: self.layout(),
+                    extra_args: self.extra_args.clone(),
+                    preamble: self.preamble(),
+                    post: self.post.for_format(self.format),
+                    latex_dir: get_dir(self.equation_hash()),
+                    scratch: self.scratch_dir(),
+                    fonts: self.svg_fonts(),
+                    staging,
+                    name,
+                };
+                Command::perform(
+                    theme_pair::export(job),
+                    Message::ThemePairExported,
+                )
+            }
+            Message::ThemePairExported(res) => {
+                self.update_last_command();
+                match res {
+                    Ok(staged) => self.export_staged(&staged),
+                    Err(e) => self.state = State::Errored(e),
+                }
+                Command::none()
+            }
+            Message::SetLatexLive(live) => {
+                self.latex_live = live;
+                if self.backend == Backend::LaTeX {
+                    self.edited_at = None;
+                }
+                Command::none()
+            }
+            Message::SetLatexIdle(ms) => {
+                if let Ok(ms) = ms.parse() {
+                    self.latex_idle = Duration::from_millis(ms);
+                }
+                Command::none()
+            }
+            Message::EditIdle => {
+                match self.edited_at {
+                    Some(edited_at) if edited_at.elapsed() >= self.idle() => {
+                        self.edited_at = None;
+                        self.update(Message::Compile)
+                    }
+                    _ => Command::none(),
+                }
+            }
+            Message::SetKeepLastRender(keep) => {
+                self.keep_last_render = keep;
+                Command::none()
+            }
+            Message::SetCopySvgMarkup(markup) => {
+                self.copy_svg_markup = markup;
+                Command::none()
+            }
+            Message::SetDimWhileCompiling(dim) => {
+                self.dim_while_compiling = dim;
+                Command::none()
+            }
+            Message::ShowStaleError => {
+                if let Some(e) = self.stale_error.take() {
+                    self.state = State::Errored(e);
+                }
+                Command::none()
+            }
+            Message::SetRoundNumbers(round_numbers) => {
+                self.round_numbers = round_numbers;
+                self.update(Message::Compile)
+            }
+            Message::SetSigFigs(sig_figs) => {
+                let Ok(sig_figs) = sig_figs.parse() else {
+                    return Command::none();
+                };
+                self.sig_figs = sig_figs;
+                if self.round_numbers {
+                    self.update(Message::Compile)
+                } else {
+                    Command::none()
+                }
+            }
+            Message::SetMaxPngSize(max) => {
+                let Ok(max) = max.parse() else {
+                    return Command::none();
+                };
+                self.max_png_size = max;
+                backends::set_max_png_size(max);
+                Command::none()
+            }
+            Message::SetMaxWorkers(workers) => {
+                let Ok(workers) = workers.parse::<usize>() else {
+                    return Command::none();
+                };
+                self.max_workers = workers.max(1);
+                Command::perform(
+                    backends::set_max_workers(self.max_workers),
+                    |()| Message::WorkersSet,
+                )
+            }
+            Message::SetScratchDir(scratch_dir) => {
+                self.scratch_dir = scratch_dir;
+                Command::none()
+            }
+            Message::SetHighContrast(high_contrast) => {
+                self.high_contrast = high_contrast;
+                Command::none()
+            }
+            Message::SetTheme(theme_mode) => {
+                let color = self.color().to_string();
+                self.theme_mode = theme_mode;
+                self.theme_changed(&color)
+            }
+            Message::SystemTheme(system_dark) => {
+                let color = self.color().to_string();
+                self.system_dark = system_dark;
+                self.theme_changed(&color)
+            }
+            Message::SetSafeMode(safe_mode) => {
+                self.safe_mode = safe_mode;
+                backends::set_safe_mode(safe_mode);
+                Command::none()
+            }
+            Message::SetShowCommand(show) => {
+                self.show_command = show;
+                Command::none()
+            }
+            Message::EditCommand(command_line) => {
+                self.command_line = command_line;
+                Command::none()
+            }
+            Message::RerunCommand => {
+                let Some(last_command) = &self.last_command else {
+                    return Command::none();
+                };
+                self.rerun_output = Some("Running...".into());
+                Command::perform(
+                    backends::rerun(last_command.dir.clone(), self.command_line.clone()),
+                    Message::CommandRerun,
+                )
+            }
+            Message::CommandRerun(res) => {
+                self.rerun_output = Some(match res {
+                    Ok(output) if output.trim().is_empty() => "Finished with no output".into(),
+                    Ok(output) => output,
+                    Err(e) => e.to_string(),
+                });
+                Command::none()
+            }
+            Message::SetPrefill(prefill) => {
+                self.prefill = prefill;
+                Command::none()
+            }
+            Message::ToggleShortcuts => {
+                self.show_shortcuts = !self.show_shortcuts;
+                Command::none()
+            }
+            Message::HideShortcuts => {
+                self.show_shortcuts = false;
+                Command::none()
+            }
+            Message::SetExtraArgs(tool, args) => {
+                *self.extra_args.get_mut(tool) = args;
+                Command::none()
+            }
+            Message::LoadRecent(i) => {
+                let Some(recent) = self.recent.get(i).cloned() else {
+                    return Command::none();
+                };
+                self.backend = recent.backend;
+                *self.eq_mut() = recent.eq;
+                self.color = recent.color;
+                self.sync_editor();
+                self.check_tools();
+                self.update(Message::Compile)
+            }
+            Message::ToggleHistory => {
+                self.show_history = !self.show_history;
+                Command::none()
+            }
+            Message::LoadHistory(i) => {
+                let Some(entry) = self.history.entries.get(i).cloned() else {
+                    return Command::none();
+                };
+                self.backend = entry.backend;
+                *self.eq_mut() = entry.eq;
+                self.color = entry.color;
+                self.format = entry.format;
+                self.sync_editor();
+                self.check_tools();
+                self.update(Message::Compile)
+            }
+            Message::SetSheetColumns(columns) => {
+                if let Ok(columns) = columns.parse() {
+                    self.contact_sheet.columns = columns;
+                }
+                Command::none()
+            }
+            Message::SetSheetPadding(padding) => {
+                if padding.is_empty() {
+                    self.contact_sheet.padding = 0;
+                } else if let Ok(padding) = padding.parse() {
+                    self.contact_sheet.padding = padding;
+                }
+                Command::none()
+            }
+            Message::SetSheetLabels(labels) => {
+                self.contact_sheet.labels = labels;
+                Command::none()
+            }
+            Message::ExportContactSheet => {
+                let path = match self.create_staging_dir() {
+                    Ok(staging) => staging.join("contact_sheet.png"),
+                    Err(e) => {
+                        self.state = State::Errored(e);
+                        return Command::none();
+                    }
+                };
+                Command::perform(
+                    contact_sheet::export(
+                        self.thumbnails.clone(),
+                        self.contact_sheet.clone(),
+                        self.dpi,
+                        path.clone(),
+                    ),
+                    move |res| Message::ContactSheetExported(path, res),
+                )
+            }
+            Message::SetPdfCaptions(captions) => {
+                self.pdf.captions = captions;
+                Command::none()
+            }
+            Message::SetPdfDarkPages(dark_pages) => {
+                self.pdf.dark_pages = dark_pages;
+                Command::none()
+            }
+            Message::ExportPdf => {
+                let path = match self.create_staging_dir() {
+                    Ok(staging) => staging.join("equations.pdf"),
+                    Err(e) => {
+                        self.state = State::Errored(e);
+                        return Command::none();
+                    }
+                };
+                Command::perform(
+                    pdf::export(
+                        self.thumbnails.clone(),
+                        self.pdf.clone(),
+                        path.clone(),
+                    ),
+                    // exported the same way as the contact sheet
+                    move |res| Message::ContactSheetExported(path, res),
+                )
+            }
+            Message::ContactSheetExported(path, res) => {
+                match res {
+                    Ok(_) => self.export_staged(&[path]),
+                    Err(e) => self.state = State::Errored(e),
+                }
+                Command::none()
+            }
+            // only sent to redraw, or to run something in the background
+            Message::SpinnerDelay | Message::WorkersSet => Command::none(),
+            Message::CancelCompile => {
+                let State::Compiling { previous, .. } = &mut self.state else {
+                    return Command::none();
+                };
+                let previous = mem::take(&mut **previous);
+                self.state = previous;
+                if self.compiling {
+                    self.cancelled = true;
+                    backends::cancel();
+                }
+                // the edits that would have compiled next are dropped too
+                self.compile_pending = false;
+                self.record_requested = false;
+                self.edited_at = None;
+                self.copy_after_compile = false;
+                self.quit_after_export = false;
+                Command::none()
+            }
+            Message::DismissMissingTools => {
+                self.missing_tools.clear();
+                Command::none()
+            }
+            Message::ToggleErrorDetails => {
+                self.show_error_details = !self.show_error_details;
+                Command::none()
+            }
+            Message::Zoom(factor, around) => {
+                self.zoom = self.zoom.zoomed(factor, around);
+                Command::none()
+            }
+            Message::Pan(by) => {
+                self.zoom = self.zoom.panned(by);
+                Command::none()
+            }
+            Message::ResetZoom => {
+                self.zoom = zoom::View::default();
+                Command::none()
+            }
+            Message::FileDropped(path) => {
+                let path_err = path.to_string_lossy().to_string();
+                let Some(backend) = path.extension()
+                    .and_then(|extension| Backend::from_extension(&extension.to_string_lossy())) else {
+                    self.state = State::Errored(GuiError::OpenFile(path_err, "only .tex and .typ files can be loaded".into()));
+                    return Command::none();
+                };
+                Command::perform(
+                    async move {
+                        tokio::fs::read_to_string(&path)
+                            .await
+                            .map_err(|_| GuiError::ReadFile(path_err))
+                    },
+                    move |res| Message::FileLoaded(backend, res),
+                )
+            }
+            Message::FileLoaded(backend, res) => {
+                let source = match res {
+                    Ok(source) => source,
+                    Err(e) => {
+                        self.state = State::Errored(e);
+                        return Command::none();
+                    }
+                };
+                self.backend = backend;
+                *self.eq_mut() = backend.extract_equation(&source).to_string();
+                self.sync_editor();
+                self.check_tools();
+                self.update(Message::Compile)
+            }
+            Message::TogglePreamble => {
+                self.show_preamble = !self.show_preamble;
+                Command::none()
+            }
+            Message::PreambleAction(action) => {
+                match self.backend {
+                    Backend::LaTeX => self.preamble.perform(action),
+                    Backend::Typst => self.typst_imports.perform(action),
+                }
+                Command::none()
+            }
+            Message::ResetPreamble => {
+                match self.backend {
+                    Backend::LaTeX => self.preamble = text_editor::Content::with_text(latex::DEFAULT_PREAMBLE),
+                    Backend::Typst => self.typst_imports = text_editor::Content::with_text(typst::DEFAULT_IMPORTS),
+                }
+                Command::none()
+            }
+            Message::SaveSettings => {
+                self.save_settings();
+                Command::none()
+            }
+            Message::CopyImage => {
+                let format = match self.state {
+                    State::Svg(_) => ImageFormat::Svg,
+                    State::Png(_) => ImageFormat::Png,
+                    State::Compiling { .. } | State::Errored(_) => return Command::none(),
+                };
+                let path = self.cache_dir().join(format!("{}_eq.{format}", self.compiled_stem()));
+                if self.copy_svg_markup && format == ImageFormat::Svg {
+                    let Ok(svg) = fs::read_to_string(&path) else {
+                        self.state = State::Errored(GuiError::ReadFile(path.to_string_lossy().to_string()));
+                        return Command::none();
+                    };
+                    self.toast = Some("Copied the svg to the clipboard");
+                    return iced::clipboard::write(svg);
+                }
+                Command::perform(
+                    clipboard::copy_image(path, self.dpi),
+                    Message::ImageCopied,
+                )
+            }
+            Message::CopyHtml => {
+                match self.html_snippet() {
+                    Ok(snippet) => iced::clipboard::write(snippet),
                     Err(e) => {
                         self.state = State::Errored(e);
                         Command::none()
                     }
                 }
             }
-            Message::PngGenerated(res) => {
-                match res {
-                    Ok(()) => {
-                        let dir = self.cache_dir();
-                        self.state = State::Png(dir);
-                        self.copy_to_dest().unwrap();
+            Message::SetIncludeWidth(width) => {
+                self.include_width = width;
+                Command::none()
+            }
+            Message::CopyInclude => {
+                let width = if self.include_width.trim().is_empty() {
+                    self.intrinsic_width()
+                } else {
+                    Ok(self.include_width.trim().to_string())
+                };
+                match width {
+                    Ok(width) => iced::clipboard::write(latex::include_snippet(&self.file_name().to_string_lossy(), self.format, &width)),
+                    Err(e) => {
+                        self.state = State::Errored(e);
+                        Command::none()
                     }
-                    Err(e) => self.state = State::Errored(e),
                 }
+            }
+            Message::CopyTikz => {
+                iced::clipboard::write(latex::tikz_snippet(&self.rendered(&self.latex_eq), self.alignment))
+            }
+            Message::SetHtmlStyle(style) => {
+                self.html_style = style;
                 Command::none()
             }
-            Message::FocusNext => widget::focus_next(),
-            Message::FocusPrevious => widget::focus_previous(),
-            Message::Format(f) => {
-                self.format = f;
-                self.update(Message::Compile)
+            Message::SetAlignBaseline(align) => {
+                self.align_baseline = align;
+                Command::none()
             }
-            Message::SetDpi(dpi) => {
-                if dpi.is_empty() {
-                    self.dpi = 0;
-                } else if let Ok(dpi) = dpi.parse() {
-                    self.dpi = dpi;
-                }
-                self.update(Message::Compile)
+            Message::CompileAndCopy => {
+                self.copy_after_compile = true;
+                self.update(Message::Render)
             }
-            Message::OutDir(dir) => {
-                // println!("dir = {:?}", dir);
-                self.out_dir = dir.into();
-                // don't copy the file eagerly, wait for user to request re-compile cuz otherwise it
-                //  will try to copy to each non-existent directory as they type the full thing in
-                //  and will successfully copy to each subdirectory which is no good
+            Message::ImageCopied(Ok(())) => {
+                self.toast = Some("Copied the image to the clipboard");
                 Command::none()
             }
-            Message::OpenExplorer => {
-                self.folder_icon = Icon::Folder2Open;
-                Command::perform(
-                    AsyncFileDialog::new().pick_folder(),
-                    |fh: Option<FileHandle>| Message::PickedDir(fh.map(|fh| fh.path().to_path_buf())),
-                )
+            Message::HideToast => {
+                self.toast = None;
+                Command::none()
             }
-            Message::PickedDir(dir) => {
-                self.folder_icon = Icon::Folder2;
-                if let Some(dir) = dir {
-                    self.out_dir = dir;
+            Message::NotificationExpire => {
+                if self.notification.as_ref().is_some_and(|(_, shown)| shown.elapsed() >= NOTIFICATION_DURATION) {
+                    self.notification = None;
                 }
                 Command::none()
             }
-            Message::FontLoaded => {
+            Message::ImageCopied(res) | Message::Opened(res) => {
+                if let Err(e) = res {
+                    self.state = State::Errored(e);
+                }
                 Command::none()
             }
-            Message::SetBackend(backend) => {
-                self.backend = backend;
-                self.update(Message::Compile)
+            Message::CopyBothSyntaxes => {
+                // fill in whichever one hasn't been written by translating the other
+                let latex = match (&*self.latex_eq, self.backend) {
+                    ("", Backend::Typst) => translate::typst_to_latex(&self.typst_eq),
+                    (latex, _) => latex.to_string(),
+                };
+                let typst = match (&*self.typst_eq, self.backend) {
+                    ("", Backend::LaTeX) => translate::latex_to_typst(&self.latex_eq),
+                    (typst, _) => typst.to_string(),
+                };
+                iced::clipboard::write(format!("LaTeX:\n{latex}\n\nTypst:\n{typst}"))
+            }
+            Message::CloseRequested => {
+                self.save_settings();
+                if self.dirty && !is_blank(self.eq(), self.backend) {
+                    Command::perform(
+                        AsyncMessageDialog::new()
+                            .set_level(MessageLevel::Warning)
+                            .set_title("Unsaved changes")
+                            .set_description("The current equation hasn't been exported. Export it before quitting?")
+                            .set_buttons(MessageButtons::YesNoCancel)
+                            .show(),
+                        Message::ConfirmQuit,
+                    )
+                } else {
+                    window::close(window::Id::MAIN)
+                }
             }
+            Message::ConfirmQuit(result) => match result {
+                MessageDialogResult::Yes => {
+                    // cleared if the equation can't be compiled, see `Gui::reject_compile`
+                    self.quit_after_export = true;
+                    self.update(Message::Compile)
+                }
+                MessageDialogResult::No => window::close(window::Id::MAIN),
+                _ => Command::none(),
+            },
         }
     }
 
@@ -434,39 +2925,800 @@ impl Application for Gui {
                     &self.dpi.to_string()
                 ).width(100.0)
                  .on_input(Message::SetDpi),
+                6,
+                pick_list(
+                    &PngDepth::ALL[..],
+                    Some(self.post.depth),
+                    Message::SetDepth,
+                ),
+                6,
+                pick_list(
+                    &ColorProfile::ALL[..],
+                    Some(self.post.profile),
+                    Message::SetProfile,
+                ),
+                6,
+                tooltip(
+                    row![
+                        text_input("canvas", &self.canvas_size)
+                            .width(90)
+                            .on_input(Message::SetCanvasSize),
+                        text(" at "),
+                        text_input("0,0", &self.canvas_offset)
+                            .width(70)
+                            .on_input(Message::SetCanvasOffset),
+                    ].align_items(Alignment::Center),
+                    "Place the image on a transparent canvas like 800x600, with its top left corner at x,y",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                if self.post.profile == ColorProfile::Icc {
+                    row![
+                        text_input(
+                            "profile.icc",
+                            &self.post.icc_path.to_string_lossy(),
+                        ).width(160)
+                         .on_input(Message::SetIccPath)
+                         .on_submit(Message::Compile),
+                    ]
+                } else {
+                    row!()
+                },
+            ].spacing(4)
+             .align_items(Alignment::Center)
+        } else {
+            row!()
+        };
+        let current_color = if self.format == ImageFormat::Svg {
+            row![
+                6,
+                tooltip(
+                    checkbox("currentColor", self.current_color)
+                        .on_toggle(Message::SetCurrentColor),
+                    "Use `currentColor` for the fill so css can set the color.\nThe preview shows it in the theme's text color.",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                6,
+                tooltip(
+                    checkbox("+ png", self.secondary_png)
+                        .on_toggle(Message::SetSecondaryPng),
+                    text(format!("Also export a {} dpi png made from the svg", self.dpi)),
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                6,
+                tooltip(
+                    pick_list(
+                        &SvgFonts::ALL[..],
+                        Some(self.svg_fonts),
+                        Message::SetSvgFonts,
+                    ),
+                    "LaTeX only. Paths look the same everywhere, but make long equations bigger and\ntheir text can't be selected. Fonts keep the text as text, using woff2 fonts of\njust the glyphs it needs, embedded in the svg or written next to it.\nThe preview can't show fonts",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                6,
+                pick_list(
+                    &SvgSize::ALL[..],
+                    Some(self.post.size),
+                    Message::SetSvgSize,
+                ),
+                6,
+                tooltip(
+                    checkbox("No prolog", self.post.strip_prolog)
+                        .on_toggle(Message::SetStripProlog),
+                    "Remove the <?xml ...?> declaration and doctype, for programs that can't parse them",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
             ]
         } else {
             row!()
         };
+        let recent = if self.recent.is_empty() {
+            row!()
+        } else {
+            self.recent.iter()
+                .enumerate()
+                .fold(row!(), |row, (i, recent)| row.push(
+                    button(text(recent.label()).size(12))
+                        .style(iced::theme::Button::Secondary)
+                        .padding([2, 6])
+                        .on_press(Message::LoadRecent(i))
+                ))
+                .spacing(4)
+                .padding([4, 0, 0, 0])
+                .align_items(Alignment::Center)
+        };
+        // LaTeX's document setup is edited as source, Typst's font is picked and only its imports
+        //  are edited
+        let document = if self.backend == Backend::LaTeX {
+            let preamble = self.preamble();
+            let toggle = row![
+                button(text(if self.show_preamble { "Hide preamble" } else { "Preamble" }).size(12))
+                    .style(iced::theme::Button::Secondary)
+                    .padding([2, 6])
+                    .on_press(Message::TogglePreamble),
+                tooltip(
+                    checkbox("exact bounding box", self.exact_bbox)
+                        .text_size(12)
+                        .size(14)
+                        .on_toggle(Message::SetExactBbox),
+                    "Fit the image to the glyphs' outlines instead of the fonts' metrics. Turn off if parts of the equation are clipped",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                match latex::check_preamble(&preamble) {
+                    Ok(()) if preamble == latex::DEFAULT_PREAMBLE => text(""),
+                    Ok(()) => text("edited")
+                        .size(12)
+                        .style(Color::from_rgb(0.5, 0.5, 0.5)),
+                    Err(e) => text(e)
+                        .size(12)
+                        .style(Color::from_rgb(0.95, 0.4, 0.4)),
+                },
+            ].spacing(6)
+                .align_items(Alignment::Center);
+            if self.show_preamble {
+                col![
+                    toggle,
+                    text_editor(&self.preamble)
+                        .font(Font::MONOSPACE)
+                        .height(120)
+                        .on_action(Message::PreambleAction),
+                    tooltip(
+                        button(text("Reset to default").size(12))
+                            .style(iced::theme::Button::Secondary)
+                            .padding([2, 6])
+                            .on_press_maybe((preamble != latex::DEFAULT_PREAMBLE).then_some(Message::ResetPreamble)),
+                        "Go back to the packages this app starts with",
+                        tooltip::Position::Bottom,
+                    ).style(iced::theme::Container::Box),
+                ].spacing(4)
+                    .padding([4, 0, 0, 0])
+            } else {
+                col![toggle].padding([4, 0, 0, 0])
+            }
+        } else {
+            let imports = self.preamble();
+            let settings = row![
+                text("Font: ").size(12),
+                pick_list(
+                    &MathFont::ALL[..],
+                    Some(self.typst_font),
+                    Message::SetTypstFont,
+                ).text_size(12)
+                    .padding([2, 6]),
+                6,
+                text("size: ").size(12),
+                text_input("11", &self.typst_font_size)
+                    .size(12)
+                    .padding([2, 6])
+                    .width(50)
+                    .on_input(Message::SetTypstFontSize),
+                text("pt").size(12),
+                6,
+                button(text(if self.show_preamble { "Hide imports" } else { "Imports" }).size(12))
+                    .style(iced::theme::Button::Secondary)
+                    .padding([2, 6])
+                    .on_press(Message::TogglePreamble),
+                if imports == typst::DEFAULT_IMPORTS {
+                    text("")
+                } else {
+                    text("edited")
+                        .size(12)
+                        .style(Color::from_rgb(0.5, 0.5, 0.5))
+                },
+            ].spacing(4)
+                .align_items(Alignment::Center);
+            if self.show_preamble {
+                col![
+                    settings,
+                    text_editor(&self.typst_imports)
+                        .font(Font::MONOSPACE)
+                        .height(120)
+                        .on_action(Message::PreambleAction),
+                    tooltip(
+                        button(text("Reset to default").size(12))
+                            .style(iced::theme::Button::Secondary)
+                            .padding([2, 6])
+                            .on_press_maybe((imports != typst::DEFAULT_IMPORTS).then_some(Message::ResetPreamble)),
+                        "Go back to the packages this app starts with",
+                        tooltip::Position::Bottom,
+                    ).style(iced::theme::Container::Box),
+                ].spacing(4)
+                    .padding([4, 0, 0, 0])
+            } else {
+                col![settings].padding([4, 0, 0, 0])
+            }
+        };
+        let color_picker = self.color_picker.map_or_else(
+            || row!(),
+            |rgba| ["r", "g", "b", "a"].into_iter()
+                .enumerate()
+                .fold(row!().spacing(4).padding([4, 0, 0, 0]).align_items(Alignment::Center), |row, (channel, name)| row
+                    .push(text(format!("{name}: ")))
+                    .push(slider(0..=255, rgba[channel], move |value| Message::PickColor(channel, value))
+                        .on_release(Message::Compile)
+                        .width(120))
+                    .push(text(rgba[channel].to_string()).width(30))),
+        );
+        let has_swatch = self.swatches.iter().any(|swatch| swatch.color == self.color());
+        let swatches = self.swatches.iter()
+            .enumerate()
+            .fold(row![6], |row, (i, swatch)| row.push(
+                swatch.fill.map_or_else(
+                    || button(text(&swatch.color).size(12))
+                        .style(iced::theme::Button::Secondary)
+                        .padding([2, 6]),
+                    |fill| button("")
+                        .style(iced::theme::Button::custom(style::Swatch(fill)))
+                        .width(20)
+                        .height(20),
+                ).on_press(Message::PickSwatch(i))
+            ))
+            .push(tooltip(
+                button(text(if has_swatch { "−" } else { "+" }).size(12))
+                    .style(iced::theme::Button::Secondary)
+                    .padding([2, 6])
+                    .on_press(if has_swatch { Message::RemoveSwatch } else { Message::AddSwatch }),
+                if has_swatch { "Remove the current color from the palette" } else { "Add the current color to the palette" },
+                tooltip::Position::Bottom,
+            ).style(iced::theme::Container::Box))
+            .spacing(4)
+            .align_items(Alignment::Center);
+        let warning = self.warning.as_ref()
+            .map_or_else(
+                || row!(),
+                |warning| row![
+                    text(format!("Warning: {warning}"))
+                        .size(14)
+                        .style(Color::from_rgb(0.95, 0.75, 0.3)),
+                ].padding([4, 0, 0, 0]),
+            );
+        let status = self.toast
+            .map(str::to_string)
+            .or_else(|| (!self.last_exports.is_empty()).then(|| {
+                let exports = self.last_exports.iter()
+                    .map(|Export { path, size }| format!("{} ({})", path.display(), file_size(*size)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Exported {exports}")
+            }))
+            .map_or_else(
+                || row!(),
+                |status| row![
+                    Fill,
+                    text(status)
+                        .size(12),
+                ],
+            );
+        let failed_exports = self.failed_exports.iter()
+            .fold(col![], |col, e| col.push(row![
+                Fill,
+                text(e)
+                    .size(12)
+                    .style(Color::from_rgb(0.95, 0.4, 0.4)),
+            ]));
+        let compatibility = match self.extra_args.check_compatible(self.backend) {
+            Ok(()) => text("Tools in gray aren't used for this backend and format").size(12),
+            Err(e) => text(e.to_string())
+                .size(12)
+                .style(Color::from_rgb(0.95, 0.4, 0.4)),
+        };
+        let settings = if self.show_settings {
+            Tool::ALL.into_iter()
+                .fold(
+                    col![text("Extra arguments").size(14)].spacing(4),
+                    |col, tool| col.push(row![
+                        if tool.is_used(self.backend, self.format) {
+                            text(format!("{}: ", tool.name()))
+                        } else {
+                            text(format!("{}: ", tool.name()))
+                                .style(Color::from_rgb(0.5, 0.5, 0.5))
+                        }.width(80),
+                        text_input(
+                            "",
+                            self.extra_args.get(tool),
+                        ).on_input(move |args| Message::SetExtraArgs(tool, args))
+                         .on_submit(Message::Compile),
+                    ].align_items(Alignment::Center)),
+                )
+                .push(compatibility)
+                .push(row![
+                    tooltip(
+                        checkbox("Crop to content", self.crop)
+                            .on_toggle(Message::SetCrop),
+                        "Pngs only: remove the transparent border, so the equation lines up with the text\nnext to it. Some padding keeps the edges of the glyphs from being cut off",
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                    Fill,
+                    text("padding: "),
+                    text_input("2", &self.crop_padding)
+                        .width(50)
+                        .on_input(Message::SetCropPadding),
+                    text("px"),
+                ].spacing(4)
+                 .align_items(Alignment::Center))
+                .push(row![
+                    tooltip(
+                        checkbox("Drop shadow", self.shadow)
+                            .on_toggle(Message::SetShadow),
+                        "Draw a blurred copy of the equation under it, to stand out on busy backgrounds",
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                    Fill,
+                    text("offset: "),
+                    text_input("2,2", &self.shadow_offset)
+                        .width(60)
+                        .on_input(Message::SetShadowOffset),
+                    text("blur: "),
+                    text_input("0", &self.shadow_blur)
+                        .width(50)
+                        .on_input(Message::SetShadowBlur),
+                    text("color: "),
+                    text_input("black", &self.shadow_color)
+                        .width(100)
+                        .on_input(Message::SetShadowColor)
+                        .on_submit(Message::Compile),
+                ].spacing(4)
+                 .align_items(Alignment::Center))
+                .push(text("Light & dark export").size(14))
+                .push(Variant::ALL.into_iter()
+                    .fold(
+                        row![].spacing(4).align_items(Alignment::Center),
+                        |row, variant| row.push(text(format!("{}: ", variant.name())))
+                            .push(text_input(
+                                "color",
+                                self.theme_pair.get(variant),
+                            ).on_input(move |color| Message::SetPairColor(variant, color))),
+                    )
+                    .push(text("names: "))
+                    .push(text_input(
+                        "{name}-{theme}",
+                        &self.theme_pair.template,
+                    ).on_input(Message::SetPairTemplate)))
+                .push(row![
+                    text("Share by: "),
+                    pick_list(
+                        &ShareMethod::ALL[..],
+                        Some(self.share_method),
+                        Message::SetShareMethod,
+                    ),
+                ].align_items(Alignment::Center))
+                .push(row![
+                    text("Html snippet: "),
+                    pick_list(
+                        &HtmlStyle::ALL[..],
+                        Some(self.html_style),
+                        Message::SetHtmlStyle,
+                    ),
+                    6,
+                    tooltip(
+                        checkbox("Align to the text's baseline", self.align_baseline)
+                            .on_toggle(Message::SetAlignBaseline),
+                        "Add a vertical-align so the equation lines up with the text around it, svgs only",
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                    6,
+                    text(self.baseline.map_or_else(
+                        || "baseline: unknown".into(),
+                        |depth| format!("baseline: {depth:.2}pt above the bottom"),
+                    )).size(12),
+                ].align_items(Alignment::Center))
+                .push(row![
+                    text("LaTeX width: "),
+                    text_input("the image's width", &self.include_width)
+                        .width(160)
+                        .on_input(Message::SetIncludeWidth)
+                        .on_submit(Message::CopyInclude),
+                    tooltip(
+                        button(text(r"Copy \includegraphics"))
+                            .style(iced::theme::Button::Secondary)
+                            .on_press(Message::CopyInclude),
+                        "Copy a line that includes the exported file in a LaTeX document",
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                    tooltip(
+                        button(text("Copy TikZ"))
+                            .style(iced::theme::Button::Secondary)
+                            .on_press_maybe((self.backend == Backend::LaTeX && !is_blank(&self.latex_eq, Backend::LaTeX)).then_some(Message::CopyTikz)),
+                        "Copy the LaTeX equation in a TikZ node, to edit and style in the document instead of an image",
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                ].spacing(4)
+                 .align_items(Alignment::Center))
+                .push(text("Contact sheet").size(14))
+                .push(row![
+                    text("columns: "),
+                    text_input("3", &self.contact_sheet.columns.to_string())
+                        .width(60)
+                        .on_input(Message::SetSheetColumns),
+                    text("padding: "),
+                    text_input("0", &self.contact_sheet.padding.to_string())
+                        .width(60)
+                        .on_input(Message::SetSheetPadding),
+                    checkbox("Labels", self.contact_sheet.labels)
+                        .on_toggle(Message::SetSheetLabels),
+                    Fill,
+                    tooltip(
+                        button("Export contact sheet")
+                            .on_press_maybe((!self.thumbnails.is_empty()).then_some(Message::ExportContactSheet)),
+                        text(format!("Write every equation exported this session ({}) to one png", self.thumbnails.len())),
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                ].spacing(4)
+                 .align_items(Alignment::Center))
+                .push(row![
+                    checkbox("Captions", self.pdf.captions)
+                        .on_toggle(Message::SetPdfCaptions),
+                    checkbox("Dark pages", self.pdf.dark_pages)
+                        .on_toggle(Message::SetPdfDarkPages),
+                    Fill,
+                    tooltip(
+                        button("Export pdf")
+                            .on_press_maybe((!self.thumbnails.is_empty()).then_some(Message::ExportPdf)),
+                        text(format!("Write every equation exported this session ({}) to a pdf, one per page", self.thumbnails.len())),
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                ].spacing(4)
+                 .align_items(Alignment::Center))
+                .push(checkbox("Start with an example equation", self.prefill)
+                    .on_toggle(Message::SetPrefill))
+                .push(row![
+                    tooltip(
+                        checkbox("Live LaTeX preview, after ", self.latex_live)
+                            .on_toggle(Message::SetLatexLive),
+                        "Compile LaTeX when you stop typing, like Typst. Off by default since LaTeX can be slow",
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                    text_input("600", &self.latex_idle.as_millis().to_string())
+                        .width(60)
+                        .on_input(Message::SetLatexIdle),
+                    text(" ms idle"),
+                ].align_items(Alignment::Center))
+                .push(checkbox("Keep showing the last image when rendering fails", self.keep_last_render)
+                    .on_toggle(Message::SetKeepLastRender))
+                .push(checkbox("Dim the last image while rendering, instead of a spinner", self.dim_while_compiling)
+                    .on_toggle(Message::SetDimWhileCompiling))
+                .push(checkbox("Copy svgs as markup instead of as a png", self.copy_svg_markup)
+                    .on_toggle(Message::SetCopySvgMarkup))
+                .push(self.extra_out_dirs.iter()
+                    .enumerate()
+                    .fold(
+                        col![row![
+                            text("Also export to").size(14),
+                            Fill,
+                            button("Add directory")
+                                .style(iced::theme::Button::Secondary)
+                                .on_press(Message::AddOutDir),
+                        ].align_items(Alignment::Center)].spacing(4),
+                        |col, (i, dir)| col.push(row![
+                            text_input("directory", dir)
+                                .on_input(move |dir| Message::EditOutDir(i, dir))
+                                .on_submit(Message::Compile),
+                            button("Remove")
+                                .style(iced::theme::Button::Secondary)
+                                .on_press(Message::RemoveOutDir(i)),
+                        ].spacing(4)
+                         .align_items(Alignment::Center)),
+                    ))
+                .push(row![
+                    text("Scratch directory: "),
+                    text_input(&env::temp_dir().to_string_lossy(), &self.scratch_dir)
+                        .on_input(Message::SetScratchDir),
+                ].align_items(Alignment::Center))
+                .push(row![
+                    tooltip(
+                        checkbox("Round decimals to ", self.round_numbers)
+                            .on_toggle(Message::SetRoundNumbers),
+                        "Round numbers like 6.02214076 before rendering, but not ones in scripts or touching letters",
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                    text_input("4", &self.sig_figs.to_string())
+                        .width(40)
+                        .on_input(Message::SetSigFigs),
+                    text(" significant figures"),
+                ].align_items(Alignment::Center))
+                .push(row![
+                    tooltip(
+                        text("Largest png: "),
+                        "Pngs bigger than this are refused instead of rendered, since a huge one can take minutes",
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                    text_input(&backends::DEFAULT_MAX_PNG_SIZE.to_string(), &self.max_png_size.to_string())
+                        .width(80)
+                        .on_input(Message::SetMaxPngSize),
+                    text(" pixels per side"),
+                ].align_items(Alignment::Center))
+                .push(row![
+                    tooltip(
+                        text("Parallel renders: "),
+                        "How many animation frames or manifest entries render at once",
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                    text_input(&backends::default_workers().to_string(), &self.max_workers.to_string())
+                        .width(60)
+                        .on_input(Message::SetMaxWorkers),
+                ].align_items(Alignment::Center))
+                .push(tooltip(
+                    checkbox("Safe mode (for untrusted equations)", self.safe_mode)
+                        .on_toggle(Message::SetSafeMode),
+                    "Stops equations from reading files outside the cache, downloading anything, or running programs.\nExtra arguments are disabled.",
+                    tooltip::Position::Top,
+                ).style(iced::theme::Container::Box))
+                .push(row![
+                    text("Theme: "),
+                    pick_list(
+                        &ThemeMode::ALL[..],
+                        Some(self.theme_mode),
+                        Message::SetTheme,
+                    ),
+                    6,
+                    checkbox("High contrast controls", self.high_contrast)
+                        .on_toggle(Message::SetHighContrast),
+                ].spacing(4)
+                 .align_items(Alignment::Center))
+                .push(row![
+                    text(format!(
+                        "Cache: {}",
+                        self.cache_size.map_or_else(|| "measuring…".into(), file_size),
+                    )),
+                    tooltip(
+                        button(text(if self.clearing_cache { "Clearing…" } else { "Clear cache" }))
+                            .style(iced::theme::Button::Destructive)
+                            .on_press_maybe((!self.clearing_cache && !matches!(self.state, State::Compiling { .. }))
+                                .then_some(Message::ClearCache)),
+                        "Delete every cached equation, they're compiled again when they're next needed",
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                    Fill,
+                    tooltip(
+                        text("Limit: "),
+                        "Once the cache is bigger, the equations used longest ago are deleted. 0 for no limit",
+                        tooltip::Position::Top,
+                    ).style(iced::theme::Container::Box),
+                    text_input("500", &self.cache_budget_mb.to_string())
+                        .width(70)
+                        .on_input(Message::SetCacheBudget),
+                    text(" MB"),
+                ].spacing(8)
+                 .align_items(Alignment::Center))
+                .push(checkbox("Show the last command (for debugging)", self.show_command)
+                    .on_toggle(Message::SetShowCommand))
+                .padding([6, 0, 0, 0])
+        } else {
+            col!()
+        };
+        let history = if self.show_history {
+            let entries = if self.history.entries.is_empty() {
+                col![text("Nothing has been compiled yet").size(14)]
+            } else {
+                self.history.entries.iter()
+                    .enumerate()
+                    .fold(col![].spacing(2), |col, (i, entry)| col.push(
+                        button(row![
+                            text(entry.backend.letter()).size(12).width(16),
+                            text(entry.label()).size(12),
+                            Fill,
+                            text(format!("{} {}", entry.color.as_deref().unwrap_or("default color"), entry.format)).size(12),
+                        ].spacing(6))
+                            .style(iced::theme::Button::Secondary)
+                            .width(Fill)
+                            .padding([2, 6])
+                            .on_press(Message::LoadHistory(i))
+                    ))
+            };
+            col![
+                text("History").size(14),
+                scrollable(entries).height(150),
+            ].spacing(4)
+             .padding([6, 0, 0, 0])
+        } else {
+            col!()
+        };
+        let animation = if self.show_animation {
+            let frames = self.animation.frames.iter()
+                .enumerate()
+                .fold(
+                    col![].spacing(4),
+                    |col, (i, frame)| col.push(row![
+                        text(format!("{}.", i + 1))
+                            .width(24),
+                        text_input(
+                            self.backend.example(),
+                            frame,
+                        ).on_input(move |eq| Message::EditFrame(i, eq))
+                         .on_submit(Message::ExportAnimation),
+                        button("Remove")
+                            .style(iced::theme::Button::Secondary)
+                            .on_press(Message::RemoveFrame(i)),
+                    ].spacing(4)
+                     .align_items(Alignment::Center)),
+                );
+            col![
+                row![
+                    text("Animation").size(14),
+                    Fill,
+                    pick_list(
+                        &AnimationFormat::ALL[..],
+                        Some(self.animation.format),
+                        Message::SetAnimationFormat,
+                    ),
+                    6,
+                    text("ms per frame: "),
+                    text_input(
+                        "ms",
+                        &self.animation.frame_duration.as_millis().to_string(),
+                    ).width(80)
+                     .on_input(Message::SetFrameDuration),
+                ].align_items(Alignment::Center),
+                frames,
+                row![
+                    button("Add frame")
+                        .style(iced::theme::Button::Secondary)
+                        .on_press(Message::AddFrame),
+                    Fill,
+                    button("Export animation")
+                        .on_press(Message::ExportAnimation),
+                ],
+            ].spacing(4)
+             .padding([6, 0, 0, 0])
+        } else {
+            col!()
+        };
+        let last_command = match &self.last_command {
+            Some(last_command) if self.show_command => col![
+                text(format!("Last command, run in {}", last_command.dir.display())).size(14),
+                row![
+                    text_input("", &self.command_line)
+                        .font(Font::MONOSPACE)
+                        .on_input(Message::EditCommand)
+                        .on_submit(Message::RerunCommand),
+                    button("Run")
+                        .on_press(Message::RerunCommand),
+                ].spacing(4),
+                self.rerun_output.as_ref().map_or_else(
+                    || text(""),
+                    |output| text(output).font(Font::MONOSPACE).size(12),
+                ),
+            ].spacing(4)
+             .padding([6, 0, 0, 0]),
+            _ => col!(),
+        };
+        let out_dir = text_input(
+            ".",
+            &self.out_dir.to_string_lossy()
+        ).on_submit(Message::Compile)
+         .id(out_dir_id());
+        // can't be edited while pinned
+        let out_dir = if self.pin_out_dir {
+            out_dir
+        } else {
+            out_dir.on_input(Message::OutDir)
+        };
+        let switch_keys = Backend::ALL.into_iter()
+            .filter_map(|backend| shortcuts::switch_to(backend)
+                .map(|shortcut| format!("{} for {}", shortcut.keys(), backend.stylized())))
+            .join(", ");
+        let color_input = text_input(
+            self.default_color(),
+            self.color.as_deref().unwrap_or_default(),
+        ).on_input(Message::Color)
+         .id(color_id());
+        // catch typos while typing, rather than as a compiler error
+        let (color_input, invalid_color) = if self.color_is_valid() {
+            (color_input.on_submit(Message::Compile), text(""))
+        } else {
+            (
+                color_input.style(iced::theme::TextInput::Custom(Box::new(style::InvalidInput))),
+                text("not a css color")
+                    .size(12)
+                    .style(Color::from_rgb(0.95, 0.4, 0.4)),
+            )
+        };
+        let background = text_input("transparent", self.post.background.as_deref().unwrap_or_default())
+            .width(100)
+            .on_input(Message::SetBackground);
+        let background = if self.post.background.as_deref().is_none_or(|background| color::parse_color(background).is_some()) {
+            background.on_submit(Message::Compile)
+        } else {
+            background.style(iced::theme::TextInput::Custom(Box::new(style::InvalidInput)))
+        };
+        let copies_markup = self.copy_svg_markup && matches!(self.state, State::Svg(_));
         let input_col = col![
             row![
-                text_input(
-                    self.backend.name(),
-                    self.eq(),
-                ).on_input(Message::EditEquation)
-                 .on_submit(Message::Compile)
-                 .id(eq_editor_id()),
+                tooltip(
+                    text(self.backend.stylized())
+                        .size(20)
+                        .width(70),
+                    text(format!("The editor expects {} syntax. Switch with {switch_keys}", self.backend.stylized())),
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                // Enter adds a line, eg for an `align*` block
+                text_editor(&self.editor)
+                    .on_action(Message::EditorAction),
+                tooltip(
+                    pick_list(
+                        &Template::ALL[..],
+                        None::<Template>,
+                        Message::InsertTemplate,
+                    ).placeholder("Insert")
+                        .width(100),
+                    text(format!("Insert a common construct in {} syntax where the cursor is", self.backend.stylized())),
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                button("Render")
+                    .on_press_maybe(self.extra_args.check_compatible(self.backend)
+                        .and_then(|()| self.check_color())
+                        .is_ok()
+                        .then_some(Message::Render)),
+                tooltip(
+                    button("Copy both")
+                        .style(iced::theme::Button::Secondary)
+                        .on_press(Message::CopyBothSyntaxes),
+                    "Copy the equation in both LaTeX and Typst syntax",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                tooltip(
+                    button(if copies_markup { "Copy svg" } else { "Copy png" })
+                        .style(iced::theme::Button::Secondary)
+                        .on_press_maybe(matches!(self.state, State::Svg(_) | State::Png(_))
+                            .then_some(Message::CopyImage)),
+                    text(if copies_markup {
+                        "Copy the svg's markup, without saving it. Copy a png instead in the settings".to_string()
+                    } else {
+                        format!("Copy the image as a {} dpi png, without saving it", self.dpi)
+                    }),
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                tooltip(
+                    button("Copy html")
+                        .style(iced::theme::Button::Secondary)
+                        .on_press(Message::CopyHtml),
+                    text(format!("Copy an html {} for the exported image, set the style in the settings", self.html_style)),
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                tooltip(
+                    button("Light & dark")
+                        .style(iced::theme::Button::Secondary)
+                        .on_press(Message::ExportThemePair),
+                    "Export the equation in both of the light & dark colors from the settings",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
                 button(self.backend.letter())
                     .on_press(Message::SetBackend(self.backend.flip())),
-            ],
+            ].spacing(4),
+            warning,
+            recent,
+            document,
             6,
             row![
                 text("Color: "),
-                text_input(
-                    DEFAULT_COLOR,
-                    self.color.as_deref().unwrap_or_default(),
-                ).on_input(Message::Color)
-                 .on_submit(Message::Compile)
-                 .id(color_id()),
+                color_input,
+                invalid_color,
+                6,
+                tooltip(
+                    button("")
+                        .style(iced::theme::Button::custom(style::Swatch(
+                            self.color_rgba().map_or(Color::TRANSPARENT, |[r, g, b, a]| Color::from_rgba8(r, g, b, f32::from(a) / 255.0)),
+                        )))
+                        .width(28)
+                        .height(28)
+                        .on_press(Message::ToggleColorPicker),
+                    "Pick a color",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                swatches,
                 Fill,
                 text("File: "),
-                text_input(
-                    self.format.default_file_name(),
-                    self.name.as_deref().unwrap_or_default()
-                ).on_input(Message::Name)
-                 .on_submit(Message::Compile)
-                 .id(file_id()),
+                tooltip(
+                    text_input(
+                        self.format.default_file_name(),
+                        self.name.as_deref().unwrap_or_default()
+                    ).on_input(Message::Name)
+                     .on_submit(Message::Compile)
+                     .id(file_id()),
+                    "{color}, {format}, {backend}, and {hash} are filled in when saving, eg integral_{color}",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
             ].align_items(Alignment::Center),
+            color_picker,
             6,
             row![
                 text("Format: "),
@@ -476,19 +3728,94 @@ impl Application for Gui {
                     Message::Format,
                 ),
                 png_density,
+                6,
+                pick_list(
+                    &LineAlignment::ALL[..],
+                    Some(self.alignment),
+                    Message::SetAlignment,
+                ),
+                6,
+                tooltip(
+                    row![
+                        text("scale: "),
+                        text_input("1", &self.scale)
+                            .width(60)
+                            .on_input(Message::SetScale),
+                    ].align_items(Alignment::Center),
+                    "How much bigger to make the equation, eg 1.5",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                current_color,
+                6,
+                checkbox("Invert", self.post.invert)
+                    .on_toggle(Message::SetInvert),
+                6,
+                tooltip(
+                    background,
+                    "Background color, any css color. Leave empty to keep it transparent",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                6,
+                tooltip(
+                    text_input("aspect", &self.aspect)
+                        .width(60)
+                        .on_input(Message::SetAspect),
+                    "Pad the image to an aspect ratio like 1:1 or 16:9",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
                 Fill,
                 text("Directory: "),
-                text_input(
-                    ".",
-                    &self.out_dir.to_string_lossy()
-                ).on_input(Message::OutDir)
-                 .on_submit(Message::Compile)
-                 .id(out_dir_id()),
-                button(
-                    text(Icon::Folder2)
+                out_dir,
+                button(if self.icon_font_failed {
+                    text("Folder")
+                } else {
+                    text(self.folder_icon)
                         .font(ICON_FONT)
-                ).on_press(Message::OpenExplorer),
-            ].align_items(Alignment::Center),
+                }).on_press_maybe((!self.picking_dir && !self.pin_out_dir).then_some(Message::OpenExplorer)),
+                tooltip(
+                    checkbox("Pin", self.pin_out_dir)
+                        .on_toggle(Message::SetPinOutDir),
+                    "Keep exporting to this directory, so it can't be changed by accident",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                tooltip(
+                    button("Open")
+                        .style(iced::theme::Button::Secondary)
+                        .on_press_maybe((!self.last_exports.is_empty()).then_some(Message::OpenLastExport)),
+                    "Open the last exported file in its default app",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                tooltip(
+                    button("Show folder")
+                        .style(iced::theme::Button::Secondary)
+                        .on_press(Message::RevealOutput),
+                    "Open the output directory in the file manager",
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                tooltip(
+                    button("Share")
+                        .style(iced::theme::Button::Secondary)
+                        .on_press_maybe((!self.last_exports.is_empty()).then_some(Message::ShareLastExport)),
+                    text(format!("Share the last exported file by {}, change how in the settings", self.share_method)),
+                    tooltip::Position::Bottom,
+                ).style(iced::theme::Container::Box),
+                button("History")
+                    .on_press(Message::ToggleHistory),
+                button("Animate")
+                    .on_press(Message::ToggleAnimation),
+                button("Settings")
+                    .on_press(Message::ToggleSettings),
+                button("?")
+                    .style(iced::theme::Button::Secondary)
+                    .on_press(Message::ToggleShortcuts),
+            ].spacing(4)
+             .align_items(Alignment::Center),
+            status,
+            failed_exports,
+            history,
+            settings,
+            last_command,
+            animation,
             horizontal_rule(20),
         ].width(FillPortion(3));
         let row = row![
@@ -496,37 +3823,197 @@ impl Application for Gui {
             input_col,
             Fill
         ];
-        let content = self.state.content(&self.compiled_color, self.backend);
+        let content = if self.show_shortcuts {
+            shortcut_reference()
+        } else {
+            self.state.content(&self.compiled_stem(), self.dim_while_compiling, self.show_error_details, self.zoom)
+        };
+        let stale_error = if self.stale_error.is_some() {
+            row![
+                Fill,
+                tooltip(
+                    button(text("Failed to render").size(12))
+                        .style(iced::theme::Button::Destructive)
+                        .on_press(Message::ShowStaleError),
+                    "Showing the last image that rendered. Click to see the error",
+                    tooltip::Position::Left,
+                ).style(iced::theme::Container::Box),
+            ].padding([0, 8])
+        } else {
+            row!()
+        };
+        // only once the spinner shows, like it, since most compiles are done before it could be clicked
+        let cancel = match self.state {
+            State::Compiling { since, .. } if since.elapsed() >= SPINNER_DELAY => row![
+                Fill,
+                button(text("Cancel").size(14))
+                    .style(iced::theme::Button::Secondary)
+                    .on_press(Message::CancelCompile),
+                Fill,
+            ].padding([0, 8]),
+            _ => row!(),
+        };
+        let reset_zoom = if self.zoom.is_reset() || !matches!(self.state, State::Svg(_) | State::Png(_)) {
+            row!()
+        } else {
+            row![
+                Fill,
+                text(format!("{:.0}%", self.zoom.zoom * 100.0)).size(14),
+                button(text("Reset view").size(14))
+                    .style(iced::theme::Button::Secondary)
+                    .on_press(Message::ResetZoom),
+                Fill,
+            ].spacing(8)
+                .padding([0, 8])
+                .align_items(Alignment::Center)
+        };
+        let notification = self.notification.as_ref().map_or_else(
+            || row!(),
+            |(message, shown)| {
+                // fully visible, then eased out over the last part of its duration
+                let fade_start = NOTIFICATION_DURATION.saturating_sub(NOTIFICATION_FADE);
+                let faded = shown.elapsed().saturating_sub(fade_start).as_secs_f32() / NOTIFICATION_FADE.as_secs_f32();
+                let opacity = 1.0 - easing::STANDARD.y_at_x(faded.min(1.0));
+                row![
+                    Fill,
+                    container(text(message).size(14))
+                        .padding([4, 10])
+                        .style(iced::theme::Container::Custom(Box::new(style::Notification(opacity)))),
+                    Fill,
+                ].padding([0, 8])
+            },
+        );
+        let missing_tools = if self.missing_tools.is_empty() {
+            row!()
+        } else {
+            let missing = self.missing_tools.iter()
+                .map(|tool| format!("{} ({})", tool.name(), tool.install_hint()))
+                .join(", ");
+            row![
+                Fill,
+                text(format!("Couldn't find {missing}"))
+                    .size(14)
+                    .style(Color::from_rgb(0.95, 0.4, 0.4)),
+                button(text("Dismiss").size(14))
+                    .style(iced::theme::Button::Secondary)
+                    .on_press(Message::DismissMissingTools),
+                Fill,
+            ].spacing(8)
+             .padding([0, 8])
+             .align_items(Alignment::Center)
+        };
+        let suggestion = matches!(self.state, State::Errored(_))
+            .then(|| check::other_syntax(self.eq(), self.backend))
+            .flatten()
+            .map_or_else(
+                || row!(),
+                |other| row![
+                    Fill,
+                    text(format!("This looks like {} syntax", other.stylized())).size(14),
+                    tooltip(
+                        button(text(format!("Switch to {}", other.stylized())).size(14))
+                            .on_press(Message::SwitchSyntax(other)),
+                        text(format!("Move the equation to {} and compile it", other.stylized())),
+                        tooltip::Position::Left,
+                    ).style(iced::theme::Container::Box),
+                    Fill,
+                ].spacing(8)
+                 .padding([0, 8])
+                 .align_items(Alignment::Center),
+            );
 
-        container(col![row, content])
+        container(col![row, missing_tools, stale_error, suggestion, cancel, reset_zoom, notification, content])
             .align_x(Horizontal::Center)
             .align_y(Vertical::Top)
             .into()
     }
 
     fn theme(&self) -> Theme {
-        Theme::Dark
+        if self.high_contrast {
+            style::HIGH_CONTRAST.clone()
+        } else if self.theme_mode.is_dark(self.system_dark) {
+            Theme::Dark
+        } else {
+            Theme::Light
+        }
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
         // const NONE: Modifiers = Modifiers::empty();
         // const CMD_SHIFT: Modifiers = Modifiers::COMMAND | Modifiers::SHIFT;
 
-        iced::event::listen_with(|event, _status| match event {
+        let events = iced::event::listen_with(|event, status| match event {
             Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. }) => {
-                match (modifiers.command(), modifiers.shift(), key.as_ref()) {
-                    (true, true, Key::Named(Named::Tab)) => Some(Message::FocusPrevious),
-                    (true, _, Key::Named(Named::Tab)) => Some(Message::FocusNext),
-                    (true, _, Key::Character("L")) => Some(Message::SetBackend(Backend::LaTeX)),
-                    (true, _, Key::Character("T")) => Some(Message::SetBackend(Backend::Typst)),
-                    _ => None,
-                }
+                shortcuts::find(&key.as_ref(), modifiers, status)
             }
+            Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => Some(Message::ModifiersChanged(modifiers)),
+            Event::Window(_, window::Event::CloseRequested) => Some(Message::CloseRequested),
+            Event::Window(_, window::Event::FileDropped(path)) => Some(Message::FileDropped(path)),
             _ => None,
-        })
+        });
+
+        // redraw once the spinner should be shown, after that it animates itself
+        let spinner = if matches!(self.state, State::Compiling { .. }) {
+            iced::time::every(SPINNER_DELAY).map(|_| Message::SpinnerDelay)
+        } else {
+            Subscription::none()
+        };
+
+        let toast = if self.toast.is_some() {
+            iced::time::every(TOAST_DURATION).map(|_| Message::HideToast)
+        } else {
+            Subscription::none()
+        };
+
+        let notification = if self.notification.is_some() {
+            iced::time::every(NOTIFICATION_FRAME).map(|_| Message::NotificationExpire)
+        } else {
+            Subscription::none()
+        };
+
+        // check a few times per idle period, so the compile starts soon after editing stops
+        let edit_idle = if self.edited_at.is_some() {
+            iced::time::every((self.idle() / 4).max(Duration::from_millis(25))).map(|_| Message::EditIdle)
+        } else {
+            Subscription::none()
+        };
+
+        // rather than on every change, so typing in a field doesn't write the file on each key
+        let save = iced::time::every(SETTINGS_SAVE_INTERVAL).map(|_| Message::SaveSettings);
+
+        Subscription::batch([events, spinner, toast, notification, edit_idle, save])
     }
 }
 
+/// Lists every shortcut in [`shortcuts::SHORTCUTS`], shown in place of the preview
+fn shortcut_reference<'a>() -> Container<'a, Message> {
+    let list = shortcuts::SHORTCUTS.iter()
+        .fold(
+            col![text("Keyboard shortcuts").size(20)].spacing(6),
+            |col, shortcut| col.push(row![
+                text(shortcut.keys())
+                    .font(Font::MONOSPACE)
+                    .width(160),
+                text(shortcut.description),
+            ]),
+        );
+    let card = container(col![
+        list,
+        12,
+        row![
+            Fill,
+            button("Close")
+                .on_press(Message::HideShortcuts),
+        ],
+    ]).padding(16)
+        .width(420)
+        .style(iced::theme::Container::Box);
+    container(card)
+        .width(Fill)
+        .padding(20)
+        .center_x()
+}
+
 static CACHE_DIR: Lazy<PathBuf> = Lazy::new(|| {
     let path = dirs::data_local_dir()
         .expect("unsupported os?")
@@ -539,3 +4026,297 @@ pub fn get_dir(hash: u64) -> Dir {
     let hash_dir = format!("latex_{hash}");
     CACHE_DIR.join(hash_dir)
 }
+
+fn get_typst_dir(hash: u64) -> Dir {
+    let hash_dir = format!("typst_{hash}");
+    CACHE_DIR.join(hash_dir)
+}
+
+/// How many bytes everything in [`CACHE_DIR`] takes up
+fn cache_size() -> io::Result<u64> {
+    dir_size(&CACHE_DIR)
+}
+
+/// How many bytes the files in `dir` and its subdirectories take up
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    fs::read_dir(dir)?.try_fold(0, |size, entry| {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let entry_size = if metadata.is_dir() { dir_size(&entry.path())? } else { metadata.len() };
+        Ok(size + entry_size)
+    })
+}
+
+/// Every equation's directory in [`CACHE_DIR`], see [`get_dir`] and [`get_typst_dir`]
+fn cached_equations() -> io::Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(&*CACHE_DIR)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if entry.file_type()?.is_dir() && (name.starts_with("latex_") || name.starts_with("typst_")) {
+            dirs.push(entry.path());
+        }
+    }
+    Ok(dirs)
+}
+
+/// Deletes every equation's directory in [`CACHE_DIR`]
+fn clear_cache() -> io::Result<()> {
+    cached_equations()?
+        .into_iter()
+        .try_for_each(fs::remove_dir_all)
+}
+
+/// Written in an equation's directory whenever it's used, so its modification time is when it was
+/// last used. A cache hit doesn't write anything else there
+const LAST_USED: &str = "last_used";
+
+/// When the equation in `dir` was last used, see [`LAST_USED`]
+fn last_used(dir: &Path) -> SystemTime {
+    fs::metadata(dir.join(LAST_USED))
+        .or_else(|_| fs::metadata(dir))
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Marks `used` as just used, then deletes the least recently used equations until [`CACHE_DIR`]
+/// takes up at most `budget` bytes, returning how much it does. `used` is never deleted, and a
+/// `budget` of 0 is unlimited.
+fn evict_cache(used: &Path, budget: u64) -> io::Result<u64> {
+    if used.is_dir() {
+        fs::write(used.join(LAST_USED), "")?;
+    }
+    let mut size = cache_size()?;
+    if budget == 0 || size <= budget {
+        return Ok(size);
+    }
+
+    let mut equations = cached_equations()?
+        .into_iter()
+        .filter(|dir| dir != used)
+        .map(|dir| (last_used(&dir), dir))
+        .collect::<Vec<_>>();
+    equations.sort();
+    for (_, dir) in equations {
+        if size <= budget {
+            break;
+        }
+        let dir_size = dir_size(&dir)?;
+        fs::remove_dir_all(&dir)?;
+        size = size.saturating_sub(dir_size);
+    }
+    Ok(size)
+}
+
+/// Measures [`cache_size`] off the ui thread, since a big cache has a lot of files
+fn measure_cache() -> Command<Message> {
+    Command::perform(
+        async {
+            tokio::task::spawn_blocking(cache_size)
+                .await
+                .expect("measuring the cache doesn't panic")
+                .ok()
+        },
+        Message::CacheSize,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::InMemory;
+
+    /// With the same equation for both backends, and the default settings rather than whatever this
+    /// machine has saved
+    fn gui(eq: &str) -> Gui {
+        let mut gui = Gui::from_settings(Settings::default(), History::default());
+        gui.latex_eq = eq.into();
+        gui.typst_eq = eq.into();
+        gui
+    }
+
+    #[test]
+    fn exports_go_through_each_sink() {
+        let from = env::temp_dir().join(format!("typset_image_sink_test_{}.svg", std::process::id()));
+        fs::write(&from, "<svg/>").unwrap();
+        let memory = InMemory::default();
+        let sinks: Vec<Box<dyn OutputSink>> = vec![Box::new(memory.clone())];
+
+        let exports = write_to(&sinks, &from, Path::new("eq.svg"));
+        fs::remove_file(&from).unwrap();
+        let [Ok(Export { path, size })] = exports.as_slice() else {
+            panic!("expected one export, got {exports:?}");
+        };
+        assert_eq!(path, Path::new("eq.svg"));
+        assert_eq!(*size, 6);
+        let written = memory.files.lock().unwrap().get(Path::new("eq.svg")).cloned();
+        assert_eq!(written.as_deref(), Some(&b"<svg/>"[..]));
+
+        // the error says where the file was going
+        let exports = write_to(&sinks, &from, Path::new("eq.svg"));
+        assert!(matches!(exports.as_slice(), [Err(GuiError::CopyFile(_, to))] if to == "eq.svg"));
+    }
+
+    #[test]
+    fn staged_exports_go_to_every_output_directory() {
+        let out = TempDir::new("staged_test_").unwrap();
+        let mut gui = gui("x^2");
+        // neither exists yet, like a fresh `out_dir`
+        gui.out_dir = out.path().join("out");
+        gui.extra_out_dirs = vec![out.path().join("extra").to_string_lossy().to_string()];
+        let sheet = gui.create_staging_dir().unwrap().join("contact_sheet.png");
+        fs::write(&sheet, "png").unwrap();
+
+        gui.export_staged(&[sheet]);
+        assert!(gui.failed_exports.is_empty(), "{:?}", gui.failed_exports);
+        assert_eq!(gui.last_exports.len(), 2);
+        for dir in ["out", "extra"] {
+            let exported = out.path().join(dir).join("contact_sheet.png");
+            assert_eq!(fs::read_to_string(exported).unwrap(), "png");
+        }
+    }
+
+    #[test]
+    fn pair_colors_are_checked() {
+        let mut gui = gui("x^2");
+        gui.backend = Backend::LaTeX;
+        assert!(gui.check_pair_colors().is_ok());
+        gui.theme_pair.dark = "not a color".into();
+        assert!(matches!(gui.check_pair_colors(), Err(GuiError::InvalidColor(color)) if color == "not a color"));
+        gui.backend = Backend::Typst;
+        assert!(gui.check_pair_colors().is_ok());
+        gui.theme_pair.light = " ".into();
+        assert!(gui.check_pair_colors().is_err());
+    }
+
+    #[test]
+    fn only_requested_compiles_are_recorded() {
+        let mut typing = gui("x^2");
+        // as if the live preview compiled while another compile was running
+        typing.compiling = true;
+        let _ = typing.update(Message::Compile);
+        assert!(typing.compile_pending);
+        assert!(!typing.record_requested);
+
+        let _ = typing.update(Message::Render);
+        assert!(typing.record_requested);
+
+        // waiting for the backend's version isn't a rejected compile
+        let mut starting = gui("x^2");
+        starting.latex_versions = None;
+        starting.typst_version = None;
+        let _ = starting.update(Message::CompileAndCopy);
+        assert!(starting.compile_pending);
+        assert!(starting.record_requested);
+        assert!(starting.copy_after_compile);
+
+        // nothing is recorded for an equation that can't compile
+        let mut blank = gui("");
+        let _ = blank.update(Message::CompileAndCopy);
+        assert!(!blank.record_requested);
+        assert!(!blank.copy_after_compile);
+    }
+
+    #[test]
+    fn quitting_waits_for_an_equation_that_compiles() {
+        let mut blank = gui("");
+        let _ = blank.update(Message::ConfirmQuit(MessageDialogResult::Yes));
+        assert!(!blank.quit_after_export);
+
+        let mut starting = gui("x^2");
+        starting.latex_versions = None;
+        starting.typst_version = None;
+        let _ = starting.update(Message::ConfirmQuit(MessageDialogResult::Yes));
+        assert!(starting.quit_after_export);
+    }
+
+    #[test]
+    fn hash_depends_on_backend() {
+        let mut gui = gui("x^2");
+        gui.backend = Backend::LaTeX;
+        let latex = gui.equation_hash();
+        gui.backend = Backend::Typst;
+        assert_ne!(latex, gui.equation_hash());
+    }
+
+    #[test]
+    fn hash_depends_on_the_backends_equation() {
+        let mut gui = gui("x^2");
+        gui.backend = Backend::Typst;
+        let before = gui.equation_hash();
+        gui.latex_eq = "y^2".into();
+        assert_eq!(before, gui.equation_hash());
+        gui.typst_eq = "y^2".into();
+        assert_ne!(before, gui.equation_hash());
+    }
+
+    #[test]
+    fn hash_ignores_color_and_format() {
+        let mut gui = gui("x^2");
+        for backend in Backend::ALL {
+            gui.backend = backend;
+            gui.color = Some("white".into());
+            gui.format = ImageFormat::Svg;
+            let before = gui.equation_hash();
+            gui.color = Some("#ff0000".into());
+            gui.format = ImageFormat::Png;
+            assert_eq!(before, gui.equation_hash());
+        }
+    }
+
+    #[test]
+    fn typst_takes_any_color_expression() {
+        let mut gui = gui("x");
+        gui.current_color = false;
+        gui.backend = Backend::Typst;
+        for color in ["luma(50%)", "cmyk(27%, 0%, 3%, 5%)", "red.darken(20%)", "rgb(\"#ff0000\")"] {
+            gui.color = Some(color.into());
+            assert!(gui.color_is_valid(), "{color}");
+        }
+    }
+
+    #[test]
+    fn latex_takes_css_colors() {
+        let mut gui = gui("x");
+        gui.current_color = false;
+        gui.backend = Backend::LaTeX;
+        for color in ["red", "#ff0000", "rgb(255, 0, 0)"] {
+            gui.color = Some(color.into());
+            assert!(gui.color_is_valid(), "{color}");
+        }
+        gui.color = Some("luma(50%)".into());
+        assert!(!gui.color_is_valid());
+    }
+
+    #[test]
+    fn whitespace_is_blank() {
+        for backend in Backend::ALL {
+            assert!(is_blank("", backend));
+            assert!(is_blank("   \n ", backend));
+            assert!(is_blank("\t\r\n", backend));
+        }
+    }
+
+    #[test]
+    fn comments_are_blank() {
+        assert!(is_blank("% just a note", Backend::LaTeX));
+        assert!(is_blank("  % one\n\n% two\n", Backend::LaTeX));
+        assert!(is_blank("// just a note", Backend::Typst));
+        assert!(is_blank("/* one */ // two\n/* three /* nested */ */", Backend::Typst));
+    }
+
+    #[test]
+    fn equations_are_not_blank() {
+        assert!(!is_blank("x", Backend::LaTeX));
+        assert!(!is_blank("% note\n\\frac{1}{2}", Backend::LaTeX));
+        assert!(!is_blank("\\% 50", Backend::LaTeX));
+        assert!(!is_blank("x", Backend::Typst));
+        assert!(!is_blank("// note\nfrac(1, 2)", Backend::Typst));
+        assert!(!is_blank("/* note */ x", Backend::Typst));
+        assert!(!is_blank("a / b", Backend::Typst));
+        // a comment to one backend is an equation to the other
+        assert!(!is_blank("% note", Backend::Typst));
+        assert!(!is_blank("// note", Backend::LaTeX));
+    }
+}
@@ -0,0 +1,93 @@
+//! The equations compiled in earlier sessions, kept in `history.json` next to the settings
+
+use std::fs;
+use std::path::PathBuf;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::backends::Backend;
+use crate::gui::ImageFormat;
+
+/// An equation that was compiled, with what's needed to render it the same way again
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub eq: String,
+    pub backend: Backend,
+    pub color: Option<String>,
+    pub format: ImageFormat,
+}
+
+impl Entry {
+    /// Labels longer than this are truncated with an ellipsis
+    const LABEL_LEN: usize = 60;
+
+    /// The equation on one line, short enough for the history panel
+    pub fn label(&self) -> String {
+        let eq = self.eq.split_whitespace().join(" ");
+        if eq.chars().count() > Self::LABEL_LEN {
+            let eq: String = eq.chars().take(Self::LABEL_LEN - 1).collect();
+            format!("{eq}…")
+        } else {
+            eq
+        }
+    }
+}
+
+/// The most recently compiled equations, newest first
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct History {
+    pub entries: Vec<Entry>,
+}
+
+impl History {
+    /// How many equations are kept for each backend
+    pub const CAPACITY: usize = 20;
+
+    /// Records `entry` as the newest, dropping the oldest equation of its backend if there are too
+    /// many
+    pub fn push(&mut self, entry: Entry) {
+        if let Some(newest) = self.entries.iter().position(|e| e.backend == entry.backend) {
+            // compiling the newest equation again doesn't add it twice
+            if entry.eq == self.entries[newest].eq {
+                self.entries.remove(newest);
+            }
+        }
+        self.entries.insert(0, entry);
+
+        let mut kept = 0;
+        let backend = self.entries[0].backend;
+        self.entries.retain(|e| {
+            if e.backend != backend {
+                return true;
+            }
+            kept += 1;
+            kept <= Self::CAPACITY
+        });
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("typset_image").join("history.json"))
+}
+
+/// The saved history, or none if there isn't any or it can't be read
+pub fn load() -> History {
+    path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Writes `history` next to the settings. Like the settings, failing just means it won't be
+/// remembered
+pub fn save(history: &History) {
+    let Some(path) = path() else { return };
+    let saved = path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, serde_json::to_string_pretty(history).unwrap_or_default()));
+    if let Err(e) = saved {
+        eprintln!("couldn't save the history to {}: {e}", path.display());
+    }
+}
@@ -0,0 +1,78 @@
+//! Ready to paste html for embedding an exported equation in a web page
+
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::set_svg_attribute;
+
+#[derive(Default, Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum HtmlStyle {
+    /// an `<img>` pointing at the exported file
+    #[default]
+    Img,
+    /// the svg itself, so it doesn't need a separate file
+    InlineSvg,
+    /// a `<picture>` switching between the light & dark exports with the page's color scheme
+    Picture,
+}
+
+impl HtmlStyle {
+    pub const ALL: [Self; 3] = [
+        Self::Img,
+        Self::InlineSvg,
+        Self::Picture,
+    ];
+}
+
+impl Display for HtmlStyle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Img => "<img>",
+            Self::InlineSvg => "inline <svg>",
+            Self::Picture => "light & dark <picture>",
+        })
+    }
+}
+
+/// Escapes `s` to be used in an attribute value
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Css that moves an inline image down by `depth` pt, so that its baseline is on the text's
+fn vertical_align(depth: f64) -> String {
+    format!("vertical-align: -{depth:.2}pt")
+}
+
+/// `alt` is the equation's source. With a `depth`, the image is aligned to the text's baseline, see
+/// [`crate::baseline::depth`]
+pub fn img(src: &str, alt: &str, depth: Option<f64>) -> String {
+    let style = depth.map(|depth| format!(r#" style="{}""#, vertical_align(depth)))
+        .unwrap_or_default();
+    format!(r#"<img src="{}" alt="{}"{style}>"#, escape(src), escape(alt))
+}
+
+/// `svg` without its xml prolog, labelled with `alt` for screen readers
+pub fn inline_svg(svg: &str, alt: &str, depth: Option<f64>) -> String {
+    let start = svg.find("<svg").unwrap_or_default();
+    let tag_end = svg[start..].find('>').map_or(svg.len(), |end| start + end + 1);
+    let mut root = set_svg_attribute(&svg[start..tag_end], "role", "img");
+    root = set_svg_attribute(&root, "aria-label", &escape(alt));
+    if let Some(depth) = depth {
+        root = set_svg_attribute(&root, "style", &vertical_align(depth));
+    }
+    format!("{root}{}", svg[tag_end..].trim_end())
+}
+
+/// Shows `dark` when the page prefers a dark color scheme, and `light` otherwise
+pub fn picture(light: &str, dark: &str, alt: &str, depth: Option<f64>) -> String {
+    format!(
+        r#"<picture><source srcset="{}" media="(prefers-color-scheme: dark)">{}</picture>"#,
+        escape(dark),
+        img(light, alt, depth),
+    )
+}
@@ -1,87 +1,268 @@
-use std::env;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use tempdir::TempDir;
 use tokio::fs;
-use crate::gui::Dir;
+use crate::gui::{Dir, ImageFormat};
 
-use crate::{backends, GuiError};
+use crate::{backends, color, GuiError};
+use crate::backends::{ExtraArgs, Layout, LineAlignment, Tool};
 
-const LATEX_START: &str = r"\documentclass[12pt]{article}
-\usepackage{amsmath}
+/// What goes between `\documentclass` and `\begin{document}` unless it's changed in the settings,
+/// the packages every equation can use
+pub const DEFAULT_PREAMBLE: &str = r"\usepackage{amsmath}
 \usepackage{amssymb}
 \usepackage{amsfonts}
 \usepackage[usenames,dvipsnames]{color}
 \usepackage[utf8]{inputenc}
-\thispagestyle{empty}
-\begin{document}
-\color{white}
-\begin{align*}
-    ";
+";
 
 const LATEX_END: &str = r"
-\end{align*}
 \end{document}";
 
-pub async fn gen_svg(latex: String, dir: Dir, color: String) -> Result<(), GuiError> {
-    // println!("GENERATE SVG from LaTeX");
+/// What dvisvgm does with the text's fonts
+#[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum SvgFonts {
+    /// every glyph converted to a path, so the svg looks the same everywhere
+    #[default]
+    Paths,
+    /// kept as text, with woff2 fonts of just the glyphs it needs embedded in the svg. Smaller for
+    /// long equations, and the text can be selected
+    Embedded,
+    /// like [`SvgFonts::Embedded`], but with the fonts written next to the svg, see
+    /// [`extract_fonts`]
+    Files,
+}
 
-    let initial_dir = env::current_dir()
-        .map_err(|_| GuiError::GetSetCurrentDir)?;
+impl SvgFonts {
+    pub const ALL: [Self; 3] = [
+        Self::Paths,
+        Self::Embedded,
+        Self::Files,
+    ];
+}
 
-    // let dir = gui::get_dir(hash);
-    fs::create_dir(&dir).await
-        .map_err(|_| GuiError::TempDir)?;
+impl Display for SvgFonts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Paths => "text as paths",
+            Self::Embedded => "embedded fonts",
+            Self::Files => "font files",
+        })
+    }
+}
+
+/// The tools whose versions are part of the cache key, see [`crate::backends::tool_versions`]
+pub const TOOLS: [&str; 2] = ["latex", "dvisvgm"];
 
-    // println!("dir = {:?}", dir);
-    env::set_current_dir(&dir)
-        .map_err(|_| GuiError::GetSetCurrentDir)?;
+/// Compiles `latex` in a new directory in `scratch`, and copies just the svg into the cache
+/// directory `dir`, which is only created once compiling succeeds.
+///
+/// Unless `fonts` is [`SvgFonts::Paths`], the text is kept as text in subsetted woff2 fonts instead
+/// of being converted to paths.
+#[allow(clippy::too_many_arguments)]
+pub async fn gen_svg(latex: String, preamble: String, dir: Dir, color: String, layout: Layout, extra_args: ExtraArgs, scratch: PathBuf, fonts: SvgFonts) -> Result<(), GuiError> {
+    // println!("GENERATE SVG from LaTeX");
+
+    let scratch = TempDir::new_in(&scratch, "latex_")
+        .map_err(|_| GuiError::TempDir)?;
 
-    fs::write("eq.tex", format!("{LATEX_START}{latex}{LATEX_END}"))
+    let environment = layout.alignment.latex_environment();
+    let body = format!("\\color{{white}}\n\\begin{{{environment}}}\n    {latex}\n\\end{{{environment}}}");
+    let tex = scratch.path().join("eq.tex");
+    fs::write(&tex, document(r"\documentclass[12pt]{article}", &preamble, &body))
         .await
-        .map_err(|_| GuiError::WriteFile("eq.tex".into()))?;
+        .map_err(|_| GuiError::WriteFile(tex.to_string_lossy().to_string().into()))?;
 
-    let _output = backends::run_command("latex", [
+    // run in the scratch directory, rather than changing the whole process's working directory
+    //  out from under anything else that's compiling
+    let _output = backends::run_command_in(Some(scratch.path().to_path_buf()), "latex", [
+        // never let equations run programs, safe mode or not
         "-no-shell-escape",
         "-interaction=nonstopmode",
         "-halt-on-error",
-        "eq.tex"
-    ]).await?;
-
-    let _output = backends::run_command("dvisvgm", [
-        "--no-fonts",
-        "--scale=1",
-        "--exact",
-        // &format!("-o {file_name}"),
-        "-o eq.svg",
-        "eq.dvi"
-    ]).await?;
+    ].into_iter()
+        .chain(extra_args.args(Tool::Latex))
+        .chain(["eq.tex"]),
+    ).await?;
+
+    let scale = format!("--scale={}", layout.scale);
+    let _output = backends::run_command_in(Some(scratch.path().to_path_buf()), "dvisvgm", [
+        if fonts == SvgFonts::Paths { "--no-fonts" } else { "--font-format=woff2" },
+        &scale,
+    ].into_iter()
+        .chain(layout.exact_bbox.then_some("--exact"))
+        .chain(extra_args.args(Tool::Dvisvgm))
+        .chain([
+            // &format!("-o {file_name}"),
+            "-o eq.svg",
+            "eq.dvi",
+        ]),
+    ).await?;
+
+    fs::create_dir_all(&dir).await
+        .map_err(|_| GuiError::TempDir)?;
+    let svg = scratch.path().join("eq.svg");
+    fs::copy(&svg, dir.join("eq.svg"))
+        .await
+        .map_err(|_| GuiError::CopyFile(svg.to_string_lossy().to_string(), dir.to_string_lossy().to_string()))?;
+    if fonts == SvgFonts::Files {
+        extract_fonts(&dir).await?;
+    }
 
     set_color(dir, color)
-        .await?;
+        .await
+}
 
-    env::set_current_dir(initial_dir)
-        .map_err(|_| GuiError::GetSetCurrentDir)?;
+/// The whole document for `body`, with the user's `preamble`. `class` is the `\documentclass` and
+/// anything needed before the preamble.
+fn document(class: &str, preamble: &str, body: &str) -> String {
+    let newline = if preamble.ends_with('\n') { "" } else { "\n" };
+    format!("{class}\n{preamble}{newline}\\thispagestyle{{empty}}\n\\begin{{document}}\n{body}{LATEX_END}")
+}
+
+/// The preamble is spliced into the document, so it can't start or end the document itself
+pub fn check_preamble(preamble: &str) -> Result<(), GuiError> {
+    [r"\documentclass", r"\begin{document}", r"\end{document}"].into_iter()
+        .find(|command| preamble.lines()
+            // commented out is fine
+            .map(|line| line.split('%').next().unwrap_or_default())
+            .any(|line| line.contains(command)))
+        .map_or(Ok(()), |command| Err(GuiError::Preamble(command)))
+}
+
+/// Compiles `latex` with pdflatex to `dir/{color}_eq.pdf`, cropped to the equation by the
+/// `standalone` class. The color can't be changed afterwards like the svg's, so it's set in the
+/// document.
+pub async fn gen_pdf(latex: String, preamble: String, dir: Dir, color: String, layout: Layout, extra_args: ExtraArgs, scratch: PathBuf) -> Result<(), GuiError> {
+    let scratch = TempDir::new_in(&scratch, "latex_pdf_")
+        .map_err(|_| GuiError::TempDir)?;
+
+    // the color package only knows a few names, so anything else is given as rgb
+    let set_color = color::parse_color(&color).map_or_else(
+        || format!("\\color{{{color}}}"),
+        |parsed| {
+            let [r, g, b] = parsed.rgb_fractions();
+            format!("\\definecolor{{eqcolor}}{{rgb}}{{{r:.3},{g:.3},{b:.3}}}\\color{{eqcolor}}")
+        },
+    );
+    // standalone can only crop inline math
+    let math = inline_math(&latex, layout.alignment);
+    #[allow(clippy::float_cmp)]
+    let body = if layout.scale == 1.0 {
+        format!("{set_color}\n{math}")
+    } else {
+        format!("{set_color}\n\\scalebox{{{}}}{{{math}}}", layout.scale)
+    };
+    let tex = scratch.path().join("eq.tex");
+    fs::write(&tex, document("\\documentclass[12pt,border=1pt]{standalone}\n\\usepackage{graphicx}", &preamble, &body))
+        .await
+        .map_err(|_| GuiError::WriteFile(tex.to_string_lossy().to_string().into()))?;
+
+    let _output = backends::run_command_in(Some(scratch.path().to_path_buf()), "pdflatex", [
+        // never let equations run programs, safe mode or not
+        "-no-shell-escape",
+        "-interaction=nonstopmode",
+        "-halt-on-error",
+    ].into_iter()
+        .chain(extra_args.args(Tool::Latex))
+        .chain(["eq.tex"]),
+    ).await?;
 
+    fs::create_dir_all(&dir).await
+        .map_err(|_| GuiError::TempDir)?;
+    let pdf = scratch.path().join("eq.pdf");
+    let to = dir.join(format!("{color}_eq.pdf"));
+    fs::copy(&pdf, &to)
+        .await
+        .map_err(|_| GuiError::CopyFile(pdf.to_string_lossy().to_string(), to.to_string_lossy().to_string()))?;
     Ok(())
 }
 
-pub async fn gen_png(dir: Dir, color: String, density: usize) -> Result<(), GuiError> {
-    // println!("GENERATE PNG from LaTeX");
+/// Moves each font that dvisvgm embedded in `dir/eq.svg` into its own woff2 file next to it, and
+/// points the svg's `@font-face` at that file instead.
+///
+/// dvisvgm only embeds the glyphs the equation uses, so the same font is a different file for
+/// each equation. The file names include a hash of the contents so they don't overwrite each other
+/// when several equations are exported to the same directory.
+async fn extract_fonts(dir: &Dir) -> Result<(), GuiError> {
+    const DATA: &str = "url(data:";
+    const BASE64: &str = ";base64,";
+
+    let path = dir.join("eq.svg");
+    let svg = fs::read_to_string(&path)
+        .await
+        .map_err(|_| GuiError::ReadFile(path.to_string_lossy().to_string()))?;
 
-    let initial_dir = env::current_dir()
-        .map_err(|_| GuiError::GetSetCurrentDir)?;
+    let mut extracted = String::with_capacity(svg.len());
+    let mut rest = svg.as_str();
+    while let Some(start) = rest.find(DATA) {
+        let font = &rest[start + DATA.len()..];
+        let (Some(data_start), Some(end)) = (font.find(BASE64), font.find(')')) else { break };
+        let data = base64::engine::general_purpose::STANDARD.decode(&font[data_start + BASE64.len()..end])
+            .map_err(|_| GuiError::ReadFile(path.to_string_lossy().to_string()))?;
 
-    env::set_current_dir(&dir)
-        .map_err(|_| GuiError::GetSetCurrentDir)?;
+        // the name of the font this `@font-face` declares
+        let family = rest[..start].rfind("font-family:")
+            .map(|i| rest[i + "font-family:".len()..start].split(';').next().unwrap_or_default())
+            .map(|family| family.trim().trim_matches(['"', '\'']))
+            .filter(|family| family.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or("font");
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        let file_name = format!("{family}-{:x}.woff2", hasher.finish());
+        let font_path = dir.join(&file_name);
+        fs::write(&font_path, data)
+            .await
+            .map_err(|_| GuiError::WriteFile(font_path.to_string_lossy().to_string().into()))?;
 
-    let _output = backends::run_command("magick.exe", [
-        "convert",
-        "-background", "none",
-        "-density", &density.to_string(),
-        &format!("{color}_eq.svg"),
-        &format!("{color}_eq.png"),
-    ]).await?;
+        extracted.push_str(&rest[..start]);
+        extracted.push_str("url(");
+        extracted.push_str(&file_name);
+        extracted.push(')');
+        rest = &font[end + 1..];
+    }
+    extracted.push_str(rest);
 
-    env::set_current_dir(initial_dir)
-        .map_err(|_| GuiError::GetSetCurrentDir)?;
+    fs::write(&path, extracted)
+        .await
+        .map_err(|_| GuiError::WriteFile(path.to_string_lossy().to_string().into()))
+}
+
+/// The font files that `svg` refers to, see [`extract_fonts`]
+pub fn font_files(svg: &str) -> Vec<&str> {
+    svg.split("url(")
+        .skip(1)
+        .filter_map(|url| url.split(')').next())
+        .filter(|url| Path::new(url).extension().is_some_and(|ext| ext == "woff2"))
+        .collect()
+}
+
+pub async fn gen_png(dir: Dir, color: String, density: usize, extra_args: ExtraArgs) -> Result<(), GuiError> {
+    // println!("GENERATE PNG from LaTeX");
+
+    let svg = format!("{color}_eq.svg");
+    let contents = fs::read_to_string(dir.join(&svg))
+        .await
+        .map_err(|_| GuiError::ReadFile(svg.clone()))?;
+    backends::check_png_size(&contents, density)?;
+    let magick = backends::magick()?;
+
+    let density = density.to_string();
+    let png = format!("{color}_eq.png");
+    let _output = backends::run_command_in(Some(dir), &magick.program, magick.subcommand.into_iter().chain([
+        "-background", "none",
+        "-density", &density,
+        &svg,
+    ]).chain(extra_args.args(Tool::Magick))
+        .chain([png.as_str()]),
+    ).await?;
 
     Ok(())
 }
@@ -95,13 +276,159 @@ pub async fn set_color(dir: Dir, color: String) -> Result<(), GuiError> {
         .await
         .map_err(|_| GuiError::ReadFile("eq.svg".to_string()))?;
 
-    let svg = svg.replace(
-        "#fff",
-        &color,
-    );
+    // not being able to parse it is unlikely, but then the old find and replace is better than nothing
+    let svg = recolor_white(&svg, &color)
+        .unwrap_or_else(|| svg.replace("#fff", &color));
 
     let path_colored = dir.join(format!("{color}_eq.svg"));
     fs::write(&path_colored, svg)
         .await
         .map_err(|_| GuiError::WriteFile(path_colored.to_string_lossy().to_string().into()))
 }
+
+/// `svg` with every white `fill` and `stroke` set to `color`, including in `style` attributes.
+/// The equation is compiled in white, which dvisvgm writes as `#fff`, `#ffffff`, or `white`
+/// depending on its version and the rest of the svg.
+fn recolor_white(svg: &str, color: &str) -> Option<String> {
+    // older dvisvgms write a doctype
+    let options = roxmltree::ParsingOptions { allow_dtd: true, ..Default::default() };
+    let doc = roxmltree::Document::parse_with_options(svg, options).ok()?;
+    let mut replacements = Vec::new();
+    for attribute in doc.descendants().flat_map(|node| node.attributes()) {
+        let value = match attribute.name() {
+            "fill" | "stroke" if is_white(attribute.value()) => color.to_string(),
+            "style" => {
+                let style = attribute.value()
+                    .split(';')
+                    .map(|declaration| match declaration.split_once(':') {
+                        Some((property, value)) if matches!(property.trim(), "fill" | "stroke") && is_white(value) =>
+                            format!("{property}:{color}"),
+                        _ => declaration.to_string(),
+                    })
+                    .join(";");
+                if style == attribute.value() {
+                    continue;
+                }
+                style
+            }
+            _ => continue,
+        };
+        replacements.push((attribute.range_value(), escape_attribute(&value)));
+    }
+
+    // from the end, so that the earlier ranges stay correct
+    let mut svg = svg.to_string();
+    for (range, value) in replacements.into_iter().rev() {
+        svg.replace_range(range, &value);
+    }
+    Some(svg)
+}
+
+/// Whether the css color `color` is white
+fn is_white(color: &str) -> bool {
+    let color = color.trim().to_ascii_lowercase().replace(' ', "");
+    matches!(
+        color.as_str(),
+        "#fff" | "#ffff" | "#ffffff" | "#ffffffff" | "white" | "rgb(255,255,255)" | "rgb(100%,100%,100%)",
+    )
+}
+
+/// `value` escaped to go between either kind of quotes
+fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// `latex` in a `TikZ` picture, so it can be edited and styled in the document instead of being an
+/// image. Needs `amsmath` for multi-line equations.
+pub fn tikz_snippet(latex: &str, alignment: LineAlignment) -> String {
+    format!("\\tikz \\node[inner sep=0pt] {{{}}};", inline_math(latex, alignment))
+}
+
+/// `latex` as display style inline math, with multiple lines in `alignment`'s inner environment
+fn inline_math(latex: &str, alignment: LineAlignment) -> String {
+    let latex = latex.trim();
+    let math = alignment.latex_inner_environment().map_or_else(
+        || latex.to_string(),
+        |environment| format!("\\begin{{{environment}}}\n    {latex}\n\\end{{{environment}}}"),
+    );
+    format!("$\\displaystyle {math}$")
+}
+
+/// The first equation in `source`, inside `\begin{document}` if it's a whole document. Anything
+/// without math delimiters is taken to be the equation already.
+pub fn extract_equation(source: &str) -> &str {
+    const ENVIRONMENTS: [&str; 6] = ["align*", "align", "gather*", "gather", "equation*", "equation"];
+    const DELIMITERS: [(&str, &str); 4] = [(r"\[", r"\]"), ("$$", "$$"), (r"\(", r"\)"), ("$", "$")];
+
+    let body = source.split_once(r"\begin{document}")
+        .map_or(source, |(_, body)| body.split(r"\end{document}").next().unwrap_or(body));
+    let between = |start: &str, end: &str| body.split_once(start)
+        .and_then(|(_, rest)| rest.split_once(end))
+        .map(|(math, _)| math);
+    ENVIRONMENTS.into_iter()
+        .find_map(|environment| between(&format!("\\begin{{{environment}}}"), &format!("\\end{{{environment}}}")))
+        .or_else(|| DELIMITERS.into_iter().find_map(|(start, end)| between(start, end)))
+        .unwrap_or(body)
+        .trim()
+}
+
+/// A line including the exported image `file` in a LaTeX document. Svgs are included with the `svg`
+/// package's `\includesvg`, since `\includegraphics` can't read them
+pub fn include_snippet(file: &str, format: ImageFormat, width: &str) -> String {
+    let command = match format {
+        ImageFormat::Svg => "includesvg",
+        ImageFormat::Png | ImageFormat::Pdf => "includegraphics",
+    };
+    format!("\\{command}[width={width}]{{{file}}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recolors_every_white() {
+        let svg = r##"<svg><path fill="#fff"/><path fill='#FFFFFF' stroke="white"/><rect stroke="rgb(255, 255, 255)"/></svg>"##;
+        assert_eq!(
+            recolor_white(svg, "red").unwrap(),
+            r#"<svg><path fill="red"/><path fill='red' stroke="red"/><rect stroke="red"/></svg>"#,
+        );
+    }
+
+    #[test]
+    fn recolors_styles() {
+        let svg = r#"<svg><g style="fill:#fff; stroke-width:0.5; stroke: White"/></svg>"#;
+        assert_eq!(
+            recolor_white(svg, "#00ff00").unwrap(),
+            r#"<svg><g style="fill:#00ff00; stroke-width:0.5; stroke:#00ff00"/></svg>"#,
+        );
+    }
+
+    #[test]
+    fn keeps_other_colors() {
+        let svg = r##"<svg><path fill="#000" stroke="#ffe"/><g style="fill:black"/></svg>"##;
+        assert_eq!(recolor_white(svg, "red").unwrap(), svg);
+    }
+
+    #[test]
+    fn escapes_the_color() {
+        assert_eq!(
+            recolor_white(r##"<svg fill="#fff"/>"##, r##"rgb("#ff0000")"##).unwrap(),
+            r#"<svg fill="rgb(&quot;#ff0000&quot;)"/>"#,
+        );
+    }
+
+    #[test]
+    fn allows_a_doctype() {
+        let svg = r##"<?xml version='1.0'?><!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd"><svg fill="#fff"/>"##;
+        assert!(recolor_white(svg, "red").unwrap().ends_with(r#"<svg fill="red"/>"#));
+    }
+
+    #[test]
+    fn unparseable_svg() {
+        assert_eq!(recolor_white("<svg fill=\"#fff\">", "red"), None);
+    }
+}
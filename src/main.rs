@@ -1,4 +1,5 @@
-#![windows_subsystem = "windows"]
+// debug builds keep the console for `println!`s, release builds can get one with `--console`
+#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 #![warn(clippy::pedantic, clippy::nursery)]
 #![allow(
     clippy::cast_possible_truncation,
@@ -8,13 +9,16 @@
     clippy::default_trait_access,
     clippy::unreadable_literal,
     clippy::wildcard_imports,
+    clippy::non_std_lazy_statics,
 )]
 
 use std::borrow::Cow;
 use std::fmt::Debug;
-use iced::{Application, Font, Settings};
+use iced::{Application, Font, Settings, window};
 use thiserror::Error;
 use backends::CommandError;
+use clap::Parser;
+use cli::Cli;
 
 mod gui;
 mod utils;
@@ -25,6 +29,26 @@ mod latex;
 mod icons;
 mod typst;
 mod backends;
+mod check;
+mod translate;
+mod shortcuts;
+mod animation;
+mod clipboard;
+mod theme_pair;
+mod contact_sheet;
+mod html;
+mod rounding;
+mod sink;
+mod baseline;
+mod share;
+mod pdf;
+mod manifest;
+mod cli;
+mod settings;
+mod zoom;
+mod templates;
+mod color;
+mod history;
 
 pub const ICON_FONT_BYTES: &[u8] = include_bytes!("../resources/latex-image-icons.ttf");
 
@@ -37,22 +61,94 @@ pub enum GuiError {
     #[error("could not create temporary directory")]
     // todo rename
     TempDir,
-    #[error("could not get/set the current directory")]
-    GetSetCurrentDir,
     #[error("could not write to `{0}`")]
     WriteFile(Cow<'static, str>),
     #[error("could not read from `{0}`")]
     ReadFile(String),
     #[error("could not copy file from `{0}` to `{1}`")]
     CopyFile(String, String),
+    #[error("`{1}` isn't allowed as an extra argument to {0}")]
+    DisallowedArg(&'static str, String),
+    #[error("Those options don't work together: {0}")]
+    Incompatible(&'static str),
+    #[error("The preamble can't contain `{0}`, it's added around the preamble already")]
+    Preamble(&'static str),
+    #[error("`{0}` isn't a color")]
+    InvalidColor(String),
+    #[error("The png would be {0}x{1} pixels, more than the limit of {2}. Lower the dpi or raise the limit in the settings")]
+    TooLarge(u64, u64, u32),
+    #[error("Couldn't find {0}. Install it or add it to the PATH")]
+    BinaryNotFound(&'static str),
+    #[error("Not allowed in safe mode: {0}")]
+    Unsafe(&'static str),
+    #[error("Couldn't copy to the clipboard: {0}")]
+    Clipboard(String),
+    #[error("Couldn't read the manifest `{0}`: {1}")]
+    Manifest(String, String),
+    #[error("Couldn't open `{0}`: {1}")]
+    OpenFile(String, String),
+    #[error("Couldn't clear the cache: {0}")]
+    ClearCache(String),
     #[error(transparent)]
     Command(#[from] CommandError),
 }
 
+impl GuiError {
+    /// The full output of the command that failed, if it printed more than the error message
+    fn details(&self) -> Option<&str> {
+        match self {
+            Self::Command(CommandError::Error { message, output, .. }) if output.trim() != message.trim() => Some(output),
+            _ => None,
+        }
+    }
+}
+
 fn main() {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            // --help and --version are "errors" too, and need somewhere to be printed
+            show_console();
+            e.exit();
+        }
+    };
+    // headless runs print what they rendered, so there has to be somewhere to print it
+    if cli.console || cli.is_headless() {
+        show_console();
+    }
+    if let Some(code) = cli.run() {
+        std::process::exit(code);
+    }
     gui::Gui::run(Settings {
         antialiasing: true,
+        window: window::Settings {
+            // handled in `Gui::update` so unsaved changes can be exported first
+            exit_on_close_request: false,
+            ..window::Settings::default()
+        },
         ..Settings::default()
     }).unwrap();
 }
 
+/// Shows the output that the windows subsystem otherwise hides, in the terminal it was started
+/// from or else a new console window
+#[cfg(windows)]
+fn show_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+        fn AllocConsole() -> i32;
+    }
+    // both fail if there already is a console, which is fine
+    unsafe {
+        if AttachConsole(ATTACH_PARENT_PROCESS) == 0 {
+            AllocConsole();
+        }
+    }
+}
+
+/// Output always goes to the terminal that started it
+#[cfg(not(windows))]
+const fn show_console() {}
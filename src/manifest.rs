@@ -0,0 +1,168 @@
+//! Rendering every figure described in a json or yaml manifest without opening the window, eg for
+//! reproducible figures in CI. Run with `--manifest figures.yaml`, see [`crate::cli`].
+
+use std::path::{Path, PathBuf};
+
+use iced::futures::future;
+use serde::Deserialize;
+use tempdir::TempDir;
+use tokio::fs;
+
+use crate::{GuiError, latex, typst};
+use crate::backends::{self, Backend, ExtraArgs, Layout};
+use crate::gui::{DEFAULT_COLOR, ImageFormat};
+use crate::typst::MathFont;
+use crate::utils::file_size;
+
+/// One figure in the manifest, rendered with its own settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    pub equation: String,
+    #[serde(default)]
+    pub backend: Backend,
+    #[serde(default = "default_color")]
+    pub color: String,
+    #[serde(default)]
+    pub format: ImageFormat,
+    #[serde(default = "default_dpi")]
+    pub dpi: usize,
+    /// only used by LaTeX, see [`latex::gen_svg`]
+    #[serde(default = "default_preamble")]
+    pub preamble: String,
+    /// only used by Typst, see [`typst::gen_svg`]
+    #[serde(default = "default_imports")]
+    pub imports: String,
+    /// where the image is written, relative to the manifest
+    pub output: PathBuf,
+}
+
+fn default_color() -> String {
+    DEFAULT_COLOR.into()
+}
+
+const fn default_dpi() -> usize {
+    1000
+}
+
+fn default_preamble() -> String {
+    latex::DEFAULT_PREAMBLE.into()
+}
+
+fn default_imports() -> String {
+    typst::DEFAULT_IMPORTS.into()
+}
+
+/// Reads the list of entries in `path`, as yaml unless it ends in `.json`
+pub async fn parse(path: &Path) -> Result<Vec<Entry>, GuiError> {
+    let path_err = path.to_string_lossy().to_string();
+    let manifest = fs::read_to_string(path)
+        .await
+        .map_err(|_| GuiError::ReadFile(path_err.clone()))?;
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+        serde_json::from_str(&manifest)
+            .map_err(|e| GuiError::Manifest(path_err, e.to_string()))
+    } else {
+        serde_yaml::from_str(&manifest)
+            .map_err(|e| GuiError::Manifest(path_err, e.to_string()))
+    }
+}
+
+/// Renders every entry in the manifest at `path`, printing a line for each, and returns how many
+/// failed
+pub async fn run(path: PathBuf) -> Result<usize, GuiError> {
+    let entries = parse(&path).await?;
+    let root = path.parent().unwrap_or_else(|| Path::new(".")).to_owned();
+    let temp = TempDir::new("manifest_")
+        .map_err(|_| GuiError::TempDir)?;
+
+    let outputs = entries.iter()
+        .map(|entry| root.join(&entry.output))
+        .collect::<Vec<_>>();
+    // every entry has its own directory, so they can all render at once, as many at a time as
+    //  `backends::worker` allows. The lines are printed in the manifest's order afterwards
+    let results = future::join_all(entries.iter()
+        .zip(&outputs)
+        .enumerate()
+        .map(|(i, (entry, output))| {
+            let dir = temp.path().join(i.to_string());
+            async move {
+                let _worker = backends::worker().await;
+                render(entry, dir, output).await
+            }
+        }))
+        .await;
+
+    let mut failures = 0;
+    for (output, result) in outputs.iter().zip(results) {
+        match result {
+            Ok(size) => println!("ok    {} ({})", output.display(), file_size(size)),
+            Err(e) => {
+                failures += 1;
+                println!("error {}: {e}", output.display());
+            }
+        }
+    }
+    println!("{} of {} rendered", entries.len() - failures, entries.len());
+    Ok(failures)
+}
+
+/// Renders `entry` in the new directory `dir` and copies it to `output`, returning its size
+pub async fn render(entry: &Entry, dir: PathBuf, output: &Path) -> Result<u64, GuiError> {
+    let Entry { equation, backend, color, format, dpi, preamble, imports, .. } = entry.clone();
+    if equation.trim().is_empty() {
+        return Err(GuiError::NoEquation(backend.stylized()));
+    }
+    let layout = Layout {
+        alignment: Default::default(),
+        scale: 1.0,
+        font: MathFont::default(),
+        font_size: typst::DEFAULT_FONT_SIZE,
+        exact_bbox: true,
+    };
+    let extra_args = ExtraArgs::default();
+    if backend == Backend::LaTeX {
+        latex::check_preamble(&preamble)?;
+    }
+
+    match (backend, format) {
+        (Backend::LaTeX, _) => {
+            latex::gen_svg(equation.clone(), preamble.clone(), dir.clone(), color.clone(), layout, extra_args.clone(), std::env::temp_dir(), latex::SvgFonts::Paths).await?;
+        }
+        (Backend::Typst, ImageFormat::Svg) => {
+            fs::create_dir(&dir)
+                .await
+                .map_err(|_| GuiError::TempDir)?;
+            typst::gen_svg(equation.clone(), imports.clone(), dir.clone(), color.clone(), layout, extra_args.clone()).await?;
+        }
+        (Backend::Typst, ImageFormat::Png | ImageFormat::Pdf) => {
+            fs::create_dir(&dir)
+                .await
+                .map_err(|_| GuiError::TempDir)?;
+        }
+    }
+    match format {
+        ImageFormat::Svg => {}
+        ImageFormat::Png => backend.gen_png(equation, imports, dir.clone(), color.clone(), dpi, layout, extra_args).await?,
+        ImageFormat::Pdf => {
+            let preamble = match backend {
+                Backend::LaTeX => preamble,
+                Backend::Typst => imports,
+            };
+            backend.gen_pdf(equation, preamble, dir.clone(), color.clone(), layout, extra_args, std::env::temp_dir()).await?;
+        }
+    }
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|_| GuiError::WriteFile(parent.to_string_lossy().to_string().into()))?;
+    }
+    let from = dir.join(format!("{color}_eq.{format}"));
+    fs::copy(&from, output)
+        .await
+        .map_err(|_| GuiError::CopyFile(
+            from.to_string_lossy().to_string(),
+            output.to_string_lossy().to_string(),
+        ))
+}
@@ -0,0 +1,80 @@
+//! Every equation rendered this session in one pdf, one per page, eg for lecture notes
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tempdir::TempDir;
+use tokio::fs;
+
+use crate::{GuiError, typst};
+use crate::contact_sheet::Thumbnail;
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PdfOptions {
+    /// write each equation's source under it
+    pub captions: bool,
+    /// a black background, for equations rendered in a light color
+    pub dark_pages: bool,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            captions: true,
+            // equations are white by default
+            dark_pages: true,
+        }
+    }
+}
+
+/// Lays out `thumbnails` one per page with Typst and writes the pdf to `path`, returning its size
+pub async fn export(thumbnails: Vec<Thumbnail>, options: PdfOptions, path: PathBuf) -> Result<u64, GuiError> {
+    // typst can only read images inside the document's root
+    let temp = TempDir::new("pdf_")
+        .map_err(|_| GuiError::TempDir)?;
+    let page = if options.dark_pages { "black" } else { "white" };
+    // gray captions show up on either page
+    let mut source = format!(
+        "#set page(width: auto, height: auto, margin: 1cm, fill: {page})\n#set text(fill: gray, size: 9pt)\n"
+    );
+    for (i, thumbnail) in thumbnails.iter().enumerate() {
+        let extension = thumbnail.path.extension().unwrap_or_default().to_string_lossy();
+        let name = format!("{i}.{extension}");
+        let to = temp.path().join(&name);
+        fs::copy(&thumbnail.path, &to)
+            .await
+            .map_err(|_| GuiError::CopyFile(
+                thumbnail.path.to_string_lossy().to_string(),
+                to.to_string_lossy().to_string(),
+            ))?;
+
+        if i != 0 {
+            source.push_str("#pagebreak()\n");
+        }
+        source.push_str("#align(center)[\n#image(\"");
+        source.push_str(&name);
+        source.push_str("\")\n");
+        if options.captions {
+            source.push_str("#raw(\"");
+            source.push_str(&escape(&thumbnail.label));
+            source.push_str("\")\n");
+        }
+        source.push_str("]\n");
+    }
+
+    typst::compile_document(temp.path().to_owned(), source, &path).await?;
+
+    let path_err = path.to_string_lossy().to_string();
+    fs::metadata(&path)
+        .await
+        .map(|metadata| metadata.len())
+        .map_err(|_| GuiError::ReadFile(path_err))
+}
+
+/// `s` as the contents of a Typst string literal
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
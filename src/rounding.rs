@@ -0,0 +1,127 @@
+//! Rounding long numbers in an equation to a few significant figures before it's rendered, for
+//! pasting in computed constants
+
+/// Rounds each decimal number in `eq` with more than `sig_figs` significant figures.
+///
+/// Only plain decimals like `6.02214076` are rounded, never integers. To avoid changing the
+/// equation's meaning, numbers are left alone when they are:
+/// - sub- or superscripts, like `x_1.25` or `10^{2.5}`
+/// - touching letters, like `x1.5`, `\frac12`, or `1.5em`
+/// - in a Typst string
+pub fn round_numbers(eq: &str, sig_figs: u32) -> String {
+    let sig_figs = sig_figs.max(1);
+    let chars = eq.chars().collect::<Vec<_>>();
+    let mut rounded = String::with_capacity(eq.len());
+    let mut i = 0;
+    let mut in_string = false;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' {
+            in_string = !in_string;
+        }
+        if in_string {
+            rounded.push(c);
+            i += 1;
+            continue;
+        }
+
+        if c == '_' || c == '^' {
+            // copy the whole script as is
+            let end = script_end(&chars, i + 1);
+            rounded.extend(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let end = number_end(&chars, i);
+            let number = chars[i..end].iter().collect::<String>();
+            let touches_letter = (i > 0 && chars[i - 1].is_alphabetic())
+                || chars.get(end).is_some_and(|c| c.is_alphabetic());
+            if touches_letter {
+                rounded.push_str(&number);
+            } else {
+                rounded.push_str(&round(&number, sig_figs));
+            }
+            i = end;
+            continue;
+        }
+
+        // the digits at the end of a word aren't a separate number
+        if c.is_alphabetic() {
+            let end = i + chars[i..].iter().take_while(|c| c.is_alphanumeric()).count();
+            rounded.extend(&chars[i..end]);
+            i = end;
+            continue;
+        }
+
+        rounded.push(c);
+        i += 1;
+    }
+    rounded
+}
+
+/// The end of a number starting at `start`, including one decimal point followed by digits
+fn number_end(chars: &[char], start: usize) -> usize {
+    let digits = |from: usize| from + chars[from..].iter().take_while(|c| c.is_ascii_digit()).count();
+    let end = digits(start);
+    if chars.get(end) == Some(&'.') && chars.get(end + 1).is_some_and(char::is_ascii_digit) {
+        digits(end + 1)
+    } else {
+        end
+    }
+}
+
+/// The end of the sub- or superscript starting at `start`: a bracketed group, a number, a word, or
+/// a single character
+fn script_end(chars: &[char], start: usize) -> usize {
+    match chars.get(start) {
+        None => start,
+        Some(&open @ ('{' | '(' | '[')) => {
+            let close = match open {
+                '{' => '}',
+                '(' => ')',
+                _ => ']',
+            };
+            let mut depth = 0;
+            for (i, &c) in chars.iter().enumerate().skip(start) {
+                if c == open {
+                    depth += 1;
+                } else if c == close {
+                    depth -= 1;
+                    if depth == 0 {
+                        return i + 1;
+                    }
+                }
+            }
+            chars.len()
+        }
+        Some(c) if c.is_ascii_digit() => number_end(chars, start),
+        Some('\\') => start + 1 + chars[start + 1..].iter().take_while(|c| c.is_alphabetic()).count(),
+        Some(c) if c.is_alphabetic() => start + chars[start..].iter().take_while(|c| c.is_alphanumeric()).count(),
+        Some(_) => start + 1,
+    }
+}
+
+/// Rounds `number` to `sig_figs` significant figures, if it is a decimal with more than that
+fn round(number: &str, sig_figs: u32) -> String {
+    let significant = number.trim_start_matches(['0', '.']).chars().filter(char::is_ascii_digit).count();
+    if !number.contains('.') || significant <= sig_figs as usize {
+        return number.to_string();
+    }
+    let Ok(value) = number.parse::<f64>() else {
+        return number.to_string();
+    };
+    // round first, in case that carries into another digit, eg 9.9999 -> 10.00
+    let value = format!("{value:.0$e}", sig_figs as usize - 1).parse().unwrap_or(value);
+    // digits before the decimal point, negative for leading zeros after it
+    let magnitude = value.abs().log10().floor() as i64 + 1;
+    let decimals = i64::from(sig_figs) - magnitude;
+    if decimals >= 0 {
+        format!("{value:.0$}", decimals as usize)
+    } else {
+        // the integer part alone has too many digits, so round it too
+        let scale = 10_f64.powi(-decimals as i32);
+        format!("{}", (value / scale).round() * scale)
+    }
+}
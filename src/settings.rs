@@ -0,0 +1,172 @@
+//! The settings that are kept between launches, in `settings.json` in the config directory
+
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::backends::{self, Backend, ColorProfile, ExtraArgs, LineAlignment, PngDepth, SvgSize};
+use crate::contact_sheet::ContactSheet;
+use crate::gui::ImageFormat;
+use crate::html::HtmlStyle;
+use crate::latex;
+use crate::pdf::PdfOptions;
+use crate::share::ShareMethod;
+use crate::style::ThemeMode;
+use crate::theme_pair::ThemePair;
+use crate::typst::{self, MathFont};
+
+/// See the fields of the same name in [`crate::gui::Gui`]. Anything missing from the file, eg
+/// settings added since it was written, gets its default.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+// the same toggles as `Gui`
+#[allow(clippy::struct_excessive_bools)]
+pub struct Settings {
+    pub backend: Backend,
+    pub format: ImageFormat,
+    pub dpi: usize,
+    pub color: Option<String>,
+    /// only restored if it still exists
+    pub out_dir: Option<PathBuf>,
+    pub pin_out_dir: bool,
+    pub extra_out_dirs: Vec<String>,
+    pub current_color: bool,
+    pub secondary_png: bool,
+    pub scale: String,
+    pub alignment: LineAlignment,
+    pub exact_bbox: bool,
+    pub typst_font: MathFont,
+    pub typst_font_size: String,
+    pub crop: bool,
+    pub crop_padding: String,
+    pub aspect: String,
+    pub canvas_size: String,
+    pub canvas_offset: String,
+    pub shadow: bool,
+    pub shadow_offset: String,
+    pub shadow_blur: String,
+    pub shadow_color: String,
+    pub invert: bool,
+    pub background: Option<String>,
+    pub svg_size: SvgSize,
+    pub strip_prolog: bool,
+    pub png_depth: PngDepth,
+    pub color_profile: ColorProfile,
+    pub icc_path: PathBuf,
+    pub swatches: Vec<String>,
+    pub contact_sheet: ContactSheet,
+    pub pdf: PdfOptions,
+    pub extra_args: ExtraArgs,
+    pub latex_preamble: String,
+    pub typst_imports: String,
+    pub prefill: bool,
+    pub keep_last_render: bool,
+    pub copy_svg_markup: bool,
+    pub latex_live: bool,
+    pub latex_idle_ms: u64,
+    pub dim_while_compiling: bool,
+    pub theme_pair: ThemePair,
+    pub html_style: HtmlStyle,
+    pub svg_fonts: latex::SvgFonts,
+    pub share_method: ShareMethod,
+    pub align_baseline: bool,
+    pub include_width: String,
+    pub round_numbers: bool,
+    pub sig_figs: u32,
+    pub max_png_size: u32,
+    pub cache_budget_mb: u64,
+    pub max_workers: usize,
+    pub scratch_dir: String,
+    pub high_contrast: bool,
+    pub theme_mode: ThemeMode,
+    pub safe_mode: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            backend: Backend::default(),
+            format: ImageFormat::default(),
+            dpi: 1000,
+            color: None,
+            out_dir: None,
+            pin_out_dir: false,
+            extra_out_dirs: Vec::new(),
+            current_color: false,
+            secondary_png: false,
+            scale: "1".into(),
+            alignment: LineAlignment::default(),
+            exact_bbox: true,
+            typst_font: MathFont::default(),
+            typst_font_size: "11".into(),
+            crop: false,
+            crop_padding: "2".into(),
+            aspect: String::new(),
+            canvas_size: String::new(),
+            canvas_offset: String::new(),
+            shadow: false,
+            shadow_offset: "2,2".into(),
+            shadow_blur: "3".into(),
+            shadow_color: String::new(),
+            invert: false,
+            background: None,
+            svg_size: SvgSize::default(),
+            strip_prolog: false,
+            png_depth: PngDepth::default(),
+            color_profile: ColorProfile::default(),
+            icc_path: PathBuf::new(),
+            swatches: vec!["white".into(), "black".into()],
+            contact_sheet: ContactSheet::default(),
+            pdf: PdfOptions::default(),
+            extra_args: ExtraArgs::default(),
+            latex_preamble: latex::DEFAULT_PREAMBLE.into(),
+            typst_imports: typst::DEFAULT_IMPORTS.into(),
+            prefill: true,
+            keep_last_render: false,
+            copy_svg_markup: false,
+            latex_live: false,
+            latex_idle_ms: 600,
+            dim_while_compiling: false,
+            theme_pair: ThemePair::default(),
+            html_style: HtmlStyle::default(),
+            svg_fonts: latex::SvgFonts::Paths,
+            share_method: ShareMethod::default(),
+            align_baseline: false,
+            include_width: String::new(),
+            round_numbers: false,
+            sig_figs: 4,
+            max_png_size: backends::DEFAULT_MAX_PNG_SIZE,
+            cache_budget_mb: 500,
+            max_workers: backends::default_workers(),
+            scratch_dir: String::new(),
+            high_contrast: false,
+            theme_mode: ThemeMode::default(),
+            safe_mode: false,
+        }
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("typset_image").join("settings.json"))
+}
+
+/// The saved settings, or the defaults if there aren't any or they can't be read
+pub fn load() -> Settings {
+    path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Writes `settings` to the config directory. Failing isn't worth interrupting anything for, the
+/// settings just won't be remembered
+pub fn save(settings: &Settings) {
+    let Some(path) = path() else { return };
+    let saved = path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, serde_json::to_string_pretty(settings).unwrap_or_default()));
+    if let Err(e) = saved {
+        eprintln!("couldn't save the settings to {}: {e}", path.display());
+    }
+}
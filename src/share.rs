@@ -0,0 +1,83 @@
+//! Handing an exported image to another app. There's no cross-platform share sheet, so for now
+//! this starts an email or shows the file in its folder. A platform's own share sheet can be added
+//! by implementing [`Share`].
+
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::GuiError;
+
+/// Passes an exported file on to somewhere outside the app
+pub trait Share {
+    fn share(&self, path: &Path) -> Result<(), GuiError>;
+}
+
+/// Opens a new email in the default mail client. `mailto:` links can't attach files, so the file's
+/// path is put in the body to attach by hand.
+pub struct Email;
+
+impl Share for Email {
+    fn share(&self, path: &Path) -> Result<(), GuiError> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let url = format!(
+            "mailto:?subject={}&body={}",
+            percent_encode(&name),
+            percent_encode(&format!("Attach {}", path.display())),
+        );
+        opener::open(&url)
+            .map_err(|e| GuiError::OpenFile(url, e.to_string()))
+    }
+}
+
+/// Opens the folder the file is in, eg to drag it into a chat
+pub struct Folder;
+
+impl Share for Folder {
+    fn share(&self, path: &Path) -> Result<(), GuiError> {
+        let dir = path.parent().unwrap_or(path);
+        opener::open(dir)
+            .map_err(|e| GuiError::OpenFile(dir.to_string_lossy().to_string(), e.to_string()))
+    }
+}
+
+#[derive(Default, Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ShareMethod {
+    #[default]
+    Email,
+    Folder,
+}
+
+impl ShareMethod {
+    pub const ALL: [Self; 2] = [
+        Self::Email,
+        Self::Folder,
+    ];
+
+    pub fn sharer(self) -> Box<dyn Share + Send> {
+        match self {
+            Self::Email => Box::new(Email),
+            Self::Folder => Box::new(Folder),
+        }
+    }
+}
+
+impl Display for ShareMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Email => "email",
+            Self::Folder => "show in folder",
+        })
+    }
+}
+
+/// Escapes everything but unreserved characters, for a query parameter
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => char::from(b).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
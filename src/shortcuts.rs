@@ -0,0 +1,144 @@
+//! Every keyboard shortcut, used both to handle key presses and to list them in the reference
+
+use iced::event;
+use iced::keyboard::{Key, key::Named, Modifiers};
+
+use crate::backends::Backend;
+use crate::gui::Message;
+
+pub struct Shortcut {
+    /// Ctrl, or Cmd on macOS
+    pub command: bool,
+    /// Only checked for named keys, since whether shift is needed to type a character depends on
+    /// the keyboard layout
+    pub shift: bool,
+    pub key: Key<&'static str>,
+    pub description: &'static str,
+    pub message: fn() -> Message,
+}
+
+pub static SHORTCUTS: [Shortcut; 11] = [
+    Shortcut {
+        command: true,
+        shift: false,
+        key: Key::Named(Named::Tab),
+        description: "Focus the next field",
+        message: || Message::FocusNext,
+    },
+    Shortcut {
+        command: true,
+        shift: true,
+        key: Key::Named(Named::Tab),
+        description: "Focus the previous field",
+        message: || Message::FocusPrevious,
+    },
+    Shortcut {
+        command: true,
+        shift: false,
+        key: Key::Named(Named::Enter),
+        description: "Render and copy the image",
+        message: || Message::CompileAndCopy,
+    },
+    Shortcut {
+        command: true,
+        shift: false,
+        key: Key::Character("s"),
+        description: "Save as a new file",
+        message: || Message::OpenSaveAs,
+    },
+    Shortcut {
+        command: true,
+        shift: false,
+        key: Key::Character("o"),
+        description: "Show the output folder",
+        message: || Message::RevealOutput,
+    },
+    Shortcut {
+        command: true,
+        shift: false,
+        key: Key::Character("l"),
+        description: "Switch to LaTeX",
+        message: || Message::SetBackend(Backend::LaTeX),
+    },
+    Shortcut {
+        command: true,
+        shift: false,
+        key: Key::Character("t"),
+        description: "Switch to Typst",
+        message: || Message::SetBackend(Backend::Typst),
+    },
+    Shortcut {
+        command: true,
+        shift: false,
+        key: Key::Character("\\"),
+        description: "Swap to the other backend's last equation",
+        message: || Message::SwapBackend,
+    },
+    Shortcut {
+        command: false,
+        shift: false,
+        key: Key::Named(Named::F1),
+        description: "Show or hide this list",
+        message: || Message::ToggleShortcuts,
+    },
+    Shortcut {
+        command: false,
+        shift: false,
+        key: Key::Character("?"),
+        description: "Show or hide this list (when not typing)",
+        message: || Message::ToggleShortcuts,
+    },
+    Shortcut {
+        command: false,
+        shift: false,
+        key: Key::Named(Named::Escape),
+        description: "Hide this list",
+        message: || Message::HideShortcuts,
+    },
+];
+
+impl Shortcut {
+    /// Whether pressing `key` with `modifiers` should trigger this shortcut
+    fn matches(&self, key: &Key<&str>, modifiers: Modifiers, status: event::Status) -> bool {
+        if modifiers.command() != self.command {
+            return false;
+        }
+        match (&self.key, key) {
+            (Key::Named(named), Key::Named(pressed)) => named == pressed && modifiers.shift() == self.shift,
+            // plain characters are being typed into a text field if the event was captured
+            (Key::Character(c), Key::Character(pressed)) => c.eq_ignore_ascii_case(pressed)
+                && (self.command || status == event::Status::Ignored),
+            _ => false,
+        }
+    }
+
+    /// eg "Ctrl+Shift+Tab"
+    pub fn keys(&self) -> String {
+        let command = match (self.command, cfg!(target_os = "macos")) {
+            (false, _) => "",
+            (true, false) => "Ctrl+",
+            (true, true) => "Cmd+",
+        };
+        let shift = if self.shift { "Shift+" } else { "" };
+        let key = match &self.key {
+            Key::Named(Named::Escape) => "Esc".to_string(),
+            Key::Named(named) => format!("{named:?}"),
+            Key::Character(c) => c.to_uppercase(),
+            Key::Unidentified => String::new(),
+        };
+        format!("{command}{shift}{key}")
+    }
+}
+
+/// The shortcut that switches to `backend`
+pub fn switch_to(backend: Backend) -> Option<&'static Shortcut> {
+    SHORTCUTS.iter()
+        .find(|shortcut| matches!((shortcut.message)(), Message::SetBackend(b) if b == backend))
+}
+
+/// The message for the first shortcut matching this key press, if any
+pub fn find(key: &Key<&str>, modifiers: Modifiers, status: event::Status) -> Option<Message> {
+    SHORTCUTS.iter()
+        .find(|shortcut| shortcut.matches(key, modifiers, status))
+        .map(|shortcut| (shortcut.message)())
+}
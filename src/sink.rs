@@ -0,0 +1,60 @@
+//! Where exported images end up. Only the local file system is used for now, but other
+//! destinations (eg S3 or a `WebDAV` server) can be added by implementing [`OutputSink`]
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The last step of an export, writing the finished image somewhere
+pub trait OutputSink {
+    /// Writes the file at `from` as `name`, returning where it was written and its size in bytes
+    fn write(&self, from: &Path, name: &Path) -> io::Result<(PathBuf, u64)>;
+
+    /// Where [`OutputSink::write`] would put `name`, to say where a failed write was going
+    fn destination(&self, name: &Path) -> PathBuf;
+}
+
+/// Copies files into a directory, eg `out_dir`, creating it if it doesn't exist yet
+#[derive(Debug, Clone)]
+pub struct LocalFile {
+    pub dir: PathBuf,
+}
+
+impl OutputSink for LocalFile {
+    fn write(&self, from: &Path, name: &Path) -> io::Result<(PathBuf, u64)> {
+        let path = self.destination(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from, &path).map(|size| (path, size))
+    }
+
+    fn destination(&self, name: &Path) -> PathBuf {
+        self.dir.join(name)
+    }
+}
+
+/// Keeps files in memory instead of writing them anywhere. Shows how another sink plugs in, and is
+/// handy for checking what an export would write. Clones share the same files
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Debug, Clone, Default)]
+pub struct InMemory {
+    pub files: Arc<Mutex<HashMap<PathBuf, Vec<u8>>>>,
+}
+
+impl OutputSink for InMemory {
+    fn write(&self, from: &Path, name: &Path) -> io::Result<(PathBuf, u64)> {
+        let contents = fs::read(from)?;
+        let size = contents.len() as u64;
+        self.files.lock()
+            .expect("files aren't poisoned")
+            .insert(name.to_owned(), contents);
+        Ok((self.destination(name), size))
+    }
+
+    fn destination(&self, name: &Path) -> PathBuf {
+        name.to_owned()
+    }
+}
@@ -1,3 +1,205 @@
+use std::fmt::{Display, Formatter};
+
+use iced::{Background, Border, Color, Theme};
+use iced::theme::Palette;
+use iced::widget::{button, container, text_input};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+/// Pure black and white with saturated accents, so that borders, text, and the spinner stand out
+/// more than in [`Theme::Dark`]. Every widget's colors are derived from the palette.
+pub static HIGH_CONTRAST: Lazy<Theme> = Lazy::new(|| Theme::custom(
+    "High contrast".into(),
+    Palette {
+        background: Color::BLACK,
+        text: Color::WHITE,
+        primary: Color::from_rgb(1.0, 0.85, 0.0),
+        success: Color::from_rgb(0.0, 1.0, 0.4),
+        danger: Color::from_rgb(1.0, 0.3, 0.3),
+    },
+));
+
+/// Whether the window is light or dark
+#[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum ThemeMode {
+    #[default]
+    Dark,
+    Light,
+    /// whichever the OS uses, see [`system_is_dark`]
+    System,
+}
+
+impl ThemeMode {
+    pub const ALL: [Self; 3] = [
+        Self::Dark,
+        Self::Light,
+        Self::System,
+    ];
+
+    /// `system_dark` is what [`system_is_dark`] found
+    pub const fn is_dark(self, system_dark: bool) -> bool {
+        match self {
+            Self::Dark => true,
+            Self::Light => false,
+            Self::System => system_dark,
+        }
+    }
+}
+
+impl Display for ThemeMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Dark => "dark",
+            Self::Light => "light",
+            Self::System => "system",
+        })
+    }
+}
+
+/// Whether the OS is in dark mode, asked the same way its settings would be read. Dark if it can't
+/// be told, since that's what the window always used to be
+pub async fn system_is_dark() -> bool {
+    #[cfg(target_os = "macos")]
+    {
+        // the key only exists in dark mode
+        read_setting("defaults", &["read", "-g", "AppleInterfaceStyle"]).await
+            .is_some_and(|style| style.contains("Dark"))
+    }
+    #[cfg(windows)]
+    {
+        read_setting("reg", &[
+            "query",
+            r"HKCU\Software\Microsoft\Windows\CurrentVersion\Themes\Personalize",
+            "/v",
+            "AppsUseLightTheme",
+        ]).await
+            .is_none_or(|setting| setting.trim_end().ends_with("0x0"))
+    }
+    #[cfg(not(any(target_os = "macos", windows)))]
+    {
+        read_setting("gsettings", &["get", "org.gnome.desktop.interface", "color-scheme"]).await
+            .is_none_or(|scheme| scheme.contains("dark"))
+    }
+}
+
+/// The output of a command that reads an OS setting, if it succeeded
+async fn read_setting(program: &str, args: &[&str]) -> Option<String> {
+    #[cfg(windows)]
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let mut command = tokio::process::Command::new(program);
+    command.args(args);
+    #[cfg(windows)]
+    command.creation_flags(CREATE_NO_WINDOW);
+    let output = command.output().await.ok()?;
+    output.status.success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// A button filled with a color, for picking it
+pub struct Swatch(pub Color);
+
+impl button::StyleSheet for Swatch {
+    type Style = Theme;
+
+    fn active(&self, style: &Self::Style) -> button::Appearance {
+        button::Appearance {
+            background: Some(Background::Color(self.0)),
+            // so that colors close to the background are still visible
+            border: Border {
+                color: style.extended_palette().background.strong.color,
+                width: 1.0,
+                radius: 3.0.into(),
+            },
+            ..button::Appearance::default()
+        }
+    }
+
+    fn hovered(&self, style: &Self::Style) -> button::Appearance {
+        let active = self.active(style);
+        button::Appearance {
+            border: Border {
+                color: style.palette().primary,
+                ..active.border
+            },
+            ..active
+        }
+    }
+}
+
+/// A box for short-lived messages, drawn at this opacity so that it can fade out
+pub struct Notification(pub f32);
+
+impl container::StyleSheet for Notification {
+    type Style = Theme;
+
+    fn appearance(&self, style: &Self::Style) -> container::Appearance {
+        let palette = style.extended_palette();
+        let fade = |color: Color| Color { a: color.a * self.0, ..color };
+        container::Appearance {
+            text_color: Some(fade(palette.background.base.text)),
+            background: Some(Background::Color(fade(palette.background.weak.color))),
+            border: Border {
+                color: fade(palette.success.base.color),
+                width: 1.0,
+                radius: 4.0.into(),
+            },
+            ..container::Appearance::default()
+        }
+    }
+}
+
+/// A text input with a red border, for text that can't be used
+pub struct InvalidInput;
+
+impl InvalidInput {
+    fn invalid(style: &Theme, appearance: text_input::Appearance) -> text_input::Appearance {
+        text_input::Appearance {
+            border: Border {
+                color: style.palette().danger,
+                ..appearance.border
+            },
+            ..appearance
+        }
+    }
+}
+
+impl text_input::StyleSheet for InvalidInput {
+    type Style = Theme;
+
+    fn active(&self, style: &Self::Style) -> text_input::Appearance {
+        Self::invalid(style, style.active(&iced::theme::TextInput::Default))
+    }
+
+    fn focused(&self, style: &Self::Style) -> text_input::Appearance {
+        Self::invalid(style, style.focused(&iced::theme::TextInput::Default))
+    }
+
+    fn placeholder_color(&self, style: &Self::Style) -> Color {
+        style.placeholder_color(&iced::theme::TextInput::Default)
+    }
+
+    fn value_color(&self, style: &Self::Style) -> Color {
+        style.value_color(&iced::theme::TextInput::Default)
+    }
+
+    fn disabled_color(&self, style: &Self::Style) -> Color {
+        style.disabled_color(&iced::theme::TextInput::Default)
+    }
+
+    fn selection_color(&self, style: &Self::Style) -> Color {
+        style.selection_color(&iced::theme::TextInput::Default)
+    }
+
+    fn hovered(&self, style: &Self::Style) -> text_input::Appearance {
+        Self::invalid(style, style.hovered(&iced::theme::TextInput::Default))
+    }
+
+    fn disabled(&self, style: &Self::Style) -> text_input::Appearance {
+        style.disabled(&iced::theme::TextInput::Default)
+    }
+}
+
 // use iced::Color;
 // use iced::widget::container::{Appearance, StyleSheet};
 //
@@ -0,0 +1,53 @@
+//! Common constructs written in each backend's syntax, for inserting into the editor without
+//! knowing how that backend spells them
+
+use std::fmt::{Display, Formatter};
+
+use crate::backends::Backend;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Template {
+    Fraction,
+    Integral,
+    Summation,
+    Matrix,
+    Cases,
+}
+
+impl Template {
+    pub const ALL: [Self; 5] = [
+        Self::Fraction,
+        Self::Integral,
+        Self::Summation,
+        Self::Matrix,
+        Self::Cases,
+    ];
+
+    /// The template in `backend`'s syntax
+    pub const fn source(self, backend: Backend) -> &'static str {
+        match (self, backend) {
+            (Self::Fraction, Backend::LaTeX) => r"\frac{a}{b}",
+            (Self::Fraction, Backend::Typst) => "a / b",
+            (Self::Integral, Backend::LaTeX) => r"\int_{a}^{b} f(x) \, dx",
+            (Self::Integral, Backend::Typst) => "integral_a^b f(x) dif x",
+            (Self::Summation, Backend::LaTeX) => r"\sum_{i=1}^{n} a_i",
+            (Self::Summation, Backend::Typst) => "sum_(i=1)^n a_i",
+            (Self::Matrix, Backend::LaTeX) => r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}",
+            (Self::Matrix, Backend::Typst) => "mat(a, b; c, d)",
+            (Self::Cases, Backend::LaTeX) => r"\begin{cases} a & x > 0 \\ b & \text{otherwise} \end{cases}",
+            (Self::Cases, Backend::Typst) => r#"cases(a &"if" x > 0, b &"otherwise")"#,
+        }
+    }
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Fraction => "fraction",
+            Self::Integral => "integral",
+            Self::Summation => "summation",
+            Self::Matrix => "matrix",
+            Self::Cases => "cases",
+        })
+    }
+}
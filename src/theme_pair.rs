@@ -0,0 +1,216 @@
+//! Exporting an equation in two colors at once, for documents with both a light and a dark theme
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tempdir::TempDir;
+use tokio::fs;
+
+use crate::{GuiError, latex, typst};
+use crate::backends::{self, Backend, ExtraArgs, Layout, PostProcess};
+use crate::gui::{Dir, ImageFormat};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Variant {
+    Light,
+    Dark,
+}
+
+impl Variant {
+    pub const ALL: [Self; 2] = [
+        Self::Light,
+        Self::Dark,
+    ];
+
+    /// What `{theme}` is replaced with in [`ThemePair::template`]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Dark => "dark",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ThemePair {
+    /// the color to use on light backgrounds
+    pub light: String,
+    /// the color to use on dark backgrounds
+    pub dark: String,
+    /// file name of each variant, without the extension. `{name}` is replaced by the file name and
+    /// `{theme}` by [`Variant::name`]. Without `{theme}` both variants would get the same name, so
+    /// `-{theme}` is added, see [`ThemePair::file_name`]
+    pub template: String,
+}
+
+impl Default for ThemePair {
+    fn default() -> Self {
+        Self {
+            light: "black".into(),
+            dark: "white".into(),
+            template: "{name}-{theme}".into(),
+        }
+    }
+}
+
+impl ThemePair {
+    pub fn get(&self, variant: Variant) -> &str {
+        match variant {
+            Variant::Light => &self.light,
+            Variant::Dark => &self.dark,
+        }
+    }
+
+    pub const fn get_mut(&mut self, variant: Variant) -> &mut String {
+        match variant {
+            Variant::Light => &mut self.light,
+            Variant::Dark => &mut self.dark,
+        }
+    }
+
+    /// The file `variant` is exported to, with `-{theme}` at the end of the template if it doesn't
+    /// say where the theme goes
+    // `{theme}` is a placeholder in the template, not a format argument
+    #[allow(clippy::literal_string_with_formatting_args)]
+    pub fn file_name(&self, name: &str, variant: Variant, format: ImageFormat) -> String {
+        let mut template = self.template.clone();
+        if !template.contains("{theme}") {
+            template.push_str("-{theme}");
+        }
+        let stem = template
+            .replace("{name}", name)
+            .replace("{theme}", variant.name());
+        format!("{stem}.{format}")
+    }
+}
+
+/// Everything needed to export a [`ThemePair`] in the background
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub pair: ThemePair,
+    pub eq: String,
+    pub backend: Backend,
+    pub format: ImageFormat,
+    pub dpi: usize,
+    pub layout: Layout,
+    pub extra_args: ExtraArgs,
+    /// LaTeX's preamble or Typst's imports, see [`latex::gen_svg`] and [`typst::gen_svg`]
+    pub preamble: String,
+    pub post: PostProcess,
+    /// the LaTeX cache directory for this equation, see [`crate::gui::get_dir`]
+    pub latex_dir: Dir,
+    /// where LaTeX compiles, see [`latex::gen_svg`]
+    pub scratch: PathBuf,
+    /// keep LaTeX's text in font files, see [`latex::gen_svg`]
+    pub fonts: latex::SvgFonts,
+    /// where the variants are written, before [`crate::gui`] writes them to each output directory
+    pub staging: PathBuf,
+    /// replaces `{name}` in the template
+    pub name: String,
+}
+
+/// Renders the equation in both colors and copies each to `staging`, returning the written files
+pub async fn export(job: Job) -> Result<Vec<PathBuf>, GuiError> {
+    let Job { pair, eq, backend, format, dpi, layout, extra_args, preamble, post, latex_dir, scratch, fonts, staging, name } = job;
+    let (light, dark) = (pair.light.clone(), pair.dark.clone());
+
+    // typst's directory is shared with the main preview, so use a separate one
+    let temp = TempDir::new("theme_pair_")
+        .map_err(|_| GuiError::TempDir)?;
+    let dir = match backend {
+        Backend::LaTeX => latex_dir,
+        Backend::Typst => temp.path().to_owned(),
+    };
+
+    match (backend, format) {
+        (Backend::LaTeX, _) => {
+            // one compile, then just swap the colors. The cache directory is shared with the
+            //  preview, which may still be compiling into it, so check for the svg itself: the
+            //  directory is created before the svg is copied in
+            if dir.join("eq.svg").exists() {
+                latex::set_color(dir.clone(), light.clone()).await?;
+            } else {
+                latex::gen_svg(eq.clone(), preamble.clone(), dir.clone(), light.clone(), layout, extra_args.clone(), scratch.clone(), fonts).await?;
+            }
+            latex::set_color(dir.clone(), dark.clone()).await?;
+        }
+        (Backend::Typst, ImageFormat::Svg) => {
+            typst::gen_svg(eq.clone(), preamble.clone(), dir.clone(), light.clone(), layout, extra_args.clone()).await?;
+            if typst::svg_color(&light).is_some() && typst::svg_color(&dark).is_some() {
+                typst::set_color(dir.clone(), dark.clone()).await?;
+            } else {
+                typst::gen_svg(eq.clone(), preamble.clone(), dir.clone(), dark.clone(), layout, extra_args.clone()).await?;
+            }
+        }
+        // typst renders pngs and pdfs directly
+        (Backend::Typst, ImageFormat::Png | ImageFormat::Pdf) => {}
+    }
+
+    let mut exports = Vec::with_capacity(2);
+    for variant in Variant::ALL {
+        let color = pair.get(variant).to_string();
+        match format {
+            ImageFormat::Svg => {}
+            ImageFormat::Png => backend.gen_png(eq.clone(), preamble.clone(), dir.clone(), color.clone(), dpi, layout, extra_args.clone()).await?,
+            ImageFormat::Pdf => backend.gen_pdf(eq.clone(), preamble.clone(), dir.clone(), color.clone(), layout, extra_args.clone(), scratch.clone()).await?,
+        }
+        if !post.is_identity() {
+            backends::post_process(dir.clone(), color.clone(), format, post.clone()).await?;
+        }
+        let from = dir.join(format!("{color}{}_eq.{format}", post.tag()));
+        let to = staging.join(pair.file_name(&name, variant, format));
+        fs::copy(&from, &to)
+            .await
+            .map_err(|_| GuiError::CopyFile(
+                from.to_string_lossy().to_string(),
+                to.to_string_lossy().to_string(),
+            ))?;
+        exports.push(to);
+    }
+    if fonts == latex::SvgFonts::Files {
+        // both variants use the same fonts
+        let svg = fs::read_to_string(dir.join("eq.svg"))
+            .await
+            .map_err(|_| GuiError::ReadFile("eq.svg".into()))?;
+        for font in latex::font_files(&svg) {
+            let (from, to) = (dir.join(font), staging.join(font));
+            fs::copy(&from, &to)
+                .await
+                .map_err(|_| GuiError::CopyFile(
+                    from.to_string_lossy().to_string(),
+                    to.to_string_lossy().to_string(),
+                ))?;
+            exports.push(to);
+        }
+    }
+    Ok(exports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(template: &str) -> ThemePair {
+        ThemePair { template: template.into(), ..ThemePair::default() }
+    }
+
+    #[test]
+    fn default_template() {
+        let pair = ThemePair::default();
+        assert_eq!(pair.file_name("eq", Variant::Light, ImageFormat::Svg), "eq-light.svg");
+        assert_eq!(pair.file_name("eq", Variant::Dark, ImageFormat::Png), "eq-dark.png");
+    }
+
+    #[test]
+    fn variants_never_share_a_name() {
+        for template in ["{name}", "", "equation", "{theme}/{name}"] {
+            let pair = pair(template);
+            assert_ne!(
+                pair.file_name("eq", Variant::Light, ImageFormat::Svg),
+                pair.file_name("eq", Variant::Dark, ImageFormat::Svg),
+                "{template}",
+            );
+        }
+        assert_eq!(pair("{name}").file_name("eq", Variant::Dark, ImageFormat::Svg), "eq-dark.svg");
+    }
+}
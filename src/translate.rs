@@ -0,0 +1,584 @@
+//! Best effort translation of equations between LaTeX and Typst syntax.
+//!
+//! This only knows about common commands and symbols, anything else is passed through mostly
+//! unchanged so that the compiler can point it out.
+
+// most of the output is built up out of small `format!`s, readability beats the extra allocations
+#![allow(clippy::format_push_string)]
+
+/// LaTeX commands that are a single symbol in Typst
+const SYMBOLS: [(&str, &str); 62] = [
+    ("alpha", "alpha"),
+    ("beta", "beta"),
+    ("gamma", "gamma"),
+    ("Gamma", "Gamma"),
+    ("delta", "delta"),
+    ("Delta", "Delta"),
+    ("epsilon", "epsilon.alt"),
+    ("varepsilon", "epsilon"),
+    ("zeta", "zeta"),
+    ("eta", "eta"),
+    ("theta", "theta"),
+    ("Theta", "Theta"),
+    ("iota", "iota"),
+    ("kappa", "kappa"),
+    ("lambda", "lambda"),
+    ("Lambda", "Lambda"),
+    ("mu", "mu"),
+    ("nu", "nu"),
+    ("xi", "xi"),
+    ("Xi", "Xi"),
+    ("pi", "pi"),
+    ("Pi", "Pi"),
+    ("rho", "rho"),
+    ("sigma", "sigma"),
+    ("Sigma", "Sigma"),
+    ("tau", "tau"),
+    ("upsilon", "upsilon"),
+    ("phi", "phi.alt"),
+    ("varphi", "phi"),
+    ("Phi", "Phi"),
+    ("chi", "chi"),
+    ("psi", "psi"),
+    ("Psi", "Psi"),
+    ("omega", "omega"),
+    ("Omega", "Omega"),
+    ("infty", "infinity"),
+    ("partial", "diff"),
+    ("nabla", "nabla"),
+    ("cdot", "dot"),
+    ("times", "times"),
+    ("div", "div"),
+    ("pm", "plus.minus"),
+    ("mp", "minus.plus"),
+    ("leq", "<="),
+    ("le", "<="),
+    ("geq", ">="),
+    ("ge", ">="),
+    ("neq", "!="),
+    ("approx", "approx"),
+    ("equiv", "equiv"),
+    ("to", "->"),
+    ("rightarrow", "->"),
+    ("leftarrow", "<-"),
+    ("Rightarrow", "=>"),
+    ("iff", "<=>"),
+    ("in", "in"),
+    ("sum", "sum"),
+    ("prod", "product"),
+    ("int", "integral"),
+    ("ldots", "..."),
+    ("cdots", "dots.c"),
+    ("forall", "forall"),
+];
+
+/// LaTeX commands taking one argument that are a function of one argument in Typst
+const UNARY: [(&str, &str); 14] = [
+    ("mathbf", "bold"),
+    ("mathrm", "upright"),
+    ("mathit", "italic"),
+    ("mathbb", "bb"),
+    ("mathcal", "cal"),
+    ("hat", "hat"),
+    ("bar", "macron"),
+    ("vec", "arrow"),
+    ("dot", "dot"),
+    ("ddot", "dot.double"),
+    ("tilde", "tilde"),
+    ("overline", "overline"),
+    ("underline", "underline"),
+    ("sqrt", "sqrt"),
+];
+
+/// Environments that become `mat(...)` in Typst, with their delimiter
+const MATRICES: [(&str, &str); 5] = [
+    ("matrix", "#none"),
+    ("pmatrix", "\"(\""),
+    ("bmatrix", "\"[\""),
+    ("Bmatrix", "\"{\""),
+    ("vmatrix", "\"|\""),
+];
+
+/// Translates a LaTeX equation to Typst
+pub fn latex_to_typst(latex: &str) -> String {
+    let mut out = String::new();
+    let mut rest = latex;
+    while !rest.is_empty() {
+        rest = latex_token(rest, &mut out);
+    }
+    normalize_whitespace(&out)
+}
+
+/// Translates the first token of `s` into `out`, returning what remains
+fn latex_token<'a>(s: &'a str, out: &mut String) -> &'a str {
+    let Some(c) = s.chars().next() else {
+        return s;
+    };
+    let after = &s[c.len_utf8()..];
+    match c {
+        '\\' => latex_command(after, out),
+        '%' => after.find('\n').map_or("", |i| &after[i..]),
+        '{' => {
+            let (group, rest) = latex_group(s);
+            out.push_str(&latex_to_typst(group));
+            rest
+        }
+        '^' | '_' => {
+            out.push(c);
+            let (arg, rest) = latex_arg(after);
+            let arg = latex_to_typst(arg);
+            if arg.chars().count() == 1 {
+                out.push_str(&arg);
+            } else {
+                out.push('(');
+                out.push_str(&arg);
+                out.push(')');
+            }
+            rest
+        }
+        c if c.is_ascii_alphabetic() => {
+            // `ab` is `a b` in LaTeX but a variable named `ab` in Typst
+            let len = s.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(s.len());
+            space_before(out);
+            for (i, c) in s[..len].chars().enumerate() {
+                if i != 0 {
+                    out.push(' ');
+                }
+                out.push(c);
+            }
+            &s[len..]
+        }
+        c => {
+            out.push(c);
+            after
+        }
+    }
+}
+
+/// Translates the command at the start of `s` (just after the `\`)
+fn latex_command<'a>(s: &'a str, out: &mut String) -> &'a str {
+    let len = s.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(s.len());
+    if len == 0 {
+        // escaped character
+        let Some(c) = s.chars().next() else {
+            return s;
+        };
+        match c {
+            '\\' => out.push_str(" \\\n"),
+            ',' => out.push_str(" thin "),
+            ';' | ':' => out.push_str(" med "),
+            ' ' => out.push_str(" space "),
+            '!' => {}
+            '{' | '}' | '%' => out.push(c),
+            c => {
+                out.push('\\');
+                out.push(c);
+            }
+        }
+        return &s[c.len_utf8()..];
+    }
+
+    let (name, rest) = s.split_at(len);
+    space_before(out);
+    match name {
+        "frac" | "dfrac" | "tfrac" => {
+            let (num, rest) = latex_arg(rest);
+            let (den, rest) = latex_arg(rest);
+            out.push_str(&format!("frac({}, {})", latex_to_typst(num), latex_to_typst(den)));
+            rest
+        }
+        "sqrt" if rest.trim_start().starts_with('[') => {
+            let rest = rest.trim_start();
+            let end = rest.find(']').unwrap_or(rest.len());
+            let index = &rest[1..end];
+            let (arg, rest) = latex_arg(rest.get(end + 1..).unwrap_or_default());
+            out.push_str(&format!("root({}, {})", latex_to_typst(index), latex_to_typst(arg)));
+            rest
+        }
+        "text" | "textrm" | "mbox" => {
+            let (text, rest) = latex_arg(rest);
+            out.push_str(&format!("\"{text}\""));
+            rest
+        }
+        "operatorname" => {
+            let (op, rest) = latex_arg(rest);
+            out.push_str(&format!("op(\"{op}\")"));
+            rest
+        }
+        "left" | "right" => {
+            // typst scales delimiters automatically
+            let rest = rest.trim_start();
+            match rest.chars().next() {
+                Some('.') => &rest[1..],
+                Some('\\') => {
+                    let rest = &rest[1..];
+                    let Some(c) = rest.chars().next() else {
+                        return rest;
+                    };
+                    out.push(c);
+                    &rest[c.len_utf8()..]
+                }
+                _ => rest,
+            }
+        }
+        "begin" => latex_environment(rest, out),
+        name => {
+            if let Some(&(_, func)) = UNARY.iter().find(|(latex, _)| *latex == name) {
+                let (arg, rest) = latex_arg(rest);
+                out.push_str(&format!("{func}({})", latex_to_typst(arg)));
+                rest
+            } else {
+                let symbol = SYMBOLS.iter()
+                    .find(|(latex, _)| *latex == name)
+                    .map_or(name, |&(_, typst)| typst);
+                out.push_str(symbol);
+                rest
+            }
+        }
+    }
+}
+
+/// Translates the environment at the start of `s` (just after `\begin`)
+fn latex_environment<'a>(s: &'a str, out: &mut String) -> &'a str {
+    let (env, rest) = latex_arg(s);
+    let end = format!("\\end{{{env}}}");
+    let (body, rest) = rest.find(&end)
+        .map_or((rest, ""), |i| (&rest[..i], &rest[i + end.len()..]));
+    let rows = || body.split("\\\\")
+        .map(str::trim)
+        .filter(|row| !row.is_empty());
+
+    if let Some(&(_, delim)) = MATRICES.iter().find(|(name, _)| *name == env) {
+        let rows = rows()
+            .map(|row| row.split('&')
+                .map(|cell| latex_to_typst(cell.trim()))
+                .collect::<Vec<_>>()
+                .join(", "))
+            .collect::<Vec<_>>()
+            .join("; ");
+        out.push_str(&format!("mat(delim: {delim}, {rows})"));
+    } else if env == "cases" {
+        let rows = rows()
+            .map(latex_to_typst)
+            .collect::<Vec<_>>()
+            .join(", ");
+        out.push_str(&format!("cases({rows})"));
+    } else {
+        out.push_str(&latex_to_typst(body));
+    }
+    rest
+}
+
+/// Splits a `{...}` group off the start of `s`, returning its contents and what follows it
+fn latex_group(s: &str) -> (&str, &str) {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return (&s[1..i], &s[i + 1..]);
+                }
+            }
+            _ => {}
+        }
+    }
+    (s.get(1..).unwrap_or_default(), "")
+}
+
+/// Splits the next argument off `s`: either a `{...}` group, a command, or a single character
+fn latex_arg(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    match s.chars().next() {
+        None => ("", ""),
+        Some('{') => latex_group(s),
+        Some('\\') => {
+            let len = s[1..].find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(s.len() - 1).max(1);
+            let end = (1 + len).min(s.len());
+            s.split_at(end)
+        }
+        Some(c) => s.split_at(c.len_utf8()),
+    }
+}
+
+/// Translates a Typst equation to LaTeX
+pub fn typst_to_latex(typst: &str) -> String {
+    let mut out = String::new();
+    let mut rest = typst;
+    while !rest.is_empty() {
+        rest = typst_token(rest, &mut out);
+    }
+    normalize_whitespace(&out)
+}
+
+/// Multi character Typst operators, longest first so that `<=>` isn't read as `<=`
+const TYPST_OPERATORS: [(&str, &str); 7] = [
+    ("<=>", "\\iff"),
+    ("...", "\\ldots"),
+    ("<=", "\\leq"),
+    (">=", "\\geq"),
+    ("!=", "\\neq"),
+    ("->", "\\to"),
+    ("=>", "\\Rightarrow"),
+];
+
+/// Translates the first token of `s` into `out`, returning what remains
+fn typst_token<'a>(s: &'a str, out: &mut String) -> &'a str {
+    if let Some(&(op, latex)) = TYPST_OPERATORS.iter().find(|(op, _)| s.starts_with(op)) {
+        out.push_str(latex);
+        out.push(' ');
+        return &s[op.len()..];
+    }
+    let Some(c) = s.chars().next() else {
+        return s;
+    };
+    let after = &s[c.len_utf8()..];
+    match c {
+        '"' => {
+            let end = after.find('"').unwrap_or(after.len());
+            out.push_str(&format!("\\text{{{}}}", &after[..end]));
+            after.get(end + 1..).unwrap_or_default()
+        }
+        '\\' => match after.chars().next() {
+            Some(c) if !c.is_whitespace() => {
+                out.push('\\');
+                out.push(c);
+                &after[c.len_utf8()..]
+            }
+            _ => {
+                out.push_str(" \\\\ ");
+                after
+            }
+        },
+        '^' | '_' => {
+            out.push(c);
+            let after_ws = after.trim_start();
+            if after_ws.starts_with('(') {
+                let (group, rest) = typst_group(after_ws);
+                out.push_str(&format!("{{{}}}", typst_to_latex(group)));
+                rest
+            } else {
+                out.push('{');
+                let rest = typst_token(after_ws, out);
+                out.push('}');
+                rest
+            }
+        }
+        '/' if after.starts_with('/') => after.find('\n').map_or("", |i| &after[i..]),
+        c if c.is_alphabetic() => {
+            let len = s.find(|c: char| !(c.is_alphabetic() || c == '.'))
+                .unwrap_or(s.len());
+            let name = s[..len].trim_end_matches('.');
+            let rest = &s[name.len()..];
+            if rest.starts_with('(') {
+                typst_call(name, rest, out)
+            } else {
+                typst_identifier(name, out);
+                rest
+            }
+        }
+        c => {
+            out.push(c);
+            after
+        }
+    }
+}
+
+/// Translates a call of the function `name`, where `s` starts at its `(`
+fn typst_call<'a>(name: &str, s: &'a str, out: &mut String) -> &'a str {
+    let (args, rest) = typst_group(s);
+    let args = split_args(args);
+    let arg = |i: usize| args.get(i).map(|a| typst_to_latex(a)).unwrap_or_default();
+    match name {
+        "frac" => out.push_str(&format!("\\frac{{{}}}{{{}}}", arg(0), arg(1))),
+        "root" => out.push_str(&format!("\\sqrt[{}]{{{}}}", arg(0), arg(1))),
+        "op" => out.push_str(&format!("\\operatorname{{{}}}", args.first().map_or("", |a| a.trim_matches('"')))),
+        "abs" => out.push_str(&format!("\\left|{}\\right|", arg(0))),
+        "norm" => out.push_str(&format!("\\left\\|{}\\right\\|", arg(0))),
+        "cases" => {
+            let rows = (0..args.len()).map(arg).collect::<Vec<_>>().join(" \\\\ ");
+            out.push_str(&format!("\\begin{{cases}} {rows} \\end{{cases}}"));
+        }
+        "mat" => {
+            let delim = args.iter()
+                .find_map(|a| a.trim().strip_prefix("delim:"))
+                .map(str::trim);
+            let env = MATRICES.iter()
+                .find(|(_, d)| Some(*d) == delim)
+                .map_or("pmatrix", |&(env, _)| env);
+            let rows = args.iter()
+                .filter(|a| !a.trim().starts_with("delim:"))
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(",")
+                .split(';')
+                .map(|row| split_args(row).iter()
+                    .map(|cell| typst_to_latex(cell))
+                    .collect::<Vec<_>>()
+                    .join(" & "))
+                .collect::<Vec<_>>()
+                .join(" \\\\ ");
+            out.push_str(&format!("\\begin{{{env}}} {rows} \\end{{{env}}}"));
+        }
+        name => if let Some(&(latex, _)) = UNARY.iter().find(|(_, typst)| *typst == name) {
+            out.push_str(&format!("\\{latex}{{{}}}", arg(0)));
+        } else {
+            // not a known function, so it's just something followed by parentheses
+            typst_identifier(name, out);
+            out.push_str(&format!("({})", (0..args.len()).map(arg).collect::<Vec<_>>().join(", ")));
+        },
+    }
+    rest
+}
+
+/// Typst's names for operators that LaTeX has commands for
+const OPERATORS: [&str; 14] = [
+    "sin", "cos", "tan", "sec", "csc", "cot", "log", "ln", "exp", "lim", "max", "min", "det", "sup",
+];
+
+fn typst_identifier(name: &str, out: &mut String) {
+    if name.chars().count() == 1 {
+        out.push_str(name);
+    } else if name == "dif" {
+        out.push_str("\\,\\mathrm{d}");
+    } else if let Some(&(latex, _)) = SYMBOLS.iter().find(|(_, typst)| *typst == name) {
+        out.push('\\');
+        out.push_str(latex);
+        out.push(' ');
+    } else if OPERATORS.contains(&name) {
+        out.push('\\');
+        out.push_str(name);
+        out.push(' ');
+    } else {
+        out.push_str(&format!("\\mathrm{{{name}}}"));
+    }
+}
+
+/// Splits a `(...)` group off the start of `s`, returning its contents and what follows it.
+/// Parentheses in strings, like `mat(delim: "(", ...)`'s, don't count.
+fn typst_group(s: &str) -> (&str, &str) {
+    let mut depth = 0;
+    let mut in_string = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return (&s[1..i], &s[i + 1..]);
+                }
+            }
+            _ => {}
+        }
+    }
+    (s.get(1..).unwrap_or_default(), "")
+}
+
+/// Splits function arguments on the commas that aren't nested in another call or in a string
+fn split_args(args: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                split.push(args[start..i].trim().to_string());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = args[start..].trim();
+    if !last.is_empty() {
+        split.push(last.to_string());
+    }
+    split
+}
+
+fn normalize_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Separates what comes next from a preceding identifier
+fn space_before(out: &mut String) {
+    if out.ends_with(|c: char| c.is_alphanumeric()) {
+        out.push(' ');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `latex` translates to `typst` and back again
+    fn round_trip(latex: &str, typst: &str) {
+        assert_eq!(latex_to_typst(latex), typst);
+        assert_eq!(typst_to_latex(typst), latex);
+    }
+
+    #[test]
+    fn fractions() {
+        round_trip(r"\frac{1}{2}", "frac(1, 2)");
+        round_trip(r"\frac{a+b}{c}", "frac(a+b, c)");
+        assert_eq!(latex_to_typst(r"\dfrac12"), "frac(1, 2)");
+    }
+
+    #[test]
+    fn roots() {
+        round_trip(r"\sqrt{x}", "sqrt(x)");
+        round_trip(r"\sqrt[3]{x}", "root(3, x)");
+    }
+
+    #[test]
+    fn matrices() {
+        round_trip(r"\begin{pmatrix} 1 & 2 \\ 3 & 4 \end{pmatrix}", r#"mat(delim: "(", 1, 2; 3, 4)"#);
+        round_trip(r"\begin{bmatrix} a & b \end{bmatrix} + x", r#"mat(delim: "[", a, b) + x"#);
+        round_trip(r"\begin{matrix} 1 & 2 \end{matrix}", "mat(delim: #none, 1, 2)");
+    }
+
+    #[test]
+    fn cases() {
+        round_trip(
+            r"f(x) = \begin{cases} 1 & x > 0 \\ 0 & x \leq 0 \end{cases}",
+            "f(x) = cases(1 & x > 0, 0 & x <= 0)",
+        );
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(latex_to_typst(r"x^2 + y_{10}"), "x^2 + y_(10)");
+        assert_eq!(typst_to_latex("x^2 + y_(10)"), "x^{2} + y_{10}");
+    }
+
+    #[test]
+    fn truncated_latex() {
+        assert_eq!(latex_to_typst("\\"), "");
+        assert_eq!(latex_to_typst("a \\"), "a");
+        assert_eq!(latex_to_typst("{"), "");
+        assert_eq!(latex_to_typst(r"\frac{1"), "frac(1, )");
+        assert_eq!(latex_to_typst(r"\sqrt{"), "sqrt()");
+        assert_eq!(latex_to_typst(r"\sqrt["), "root(, )");
+        assert_eq!(latex_to_typst("f(x"), "f(x");
+        assert_eq!(latex_to_typst(r"\begin{pmatrix} 1"), r#"mat(delim: "(", 1)"#);
+        assert_eq!(latex_to_typst(r"\left"), "");
+    }
+
+    #[test]
+    fn truncated_typst() {
+        assert_eq!(typst_to_latex("\\"), r"\\");
+        assert_eq!(typst_to_latex("x^"), "x^{}");
+        assert_eq!(typst_to_latex("frac(1"), r"\frac{1}{}");
+        assert_eq!(typst_to_latex("f(x"), "f(x)");
+        assert_eq!(typst_to_latex("(a"), "(a");
+        assert_eq!(typst_to_latex(r#""unclosed"#), r"\text{unclosed}");
+        assert_eq!(typst_to_latex(r#"mat(delim: "(""#), r"\begin{pmatrix} \end{pmatrix}");
+    }
+}
@@ -1,72 +1,319 @@
 use std::env;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
 
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use tokio::fs;
 
 use crate::GuiError;
-use crate::backends::run_command;
+use crate::backends::{self, CURRENT_COLOR, ExtraArgs, Layout, run_command_in, safe_mode, Tool};
 use crate::gui::Dir;
 
-const TYPST_START: &str = r##"
-#import "@preview/physica:0.8.1": *
+/// What's imported before the equation unless it's changed in the settings, the packages every
+/// equation can use
+pub const DEFAULT_IMPORTS: &str = r#"#import "@preview/physica:0.8.1": *
+"#;
+
+const TYPST_START: &str = r"
 #set page(width: auto, height: auto, margin: 0pt)
-#set text(11pt, font: "New Computer Modern", lang: "en", fill: "##;
+";
+
+/// The text size in points, unless it's changed in the gui
+pub const DEFAULT_FONT_SIZE: f64 = 11.0;
+
+/// Common fonts with the math tables that Typst needs to lay out equations. Only New Computer
+/// Modern comes with Typst, if any of the others isn't installed Typst warns and falls back to it.
+#[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum MathFont {
+    #[default]
+    NewComputerModern,
+    LatinModern,
+    Libertinus,
+    StixTwo,
+    Fira,
+    Pagella,
+    Asana,
+}
+
+impl MathFont {
+    pub const ALL: [Self; 7] = [
+        Self::NewComputerModern,
+        Self::LatinModern,
+        Self::Libertinus,
+        Self::StixTwo,
+        Self::Fira,
+        Self::Pagella,
+        Self::Asana,
+    ];
+
+    /// The name Typst knows the font by
+    pub const fn family(self) -> &'static str {
+        match self {
+            Self::NewComputerModern => "New Computer Modern Math",
+            Self::LatinModern => "Latin Modern Math",
+            Self::Libertinus => "Libertinus Math",
+            Self::StixTwo => "STIX Two Math",
+            Self::Fira => "Fira Math",
+            Self::Pagella => "TeX Gyre Pagella Math",
+            Self::Asana => "Asana Math",
+        }
+    }
+}
+
+impl Display for MathFont {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.family())
+    }
+}
+
+/// Fill used when compiling an svg that will be recolored afterwards, see [`set_color`]
+const SENTINEL_COLOR: &str = "#010203";
+
+/// Typst's named colors, which don't all match the css colors of the same name
+const NAMED_COLORS: [(&str, &str); 18] = [
+    ("black", "#000000"),
+    ("gray", "#aaaaaa"),
+    ("silver", "#dddddd"),
+    ("white", "#ffffff"),
+    ("navy", "#001f3f"),
+    ("blue", "#0074d9"),
+    ("aqua", "#7fdbff"),
+    ("teal", "#39cccc"),
+    ("eastern", "#239dad"),
+    ("purple", "#b10dc9"),
+    ("fuchsia", "#f012be"),
+    ("maroon", "#85144b"),
+    ("red", "#ff4136"),
+    ("yellow", "#ffdc00"),
+    ("orange", "#ff851b"),
+    ("olive", "#3d9970"),
+    ("green", "#2ecc40"),
+    ("lime", "#01ff70"),
+];
+
+/// Converts a Typst color expression to the equivalent svg color, if it is simple enough to do so.
+///
+/// Handles Typst's named colors, `rgb("#...")`, bare hex codes, and [`CURRENT_COLOR`].
+pub fn svg_color(color: &str) -> Option<&str> {
+    let color = color.trim();
+    if color == CURRENT_COLOR {
+        return Some(CURRENT_COLOR);
+    }
+    if let Some(&(_, hex)) = NAMED_COLORS.iter().find(|(name, _)| *name == color) {
+        return Some(hex);
+    }
+    let hex = color.strip_prefix("rgb(")
+        .and_then(|c| c.strip_suffix(')'))
+        .map_or(color, |c| c.trim().trim_matches('"'));
+    let digits = hex.strip_prefix('#')?;
+    (matches!(digits.len(), 3 | 4 | 6 | 8) && digits.chars().all(|c| c.is_ascii_hexdigit()))
+        .then_some(hex)
+}
+
+/// The css equivalent of a Typst color, for showing it in the gui. Also converts `rgb(r, g, b, a)`
+/// with each component out of 255, which [`svg_color`] leaves alone. Anything else is returned as is.
+pub fn css_color(color: &str) -> String {
+    if let Some(hex) = svg_color(color) {
+        return hex.to_string();
+    }
+    let components = color.trim()
+        .strip_prefix("rgb(")
+        .and_then(|c| c.strip_suffix(')'))
+        .map(|c| c.split(',').map(|n| n.trim().parse::<u8>()).collect::<Result<Vec<_>, _>>());
+    match components {
+        Some(Ok(c)) if c.len() == 4 => format!("rgba({}, {}, {}, {})", c[0], c[1], c[2], f64::from(c[3]) / 255.0),
+        _ => color.to_string(),
+    }
+}
+
+/// Overrides which Typst executable is run, eg a vendored build with the `--background` option
+/// that pngs are compiled with
+pub const TYPST_BIN_VAR: &str = "TYPST_IMAGE_TYPST_BIN";
+
+/// The Typst executable from [`TYPST_BIN_VAR`], or else `typst` on the `PATH`. Looked up once
+static TYPST: Lazy<Option<String>> = Lazy::new(|| env::var_os(TYPST_BIN_VAR)
+    .map(PathBuf::from)
+    .or_else(|| backends::find_on_path("typst"))
+    .map(|path| path.to_string_lossy().to_string()));
+
+fn typst() -> Result<&'static str, GuiError> {
+    TYPST.as_deref()
+        .ok_or(GuiError::BinaryNotFound("typst"))
+}
 
-// using my vendored typst for the --background option for pngs
-const TYPST: &str = r"C:\Users\andre\CLionProjects\typst\target\release\typst.exe";
+pub fn is_installed() -> bool {
+    TYPST.is_some()
+}
 
 enum Image {
     Svg,
     Png(usize),
+    Pdf,
 }
 
-async fn gen_image(eq: String, dir: Dir, color: String, image: Image) -> Result<(), GuiError> {
-
-    // println!("dir = {:?}", dir);
+/// The imports, then the page and text setup, with the equation in `fill`
+fn start(imports: &str, fill: &str, layout: Layout) -> String {
+    format!(
+        "{imports}{TYPST_START}#set text({}pt, font: \"New Computer Modern\", lang: \"en\", fill: {fill})\n#show math.equation: set text(font: \"{}\")\n",
+        layout.font_size,
+        layout.font.family(),
+    )
+}
 
-    let initial_dir = env::current_dir()
-        .map_err(|_| GuiError::GetSetCurrentDir)?;
+/// The equation aligned as in `layout`, and scaled if `layout.scale` isn't 1
+fn body(eq: &str, layout: Layout) -> String {
+    let align = format!("#show math.equation: set align({})\n", layout.alignment.typst_align());
+    #[allow(clippy::float_cmp)]
+    if layout.scale == 1.0 {
+        format!("{align}$ {eq} $")
+    } else {
+        // reflow so that the auto-sized page grows with the equation
+        format!("{align}#scale({}%, reflow: true)[$ {eq} $]", layout.scale * 100.0)
+    }
+}
 
-    env::set_current_dir(&dir)
-        .map_err(|_| GuiError::GetSetCurrentDir)?;
+async fn gen_image(eq: String, imports: String, dir: Dir, color: String, layout: Layout, image: Image, extra_args: ExtraArgs) -> Result<(), GuiError> {
+    // svgs are compiled with the sentinel color and then recolored if possible
+    let recolor = matches!(image, Image::Svg) && svg_color(&color).is_some();
+    let fill = if recolor { format!("rgb(\"{SENTINEL_COLOR}\")") } else { color.clone() };
 
-    fs::write("eq.typ", format!("{TYPST_START}{color})\n$ {eq} $"))
+    let typ = dir.join("eq.typ");
+    fs::write(&typ, format!("{}{}", start(&imports, &fill, layout), body(&eq, layout)))
         .await
-        .map_err(|_| GuiError::WriteFile("eq.typ".into()))?;
-
-    let _output = match image {
-        Image::Svg => run_command(TYPST, [
-            "compile",
-            "eq.typ",
-            &format!("{color}_eq.svg"),
-            "--diagnostic-format",
-            "short",
-        ],
-        ).await?,
-        Image::Png(dpi) => run_command(TYPST, [
-            "compile",
-            "eq.typ",
-            &format!("{color}_eq.png"),
-            "--diagnostic-format",
-            "short",
+        .map_err(|_| GuiError::WriteFile(typ.to_string_lossy().to_string().into()))?;
+
+    if let Image::Png(dpi) = image {
+        // the page is sized to fit the equation, so check how big that is before rasterizing it
+        compile(&dir, "size.svg", &[], &extra_args).await?;
+        let size = dir.join("size.svg");
+        let svg = fs::read_to_string(&size)
+            .await
+            .map_err(|_| GuiError::ReadFile(size.to_string_lossy().to_string()))?;
+        backends::check_png_size(&svg, dpi)?;
+    }
+
+    let (out, png_args) = match image {
+        Image::Svg => (
+            if recolor { "eq.svg".to_string() } else { format!("{color}_eq.svg") },
+            None,
+        ),
+        Image::Png(dpi) => (
+            format!("{color}_eq.png"),
+            Some(dpi.to_string()),
+        ),
+        Image::Pdf => (
+            format!("{color}_eq.pdf"),
+            None,
+        ),
+    };
+    let png_args = png_args.as_ref()
+        .map(|dpi| [
             "--ppi",
-            &dpi.to_string(),
+            dpi,
             "--background",
             "#00000000",
-        ]).await?,
-    };
+        ]);
+
+    compile(&dir, &out, png_args.as_ref().map_or(&[], |args| &args[..]), &extra_args).await?;
+
+    if recolor {
+        set_color(dir, color).await?;
+    }
+
+    Ok(())
+}
+
+/// Compiles `dir/eq.typ` to `dir/out`. typst is run in `dir` rather than changing the process's
+/// working directory, which anything else compiling at the same time would also see.
+async fn compile(dir: &Dir, out: &str, args: &[&str], extra_args: &ExtraArgs) -> Result<(), GuiError> {
+    let output = run_command_in(Some(dir.clone()), typst()?, [
+        "compile",
+        "eq.typ",
+        out,
+        "--diagnostic-format",
+        "short",
+    ].into_iter()
+        .chain(args.iter().copied())
+        // the project root is already the input's directory, but don't rely on that in safe mode
+        .chain(safe_mode().then_some(["--root", "."]).into_iter().flatten())
+        .chain(extra_args.args(Tool::Typst)),
+    ).await;
+    if output.is_err() {
+        // a cancelled compile could leave part of `out` behind, which would look like it's cached
+        let _ = fs::remove_file(dir.join(out)).await;
+    }
+    output?;
+    Ok(())
+}
 
-    env::set_current_dir(initial_dir)
-        .map_err(|_| GuiError::GetSetCurrentDir)?;
+/// `typst --version`, or empty if it isn't installed
+pub async fn version() -> String {
+    match typst() {
+        Ok(typst) => backends::tool_versions(&[typst]).await,
+        Err(_) => String::new(),
+    }
+}
 
+/// Compiles the document `source` in `dir` to `out`, eg a pdf. Images in the document are
+/// relative to `dir`.
+pub async fn compile_document(dir: Dir, source: String, out: &Path) -> Result<(), GuiError> {
+    fs::write(dir.join("doc.typ"), source)
+        .await
+        .map_err(|_| GuiError::WriteFile("doc.typ".into()))?;
+    let out = out.to_string_lossy().to_string();
+    let _output = run_command_in(Some(dir), typst()?, [
+        "compile",
+        "doc.typ",
+        &out,
+        "--diagnostic-format",
+        "short",
+    ].into_iter()
+        .chain(safe_mode().then_some(["--root", "."]).into_iter().flatten()),
+    ).await?;
     Ok(())
 }
 
-pub async fn gen_svg(eq: String, dir: Dir, color: String) -> Result<(), GuiError> {
+/// `imports` go before the equation, see [`DEFAULT_IMPORTS`]
+pub async fn gen_svg(eq: String, imports: String, dir: Dir, color: String, layout: Layout, extra_args: ExtraArgs) -> Result<(), GuiError> {
     // println!("GENERATE SVG from Typst");
-    gen_image(eq, dir, color, Image::Svg).await
+    gen_image(eq, imports, dir, color, layout, Image::Svg, extra_args).await
 }
 
-pub async fn gen_png(eq: String, dir: Dir, color: String, density: usize) -> Result<(), GuiError> {
+pub async fn gen_png(eq: String, imports: String, dir: Dir, color: String, density: usize, layout: Layout, extra_args: ExtraArgs) -> Result<(), GuiError> {
     // println!("GENERATE PNG from Typst");
-    gen_image(eq, dir, color, Image::Png(density)).await
+    gen_image(eq, imports, dir, color, layout, Image::Png(density), extra_args).await
+}
+
+pub async fn gen_pdf(eq: String, imports: String, dir: Dir, color: String, layout: Layout, extra_args: ExtraArgs) -> Result<(), GuiError> {
+    gen_image(eq, imports, dir, color, layout, Image::Pdf, extra_args).await
+}
+
+/// The first equation in `source`, skipping any `#set` or `#import` lines before it. Anything
+/// without `$`s is taken to be the equation already.
+pub fn extract_equation(source: &str) -> &str {
+    source.split_once('$')
+        .and_then(|(_, rest)| rest.split_once('$'))
+        .map_or(source, |(math, _)| math)
+        .trim()
+}
+
+/// copies `eq.svg` to `{color}_eq.svg` and replaces the sentinel fill with `color`
+pub async fn set_color(dir: Dir, color: String) -> Result<(), GuiError> {
+    let svg_color = svg_color(&color)
+        .expect("only called for colors that `svg_color` can convert");
+
+    let svg = fs::read_to_string(dir.join("eq.svg"))
+        .await
+        .map_err(|_| GuiError::ReadFile("eq.svg".to_string()))?;
+
+    let svg = svg.replace(
+        SENTINEL_COLOR,
+        svg_color,
+    );
+
+    let path_colored = dir.join(format!("{color}_eq.svg"));
+    fs::write(&path_colored, svg)
+        .await
+        .map_err(|_| GuiError::WriteFile(path_colored.to_string_lossy().to_string().into()))
 }
@@ -2,7 +2,8 @@ use std::borrow::Borrow;
 use std::fmt::Display;
 
 use iced::{Element, Length};
-use iced::widget::{Button, Checkbox, Column, Container, PickList, ProgressBar, Row, Rule, Scrollable, Space, Text, TextInput, Tooltip};
+use iced::advanced::text::highlighter::PlainText;
+use iced::widget::{Button, Checkbox, Column, Container, image, Image, PickList, ProgressBar, Row, Rule, Scrollable, Space, Svg, Text, TextEditor, TextInput, Tooltip};
 
 use crate::circular::Circular;
 use crate::gui::Message;
@@ -50,6 +51,13 @@ impl Dir for RowDir {
     }
 }
 
+/// What can be passed to [`row!`] and [`col!`]. Numbers and [`Length`]s become spaces in the
+/// direction of the row or column, everything else is added as is.
+///
+/// Implemented for [`TextInput`], [`TextEditor`], [`Container`], [`Text`], [`Button`], [`Row`],
+/// [`Column`], [`Tooltip`], [`Scrollable`], [`Checkbox`], [`PickList`], [`Svg`], [`Image`], [`Rule`],
+/// [`ProgressBar`], [`Space`], [`Circular`], and [`Element`] itself. Other widgets need to be added to
+/// the `impl_directional_element!` list, or wrapped in `Element::from` first.
 pub trait DirectionalElement<'a, Dir> {
     fn into_element(self) -> Element<'a, Message>;
 }
@@ -78,6 +86,10 @@ impl_directional_element! {
     Tooltip<'a, Message>;
     Scrollable<'a, Message>;
     Checkbox<'a, Message>;
+    TextEditor<'a, PlainText, Message>;
+    Svg;
+    Image<image::Handle>;
+    Element<'a, Message>;
     Rule;
     ProgressBar;
     Space;
@@ -123,18 +135,150 @@ impl<'a, D: Dir> DirectionalElement<'a, D> for u16 {
     }
 }
 
-pub trait SpacingExt {
-    fn push_space<L: Into<Length>>(self, length: L) -> Self;
-}
+/// Formats a number of bytes for display, eg `12.3 KB`
+pub fn file_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
 
-impl<'a> SpacingExt for Column<'a, Message> {
-    fn push_space<L: Into<Length>>(self, length: L) -> Self {
-        self.push(Space::with_height(length))
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
     }
+    format!("{size:.1} {unit}")
+}
+
+/// `name` with each `{placeholder}` in `values` replaced by its value, made safe for a file name by
+/// [`sanitize_file_name`]. A name without placeholders is left as it is
+pub fn expand_file_name(name: &str, values: &[(&str, &str)]) -> String {
+    values.iter()
+        .fold(name.to_string(), |name, (placeholder, value)| {
+            name.replace(&format!("{{{placeholder}}}"), &sanitize_file_name(value))
+        })
 }
 
-impl<'a> SpacingExt for Row<'a, Message> {
-    fn push_space<L: Into<Length>>(self, length: L) -> Self {
-        self.push(Space::with_width(length))
+/// `value` with each run of characters that some file systems don't allow, or that would change
+/// the extension or directory, replaced by `_`, eg `#ff0000` is `ff0000` and `rgb(0, 0, 255)` is
+/// `rgb(0_0_255)`
+pub fn sanitize_file_name(value: &str) -> String {
+    let is_illegal = |c: char| c.is_control()
+        || c.is_whitespace()
+        || matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' | '#' | '.' | ',');
+    value.split(is_illegal)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// The value of the attribute `name` in the svg tag `tag`, with either kind of quotes
+pub fn svg_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    attribute_range(tag, name).map(|range| &tag[range])
+}
+
+/// Sets the attribute `name` in the svg tag `tag` to `value`, adding it if it isn't there yet
+pub fn set_svg_attribute(tag: &str, name: &str, value: &str) -> String {
+    attribute_range(tag, name).map_or_else(
+        || {
+            let end = tag.trim_end_matches('>').trim_end_matches('/').len();
+            format!("{} {name}=\"{value}\"{}", &tag[..end], &tag[end..])
+        },
+        |range| format!("{}{value}{}", &tag[..range.start], &tag[range.end..]),
+    )
+}
+
+/// Removes the attribute `name` from the svg tag `tag`, if it's there
+pub fn remove_svg_attribute(tag: &str, name: &str) -> String {
+    attribute_range(tag, name).map_or_else(
+        || tag.to_string(),
+        // from the space before the name to the closing quote
+        |range| format!("{}{}", &tag[..range.start - name.len() - 3], &tag[range.end + 1..]),
+    )
+}
+
+/// Where the value of the attribute `name` is in `tag`
+fn attribute_range(tag: &str, name: &str) -> Option<std::ops::Range<usize>> {
+    [format!(" {name}=\""), format!(" {name}='")].into_iter()
+        .find_map(|prefix| {
+            let start = tag.find(&prefix)? + prefix.len();
+            let quote = prefix.chars().last()?;
+            let len = tag[start..].find(quote)?;
+            Some(start..start + len)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use iced::widget::{button, checkbox, container, horizontal_rule, image, pick_list, progress_bar, scrollable, svg, text, text_editor, text_input, tooltip};
+
+    use super::*;
+
+    #[test]
+    fn expands_placeholders() {
+        let values = [("color", "#ff0000"), ("format", "svg"), ("backend", "Typst")];
+        assert_eq!(expand_file_name("eq_{color}_{backend}", &values), "eq_ff0000_Typst");
+        assert_eq!(expand_file_name("{format}/{format}", &values), "svg/svg");
+    }
+
+    #[test]
+    fn leaves_other_names_alone() {
+        let values = [("color", "red")];
+        assert_eq!(expand_file_name("equation", &values), "equation");
+        assert_eq!(expand_file_name("{colour} {hash}", &values), "{colour} {hash}");
+    }
+
+    #[test]
+    fn sanitizes_values() {
+        assert_eq!(sanitize_file_name("#ff0000"), "ff0000");
+        assert_eq!(sanitize_file_name("rgb(0, 0, 255)"), "rgb(0_0_255)");
+        assert_eq!(sanitize_file_name("../../etc/passwd"), "etc_passwd");
+        assert_eq!(sanitize_file_name("a\\b:c*d?e\"f<g>h|i"), "a_b_c_d_e_f_g_h_i");
+        assert_eq!(sanitize_file_name("line\nbreak\ttab"), "line_break_tab");
+        assert_eq!(sanitize_file_name("..."), "");
+    }
+
+    #[test]
+    fn remove_attribute() {
+        assert_eq!(remove_svg_attribute(r#"<svg width="1pt" height='2pt'>"#, "width"), "<svg height='2pt'>");
+        assert_eq!(remove_svg_attribute(r#"<svg width="1pt" height='2pt'>"#, "height"), r#"<svg width="1pt">"#);
+        assert_eq!(remove_svg_attribute(r#"<svg width="1pt">"#, "viewBox"), r#"<svg width="1pt">"#);
     }
-}
\ No newline at end of file
+
+    /// Only has to compile, with every widget [`DirectionalElement`] lists in both a row and a column
+    #[test]
+    fn directional_elements() {
+        let content = text_editor::Content::new();
+        macro_rules! every_widget {
+            ($dir:ident) => {
+                $dir![
+                    8,
+                    Length::Fill,
+                    text_input("", ""),
+                    text_editor(&content),
+                    container(text("")),
+                    text(""),
+                    button("").on_press(Message::Compile),
+                    row!(),
+                    col!(),
+                    tooltip(text(""), "", tooltip::Position::Top),
+                    scrollable(text("")),
+                    checkbox("", false),
+                    pick_list(&["a", "b"][..], None::<&str>, |_| Message::Compile),
+                    svg(svg::Handle::from_memory(&b"<svg/>"[..])),
+                    image(image::Handle::from_memory(Vec::new())),
+                    horizontal_rule(1),
+                    progress_bar(0.0..=1.0, 0.5),
+                    Space::new(0, 0),
+                    Circular::new(),
+                    Element::from(text("")),
+                ]
+            };
+        }
+        let _: Element<Message> = row![every_widget!(row), every_widget!(col)].into();
+    }
+}
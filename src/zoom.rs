@@ -0,0 +1,216 @@
+//! Zooming into the preview with the scroll wheel and panning it by dragging, to check the details
+//! of big equations
+
+use iced::{Element, Event, Length, Point, Rectangle, Size, Vector};
+use iced::advanced::{Clipboard, Layout, Shell, Widget};
+use iced::advanced::layout;
+use iced::advanced::renderer;
+use iced::advanced::widget::tree::{self, Tree};
+use iced::event;
+use iced::mouse;
+
+/// How much one line of scrolling zooms by
+const ZOOM_STEP: f32 = 1.1;
+/// Trackpads scroll in pixels, this many make up a line
+const PIXELS_PER_LINE: f32 = 50.0;
+
+/// How far the preview is zoomed in, and where to
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct View {
+    pub zoom: f32,
+    /// of the zoomed content's top left corner from the preview's
+    pub offset: Vector,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            offset: Vector::ZERO,
+        }
+    }
+}
+
+impl View {
+    pub const MIN_ZOOM: f32 = 0.5;
+    pub const MAX_ZOOM: f32 = 16.0;
+
+    /// Zoomed by `factor`, keeping the point under the cursor at `around` in the same place
+    pub fn zoomed(self, factor: f32, around: Point) -> Self {
+        let zoom = (self.zoom * factor).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        let ratio = zoom / self.zoom;
+        let around = Vector::new(around.x, around.y);
+        Self {
+            zoom,
+            offset: around - (around - self.offset) * ratio,
+        }
+    }
+
+    pub fn panned(self, by: Vector) -> Self {
+        Self {
+            offset: self.offset + by,
+            ..self
+        }
+    }
+
+    /// Whether the whole image is shown, like it is without zooming
+    pub fn is_reset(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Wraps `content` so it can be zoomed and panned. The [`View`] is kept by the caller, which is
+/// told how it should change
+#[allow(missing_debug_implementations)]
+pub struct Zoomable<'a, Message> {
+    content: Element<'a, Message>,
+    view: View,
+    /// the zoom factor, and where the cursor is in the preview
+    on_zoom: Box<dyn Fn(f32, Point) -> Message + 'a>,
+    on_pan: Box<dyn Fn(Vector) -> Message + 'a>,
+}
+
+impl<'a, Message> Zoomable<'a, Message> {
+    pub fn new(
+        content: impl Into<Element<'a, Message>>,
+        view: View,
+        on_zoom: impl Fn(f32, Point) -> Message + 'a,
+        on_pan: impl Fn(Vector) -> Message + 'a,
+    ) -> Self {
+        Self {
+            content: content.into(),
+            view,
+            on_zoom: Box::new(on_zoom),
+            on_pan: Box::new(on_pan),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// where the cursor was when last dragged
+    drag: Option<Point>,
+}
+
+impl<Message> Widget<Message, iced::Theme, iced::Renderer> for Zoomable<'_, Message> {
+    fn size(&self) -> Size<Length> {
+        Size::new(Length::Fill, Length::Fill)
+    }
+
+    fn layout(
+        &self,
+        tree: &mut Tree,
+        renderer: &iced::Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        let size = limits.resolve(Length::Fill, Length::Fill, Size::ZERO);
+        // laid out as if the preview were `zoom` times bigger, then moved by the offset
+        let zoomed = layout::Limits::new(Size::ZERO, Size::new(size.width * self.view.zoom, size.height * self.view.zoom));
+        let content = self.content.as_widget()
+            .layout(&mut tree.children[0], renderer, &zoomed)
+            .move_to(Point::ORIGIN + self.view.offset);
+        layout::Node::with_children(size, vec![content])
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut iced::Renderer,
+        theme: &iced::Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+    ) {
+        use iced::advanced::Renderer as _;
+
+        let bounds = layout.bounds();
+        let Some(content) = layout.children().next() else { return };
+        // hide whatever is zoomed or panned outside of the preview
+        renderer.with_layer(bounds, |renderer| {
+            self.content.as_widget().draw(&tree.children[0], renderer, theme, style, content, cursor, &bounds);
+        });
+    }
+
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        vec![Tree::new(&self.content)]
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        tree.diff_children(std::slice::from_ref(&self.content));
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &iced::Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) -> event::Status {
+        let state = tree.state.downcast_mut::<State>();
+        let bounds = layout.bounds();
+
+        match event {
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+                let Some(position) = cursor.position_in(bounds) else { return event::Status::Ignored };
+                let lines = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } => y,
+                    mouse::ScrollDelta::Pixels { y, .. } => y / PIXELS_PER_LINE,
+                };
+                shell.publish((self.on_zoom)(ZOOM_STEP.powf(lines), position));
+                event::Status::Captured
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let Some(position) = cursor.position_over(bounds) else { return event::Status::Ignored };
+                state.drag = Some(position);
+                event::Status::Captured
+            }
+            Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                let Some(last) = state.drag else { return event::Status::Ignored };
+                state.drag = Some(position);
+                shell.publish((self.on_pan)(position - last));
+                event::Status::Captured
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) if state.drag.is_some() => {
+                state.drag = None;
+                event::Status::Captured
+            }
+            _ => event::Status::Ignored,
+        }
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &iced::Renderer,
+    ) -> mouse::Interaction {
+        if tree.state.downcast_ref::<State>().drag.is_some() {
+            mouse::Interaction::Grabbing
+        } else if cursor.is_over(layout.bounds()) {
+            mouse::Interaction::Grab
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+}
+
+impl<'a, Message: 'a> From<Zoomable<'a, Message>> for Element<'a, Message> {
+    fn from(zoomable: Zoomable<'a, Message>) -> Self {
+        Self::new(zoomable)
+    }
+}